@@ -0,0 +1,205 @@
+use std::{path::PathBuf, process::exit};
+
+use crate::{
+    db::{TrackDB, TrackFileMode},
+    import::import_folder,
+    state::filter_state::F1State,
+    utils::init_files,
+};
+
+const IMPORT_USAGE: &str = "usage: mprs import <folder> [--copy | --reference] [--playlist <name>]
+
+Adds every audio file under <folder> to the library. With --copy (the default) files are
+copied into the music dir, with --reference they are played from where they are.";
+
+const REORGANIZE_USAGE: &str = "usage: mprs reorganize [--dry-run]
+
+Moves the files in the music dir to where path_template in the config puts them. With
+--dry-run the moves are only listed.";
+
+const EXPORT_USAGE: &str = "usage: mprs export <playlist> <file> [--relative]
+
+Writes the playlist to <file>, as m3u8 or xspf depending on its extension. With --relative
+the m3u8 paths are relative to the file's dir.";
+
+const IMPORT_PLAYLIST_USAGE: &str = "usage: mprs import-playlist <file> [--playlist <name>]
+
+Adds the library tracks a .m3u, .m3u8 or .xspf file lists to a playlist named after the file,
+or to <name>. Entries are matched by path, then by title and artist.";
+
+// runs the subcommand given in args, returns false if there is none so the gui starts instead
+pub fn run_cli(args: &[String]) -> bool {
+    match args.get(1).map(|s| s.as_str()) {
+        Some("import") => {
+            run_import(&args[2..]);
+            true
+        }
+        Some("reorganize") => {
+            run_reorganize(&args[2..]);
+            true
+        }
+        Some("export") => {
+            run_export(&args[2..]);
+            true
+        }
+        Some("import-playlist") => {
+            run_import_playlist(&args[2..]);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn run_import(args: &[String]) {
+    let mut folder = None;
+    let mut mode = TrackFileMode::Copy;
+    let mut playlist = None;
+
+    let mut args_iter = args.iter();
+    while let Some(a) = args_iter.next() {
+        match a.as_str() {
+            "--copy" => mode = TrackFileMode::Copy,
+            "--reference" => mode = TrackFileMode::Reference,
+            "--playlist" => match args_iter.next() {
+                Some(p) => playlist = Some(p.clone()),
+                None => usage_error(IMPORT_USAGE),
+            },
+            "-h" | "--help" => {
+                println!("{}", IMPORT_USAGE);
+                return;
+            }
+            _ if folder.is_none() && !a.starts_with('-') => folder = Some(PathBuf::from(a)),
+            _ => usage_error(IMPORT_USAGE),
+        }
+    }
+    let folder = folder.unwrap_or_else(|| usage_error(IMPORT_USAGE));
+
+    init_files();
+    let mut tdb = TrackDB::init();
+    let report = match import_folder(&mut tdb, &folder, mode, playlist.as_deref()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Import failed: {:#}", e);
+            exit(1);
+        }
+    };
+
+    println!("Imported {} tracks", report.imported);
+    if !report.skipped.is_empty() {
+        println!("Skipped {} files:", report.skipped.len());
+        for (p, reason) in report.skipped.iter() {
+            println!("  {} : {}", p.display(), reason);
+        }
+    }
+}
+
+fn run_reorganize(args: &[String]) {
+    let mut dry_run = false;
+    for a in args {
+        match a.as_str() {
+            "--dry-run" => dry_run = true,
+            "-h" | "--help" => {
+                println!("{}", REORGANIZE_USAGE);
+                return;
+            }
+            _ => usage_error(REORGANIZE_USAGE),
+        }
+    }
+
+    init_files();
+    let mut tdb = TrackDB::init();
+    if dry_run {
+        let moves = tdb.plan_reorganize();
+        for (_, from, to) in moves.iter() {
+            println!("{} -> {}", from.display(), to.display());
+        }
+        println!("{} files would be moved", moves.len());
+        return;
+    }
+
+    let report = tdb.reorganize();
+    println!("Moved {} files", report.moved);
+    if !report.failed.is_empty() {
+        println!("Could not move {} files:", report.failed.len());
+        for (id, reason) in report.failed.iter() {
+            println!("  track {} : {}", id, reason);
+        }
+    }
+}
+
+fn run_export(args: &[String]) {
+    let mut positional = Vec::new();
+    let mut relative = false;
+    for a in args {
+        match a.as_str() {
+            "--relative" => relative = true,
+            "-h" | "--help" => {
+                println!("{}", EXPORT_USAGE);
+                return;
+            }
+            _ if !a.starts_with('-') => positional.push(a),
+            _ => usage_error(EXPORT_USAGE),
+        }
+    }
+    let (playlist, dest) = match positional.as_slice() {
+        [p, d] => (p.as_str(), PathBuf::from(d)),
+        _ => usage_error(EXPORT_USAGE),
+    };
+
+    init_files();
+    let tdb = TrackDB::init();
+    if !tdb.filter_values(&F1State::Playlists).iter().any(|p| p == playlist) {
+        eprintln!("There is no playlist called {}", playlist);
+        exit(1);
+    }
+    if let Err(e) = tdb.export_playlist(playlist, &dest, relative) {
+        eprintln!("Export failed: {:#}", e);
+        exit(1);
+    }
+    println!("Exported {} to {}", playlist, dest.display());
+}
+
+fn run_import_playlist(args: &[String]) {
+    let mut src = None;
+    let mut playlist = None;
+
+    let mut args_iter = args.iter();
+    while let Some(a) = args_iter.next() {
+        match a.as_str() {
+            "--playlist" => match args_iter.next() {
+                Some(p) => playlist = Some(p.clone()),
+                None => usage_error(IMPORT_PLAYLIST_USAGE),
+            },
+            "-h" | "--help" => {
+                println!("{}", IMPORT_PLAYLIST_USAGE);
+                return;
+            }
+            _ if src.is_none() && !a.starts_with('-') => src = Some(PathBuf::from(a)),
+            _ => usage_error(IMPORT_PLAYLIST_USAGE),
+        }
+    }
+    let src = src.unwrap_or_else(|| usage_error(IMPORT_PLAYLIST_USAGE));
+
+    init_files();
+    let mut tdb = TrackDB::init();
+    let report = match tdb.import_playlist_file(&src, playlist.as_deref()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Import failed: {:#}", e);
+            exit(1);
+        }
+    };
+
+    println!("Added {} tracks to {}", report.added, report.playlist);
+    if !report.unresolved.is_empty() {
+        println!("Could not find {} entries:", report.unresolved.len());
+        for e in report.unresolved.iter() {
+            println!("  {}", e);
+        }
+    }
+}
+
+fn usage_error(usage: &str) -> ! {
+    eprintln!("{}", usage);
+    exit(2);
+}
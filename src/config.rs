@@ -0,0 +1,173 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+};
+
+use crate::{
+    db::layout::validate_template, downloader::DownloaderKind, utils::get_config_file_path, DEFAULT_CONCURRENT_DOWNLOADS,
+    DEFAULT_DOWNLOAD_RETRIES, DEFAULT_PATH_TEMPLATE,
+};
+
+// formats spotdl can download tracks as
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadFormat {
+    Mp3,
+    Flac,
+    Ogg,
+    Opus,
+    M4a,
+    Wav,
+}
+
+impl DownloadFormat {
+    pub const ALL: [DownloadFormat; 6] = [
+        DownloadFormat::Mp3,
+        DownloadFormat::Flac,
+        DownloadFormat::Ogg,
+        DownloadFormat::Opus,
+        DownloadFormat::M4a,
+        DownloadFormat::Wav,
+    ];
+
+    // also the value spotdl's --format takes
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadFormat::Mp3 => "mp3",
+            DownloadFormat::Flac => "flac",
+            DownloadFormat::Ogg => "ogg",
+            DownloadFormat::Opus => "opus",
+            DownloadFormat::M4a => "m4a",
+            DownloadFormat::Wav => "wav",
+        }
+    }
+}
+
+// tracklist columns that can be turned on, on top of name, artist, album and duration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackColumn {
+    AlbumArtist,
+    TrackNumber,
+    Year,
+    Genre,
+    DateAdded,
+}
+
+impl TrackColumn {
+    pub const ALL: [TrackColumn; 5] = [
+        TrackColumn::AlbumArtist,
+        TrackColumn::TrackNumber,
+        TrackColumn::Year,
+        TrackColumn::Genre,
+        TrackColumn::DateAdded,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            TrackColumn::AlbumArtist => "Album Artist",
+            TrackColumn::TrackNumber => "#",
+            TrackColumn::Year => "Year",
+            TrackColumn::Genre => "Genre",
+            TrackColumn::DateAdded => "Added",
+        }
+    }
+}
+
+// User settings from config.yaml in the platform's config dir. Fields missing from the file
+// keep their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub download_format: DownloadFormat,
+    // defaults to MUSIC_DIR in the home dir, only read at startup
+    pub music_dir: Option<PathBuf>,
+    // see db::layout for the keys it can use
+    pub path_template: String,
+    // shown in this order
+    pub tracklist_columns: Vec<TrackColumn>,
+    // synced playlists not synced for this long are synced at startup
+    pub sync_interval_hours: Option<u32>,
+    // tracks a synced playlist's remote no longer has are removed instead of flagged
+    pub sync_remove_missing: bool,
+    // downloads running at once, the rest wait their turn
+    pub max_concurrent_downloads: usize,
+    pub download_retries: u32,
+    // tried in this order, a failed download falls back to the next one that can download it
+    pub downloaders: Vec<DownloaderKind>,
+    // for the command downloader, see downloader::CustomCommand
+    pub download_command: Option<String>,
+    // for searches and links, spotdl's are used when unset
+    pub spotify_client_id: Option<String>,
+    pub spotify_client_secret: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            download_format: DownloadFormat::Mp3,
+            music_dir: None,
+            path_template: DEFAULT_PATH_TEMPLATE.to_string(),
+            tracklist_columns: Vec::new(),
+            sync_interval_hours: None,
+            sync_remove_missing: false,
+            max_concurrent_downloads: DEFAULT_CONCURRENT_DOWNLOADS,
+            download_retries: DEFAULT_DOWNLOAD_RETRIES,
+            downloaders: vec![DownloaderKind::Spotdl, DownloaderKind::YtDlp],
+            download_command: None,
+            spotify_client_id: None,
+            spotify_client_secret: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = get_config_file_path();
+        let contents = match read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => return Config::default(),
+        };
+
+        let mut config: Config = match serde_yaml::from_str(&contents) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Could not parse {:?}, using defaults : {}", path, e);
+                return Config::default();
+            }
+        };
+        if let Err(e) = validate_template(&config.path_template) {
+            warn!("Invalid path_template in {:?}, using the default : {}", path, e);
+            config.path_template = DEFAULT_PATH_TEMPLATE.to_string();
+        }
+        config
+    }
+
+    pub fn save(&self) {
+        let path = get_config_file_path();
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, serde_yaml::to_string(self).unwrap()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let c: Config = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(c.download_format, DownloadFormat::Mp3);
+
+        let c: Config = serde_yaml::from_str("download_format: flac").unwrap();
+        assert_eq!(c.download_format, DownloadFormat::Flac);
+
+        let c: Config = serde_yaml::from_str("tracklist_columns: [year, date_added]").unwrap();
+        assert_eq!(c.tracklist_columns, vec![TrackColumn::Year, TrackColumn::DateAdded]);
+
+        let c: Config = serde_yaml::from_str("downloaders: [yt-dlp, command]").unwrap();
+        assert_eq!(c.downloaders, vec![DownloaderKind::YtDlp, DownloaderKind::Command]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::params;
+
+use super::TrackDB;
+use crate::spotdl::SearchResult;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadStatus {
+    Queued,
+    Downloading,
+    // the file is there, its tags are being written and it's being added to the library
+    Tagging,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl DownloadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadStatus::Queued => "queued",
+            DownloadStatus::Downloading => "downloading",
+            DownloadStatus::Tagging => "tagging",
+            DownloadStatus::Done => "done",
+            DownloadStatus::Failed => "failed",
+            DownloadStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        match s {
+            "downloading" => DownloadStatus::Downloading,
+            "tagging" => DownloadStatus::Tagging,
+            "done" => DownloadStatus::Done,
+            "failed" => DownloadStatus::Failed,
+            "cancelled" => DownloadStatus::Cancelled,
+            _ => DownloadStatus::Queued,
+        }
+    }
+
+    // no longer queued or running
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            DownloadStatus::Done | DownloadStatus::Failed | DownloadStatus::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadJob {
+    pub id: u32,
+    pub result: SearchResult,
+    // where the track goes once it's downloaded
+    pub playlist: String,
+    pub status: DownloadStatus,
+    // in percent, as parsed from the downloader's output. Not stored
+    pub progress: f32,
+    // the downloader's output when it failed
+    pub error: Option<String>,
+    pub attempts: u32,
+    // which of the downloaders that can download the track the next attempt uses. Not stored
+    pub fallback: usize,
+}
+
+impl DownloadJob {
+    // Each job downloads into its own directory under root, so jobs for the same track or into
+    // different playlists don't pick up each other's files
+    pub fn staging_dir(&self, root: &Path) -> PathBuf {
+        root.join(format!("job-{}", self.id))
+    }
+}
+
+impl TrackDB {
+    pub fn add_download(&mut self, result: SearchResult, playlist: &str) -> DownloadJob {
+        self.conn
+            .execute(
+                "INSERT INTO downloads (result, playlist, status) VALUES (?1, ?2, ?3)",
+                params![
+                    serde_json::to_string(&result).unwrap(),
+                    playlist,
+                    DownloadStatus::Queued.as_str()
+                ],
+            )
+            .unwrap();
+        DownloadJob {
+            id: self.conn.last_insert_rowid() as u32,
+            result,
+            playlist: playlist.to_string(),
+            status: DownloadStatus::Queued,
+            progress: 0.0,
+            error: None,
+            attempts: 0,
+            fallback: 0,
+        }
+    }
+
+    pub fn update_download(&mut self, job: &DownloadJob) {
+        self.conn
+            .execute(
+                "UPDATE downloads SET status = ?2, error = ?3, attempts = ?4 WHERE id = ?1",
+                params![job.id, job.status.as_str(), job.error, job.attempts],
+            )
+            .unwrap();
+    }
+
+    pub fn remove_download(&mut self, id: u32) {
+        self.conn
+            .execute("DELETE FROM downloads WHERE id = ?1", [id])
+            .unwrap();
+    }
+
+    // all jobs in the order they were added
+    pub fn downloads(&self) -> Vec<DownloadJob> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, result, playlist, status, error, attempts FROM downloads ORDER BY id",
+            )
+            .unwrap();
+        stmt.query_map([], |r| {
+            let result: String = r.get(1)?;
+            let status: String = r.get(3)?;
+            Ok(DownloadJob {
+                id: r.get(0)?,
+                result: serde_json::from_str(&result).unwrap(),
+                playlist: r.get(2)?,
+                status: DownloadStatus::from_str(&status),
+                progress: 0.0,
+                error: r.get(4)?,
+                attempts: r.get(5)?,
+                fallback: 0,
+            })
+        })
+        .unwrap()
+        .map(|j| j.unwrap())
+        .collect()
+    }
+}
@@ -0,0 +1,166 @@
+use log::{info, warn};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs::{read, rename},
+    path::{Path, PathBuf},
+};
+
+use super::{add_to_playlist, get_or_insert_named, insert_track, TrackDB, TrackInfo};
+use crate::state::filter_state::F1State;
+
+// The bincode TrackDB from before the sqlite store, kept only to read old files. These must
+// not change, or old files stop deserializing
+#[derive(Deserialize)]
+struct LegacyTrackInfo {
+    id: u32,
+    name: String,
+    artists: Option<Vec<String>>,
+    album: Option<String>,
+    playlist: String,
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct LegacyTrackDB {
+    track_filter_cache: BTreeMap<F1State, BTreeMap<String, Vec<u32>>>,
+    trackmap: BTreeMap<u32, LegacyTrackInfo>,
+    max_id: u32,
+}
+
+// imports the bincode TrackDB at path, then renames it so this only happens once
+pub fn import_legacy_file(tdb: &mut TrackDB, path: &Path) {
+    let encoded = read(path).unwrap();
+    let legacy: LegacyTrackDB = match bincode::deserialize(&encoded) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("Could not read old track db at {:?}, not importing : {}", path, e);
+            return;
+        }
+    };
+
+    if tdb.track_count() > 0 {
+        warn!("Track db is not empty, not importing {:?}", path);
+    } else {
+        tdb.import_legacy(legacy);
+        info!("Imported old track db from {:?}", path);
+    }
+
+    let mut done_path = path.as_os_str().to_owned();
+    done_path.push(".imported");
+    rename(path, done_path).unwrap();
+}
+
+impl TrackDB {
+    // keeps track ids as they were, since they are part of the track file names
+    fn import_legacy(&mut self, legacy: LegacyTrackDB) {
+        let tx = self.conn.transaction().unwrap();
+
+        for t in legacy.trackmap.values() {
+            let t_info = TrackInfo {
+                id: t.id,
+                name: t.name.clone(),
+                artists: t.artists.clone(),
+                album: t.album.clone(),
+                duration: t.duration,
+                file: Some(PathBuf::from(format!("{} - {}.mp3", t.name, t.id).replace('/', ""))),
+                extension: "mp3".to_string(),
+                codec: Some("MP3".to_string()),
+                ..Default::default()
+            };
+            insert_track(&tx, &t_info, Some(t.id));
+        }
+
+        // playlists are imported with their order, including empty ones
+        let playlists = legacy.track_filter_cache.get(&F1State::Playlists);
+        for (name, tids) in playlists.into_iter().flatten() {
+            get_or_insert_named(&tx, "playlists", name);
+            for tid in tids.iter().filter(|t| legacy.trackmap.contains_key(t)) {
+                add_to_playlist(&tx, *tid, name);
+            }
+        }
+        // the cache could be out of sync, trackmap is what each track was saved with
+        for t in legacy.trackmap.values() {
+            add_to_playlist(&tx, t.id, &t.playlist);
+        }
+
+        // new tracks keep getting ids past the old max_id, even if that track is gone
+        tx.execute(
+            "INSERT INTO sqlite_sequence (name, seq)
+             SELECT 'tracks', 0 WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'tracks')",
+            [],
+        )
+        .unwrap();
+        tx.execute(
+            "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'tracks'",
+            [legacy.max_id],
+        )
+        .unwrap();
+
+        tx.commit().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_db() -> LegacyTrackDB {
+        let mut trackmap = BTreeMap::new();
+        trackmap.insert(
+            3,
+            LegacyTrackInfo {
+                id: 3,
+                name: "a".to_string(),
+                artists: Some(vec!["x".to_string()]),
+                album: Some("al".to_string()),
+                playlist: "Mix".to_string(),
+                duration: 120,
+            },
+        );
+        trackmap.insert(
+            5,
+            LegacyTrackInfo {
+                id: 5,
+                name: "b".to_string(),
+                artists: None,
+                album: None,
+                playlist: "Mix".to_string(),
+                duration: 60,
+            },
+        );
+
+        let mut playlists = BTreeMap::new();
+        playlists.insert("Liked".to_string(), vec![]);
+        playlists.insert("Mix".to_string(), vec![5, 3]);
+        playlists.insert("Empty".to_string(), vec![]);
+        let mut track_filter_cache = BTreeMap::new();
+        track_filter_cache.insert(F1State::Playlists, playlists);
+
+        LegacyTrackDB {
+            track_filter_cache,
+            trackmap,
+            max_id: 7,
+        }
+    }
+
+    #[test]
+    fn imports_tracks_playlists_and_ids() {
+        let mut tdb = TrackDB::open_in_memory();
+        tdb.import_legacy(legacy_db());
+
+        assert_eq!(tdb.filter_values(&F1State::Playlists), vec!["Empty", "Liked", "Mix"]);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Mix"), vec![5, 3]);
+        assert_eq!(tdb.filter_tracks(&F1State::Artists, "x"), vec![3]);
+        assert_eq!(tdb.filter_tracks(&F1State::Albums, "None"), vec![5]);
+
+        let t = tdb.get_track(3).unwrap();
+        assert_eq!(t.name, "a");
+        assert_eq!(t.duration, 120);
+        assert_eq!(t.file, Some(PathBuf::from("a - 3.mp3")));
+        assert_eq!(tdb.get_track_playlists(3), vec!["Mix"]);
+
+        let new_id = tdb.add_track(&TrackInfo { file: None, ..t }, None);
+        assert_eq!(new_id, 8);
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+
+use super::TrackInfo;
+use crate::{config::Config, utils::get_music_dir};
+
+const TEMPLATE_KEYS: &[&str] = &[
+    "title",
+    "artist",
+    "artists",
+    "album_artist",
+    "album",
+    "genre",
+    "disc",
+    "track",
+    "year",
+    "id",
+    "ext",
+];
+// characters not allowed in file names on at least one of the platforms we run on
+const RESERVED_CHARS: &str = "<>:\"/\\|?*";
+const MAX_VALUE_LEN: usize = 100;
+
+// Where managed files go under the music dir. The template is a relative path with {key}
+// placeholders from TEMPLATE_KEYS, ie: {album_artist}/{album}/{disc}-{track} {title}.{ext}
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub root: PathBuf,
+    pub template: String,
+}
+
+// splits a template component into literal text and placeholder keys
+fn parse_component(component: &str) -> Result<Vec<(bool, &str)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = component;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or(format!("unclosed {{ in {:?}", component))?
+            + start;
+        parts.push((false, &rest[..start]));
+        parts.push((true, &rest[start + 1..end]));
+        rest = &rest[end + 1..];
+    }
+    parts.push((false, rest));
+    Ok(parts)
+}
+
+pub fn validate_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("template is empty".to_string());
+    }
+    if template.starts_with('/') || template.starts_with('\\') {
+        return Err("template must be relative to the music dir".to_string());
+    }
+    for component in template.split('/') {
+        if component.is_empty() || component == "." || component == ".." {
+            return Err(format!("invalid path component {:?}", component));
+        }
+        for (is_key, s) in parse_component(component)? {
+            if is_key && !TEMPLATE_KEYS.contains(&s) {
+                return Err(format!("unknown key {{{}}}", s));
+            }
+        }
+    }
+    Ok(())
+}
+
+// makes a tag value safe to use as (part of) a file name
+pub fn sanitize_value(s: &str) -> String {
+    let cleaned = s
+        .chars()
+        .map(|c| {
+            if c.is_control() || RESERVED_CHARS.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_VALUE_LEN)
+        .collect::<String>();
+    cleaned.trim().to_string()
+}
+
+// leading dots would hide the file, trailing dots and spaces are dropped by windows
+fn sanitize_component(s: &str) -> String {
+    let trimmed = s
+        .trim_start_matches(['.', ' '])
+        .trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        "_".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn template_value(key: &str, t: &TrackInfo) -> String {
+    let first_artist = t
+        .artists
+        .as_ref()
+        .and_then(|a| a.first().cloned())
+        .unwrap_or("Unknown Artist".to_string());
+    match key {
+        "title" => t.name.clone(),
+        "artist" => first_artist,
+        "album_artist" => t.album_artist.clone().unwrap_or(first_artist),
+        "artists" => t
+            .artists
+            .as_ref()
+            .map(|a| a.join(", "))
+            .unwrap_or("Unknown Artist".to_string()),
+        "album" => t.album.clone().unwrap_or("Unknown Album".to_string()),
+        "genre" => t.genre.clone().unwrap_or("Unknown Genre".to_string()),
+        "disc" => t.disc_number.unwrap_or(1).to_string(),
+        "track" => format!("{:0>2}", t.track_number.unwrap_or(0)),
+        "year" => t
+            .release_date
+            .as_ref()
+            .and_then(|d| d.get(..4))
+            .unwrap_or("Unknown Year")
+            .to_string(),
+        "id" => t.id.to_string(),
+        "ext" => t.extension.clone(),
+        _ => String::new(),
+    }
+}
+
+impl Layout {
+    pub fn from_config(config: &Config) -> Self {
+        Layout {
+            root: get_music_dir(),
+            template: config.path_template.clone(),
+        }
+    }
+
+    // the path of t's file relative to root. Values can't add path components, a / in a title
+    // is replaced like any other reserved character
+    pub fn render(&self, t: &TrackInfo) -> PathBuf {
+        let mut path = PathBuf::new();
+        let components = self.template.split('/').collect::<Vec<&str>>();
+        for (i, component) in components.iter().enumerate() {
+            let mut s = parse_component(component)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(is_key, part)| match is_key {
+                    true => sanitize_value(&template_value(part, t)),
+                    false => part.to_string(),
+                })
+                .collect::<String>();
+            if i == components.len() - 1 && !self.template.contains("{ext}") {
+                s = format!("{}.{}", s, t.extension);
+            }
+            path.push(sanitize_component(&s));
+        }
+        path
+    }
+
+    // rel, or rel with a number appended if some other file is already there
+    pub fn free_path(&self, rel: &Path, current: Option<&Path>) -> PathBuf {
+        let taken = |p: &Path| Some(p) != current && self.root.join(p).exists();
+        if !taken(rel) {
+            return rel.to_path_buf();
+        }
+
+        let stem = rel.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = rel.extension().map(|e| e.to_string_lossy());
+        (2..)
+            .map(|n| {
+                let name = match &ext {
+                    Some(e) => format!("{} ({}).{}", stem, n, e),
+                    None => format!("{} ({})", stem, n),
+                };
+                rel.with_file_name(name)
+            })
+            .find(|p| !taken(p))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(template: &str) -> Layout {
+        Layout {
+            root: PathBuf::from("/nonexistent/mprs"),
+            template: template.to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_templates_with_sanitized_values() {
+        let t = TrackInfo {
+            id: 7,
+            name: "AC/DC: Live?".to_string(),
+            artists: Some(vec!["A".to_string(), "B".to_string()]),
+            album: Some("...Album ".to_string()),
+            track_number: Some(3),
+            release_date: Some("2001-05-04".to_string()),
+            extension: "flac".to_string(),
+            ..Default::default()
+        };
+
+        let l = layout("{album_artist}/{album} ({year})/{disc}-{track} {title}.{ext}");
+        assert_eq!(
+            l.render(&t),
+            PathBuf::from("A/Album (2001)/1-03 AC_DC_ Live_.flac")
+        );
+        assert_eq!(
+            layout("{title} - {id}").render(&t),
+            PathBuf::from("AC_DC_ Live_ - 7.flac")
+        );
+        assert_eq!(
+            layout("{artist}/{album}/x.{ext}").render(&TrackInfo::default()),
+            PathBuf::from("Unknown Artist/Unknown Album/x")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        assert!(validate_template("{album}/{title}.{ext}").is_ok());
+        assert!(validate_template("{albm}/{title}.{ext}").is_err());
+        assert!(validate_template("/{title}.{ext}").is_err());
+        assert!(validate_template("../{title}.{ext}").is_err());
+        assert!(validate_template("{title.{ext}").is_err());
+        assert!(validate_template("").is_err());
+    }
+}
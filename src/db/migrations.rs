@@ -0,0 +1,236 @@
+use std::{
+    fs::rename,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use rusqlite::{params, Connection, Transaction};
+
+use super::{layout::Layout, TrackInfo};
+use crate::DEFAULT_PATH_TEMPLATE;
+
+// Each entry upgrades the schema by one version, with the version stored in sqlite's
+// user_version. Only ever append to this list, a migration that has shipped must not change
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "
+    CREATE TABLE artists (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE albums (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE tracks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        album_id INTEGER REFERENCES albums(id),
+        duration INTEGER NOT NULL
+    );
+    CREATE INDEX tracks_album_id ON tracks(album_id);
+
+    CREATE TABLE track_artists (
+        track_id INTEGER NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+        artist_id INTEGER NOT NULL REFERENCES artists(id),
+        position INTEGER NOT NULL,
+        PRIMARY KEY (track_id, artist_id)
+    );
+    CREATE INDEX track_artists_artist_id ON track_artists(artist_id);
+
+    CREATE TABLE playlists (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE playlist_entries (
+        playlist_id INTEGER NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+        track_id INTEGER NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (playlist_id, track_id)
+    );
+    CREATE INDEX playlist_entries_track_id ON playlist_entries(track_id);
+
+    INSERT INTO playlists (name) VALUES ('Liked');
+    ",
+    // 2: where tracks came from, to recognize them when they're searched for again
+    "
+    ALTER TABLE tracks ADD COLUMN source_id TEXT;
+    ALTER TABLE tracks ADD COLUMN isrc TEXT;
+    ALTER TABLE tracks ADD COLUMN release_date TEXT;
+    ALTER TABLE tracks ADD COLUMN track_number INTEGER;
+    ALTER TABLE tracks ADD COLUMN disc_number INTEGER;
+    CREATE INDEX tracks_source_id ON tracks(source_id);
+    CREATE INDEX tracks_isrc ON tracks(isrc);
+    ",
+    // 3: tracks referenced in place outside the music dir
+    "
+    ALTER TABLE tracks ADD COLUMN path TEXT;
+    CREATE UNIQUE INDEX tracks_path ON tracks(path);
+    ",
+    // 4: file formats other than mp3, everything before this was downloaded as mp3
+    "
+    ALTER TABLE tracks ADD COLUMN extension TEXT NOT NULL DEFAULT 'mp3';
+    ALTER TABLE tracks ADD COLUMN codec TEXT;
+    ",
+    // 5: managed files can be anywhere under the music dir, name_legacy_files fills this in for
+    // the ones already there
+    "
+    ALTER TABLE tracks ADD COLUMN file TEXT;
+    CREATE UNIQUE INDEX tracks_file ON tracks(file);
+    ",
+    // 6: more of the tags, date_added is unknown for tracks added before this
+    "
+    ALTER TABLE tracks ADD COLUMN album_artist TEXT;
+    ALTER TABLE tracks ADD COLUMN genre TEXT;
+    ALTER TABLE tracks ADD COLUMN date_added TEXT;
+    CREATE INDEX tracks_genre ON tracks(genre);
+    ",
+    // 7: play counts and smart playlists, stored as json
+    "
+    ALTER TABLE tracks ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE tracks ADD COLUMN last_played TEXT;
+    CREATE TABLE smart_playlists (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        definition TEXT NOT NULL
+    );
+    ",
+    // 8: playlists synced with a remote one. Its tracks are kept in the remote order so the ones
+    // downloaded later can be put in place, stale marks entries the remote no longer has
+    "
+    ALTER TABLE playlists ADD COLUMN source_url TEXT;
+    ALTER TABLE playlists ADD COLUMN last_synced TEXT;
+    ALTER TABLE playlists ADD COLUMN sync_summary TEXT;
+    ALTER TABLE playlist_entries ADD COLUMN stale INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE remote_playlist_tracks (
+        playlist_id INTEGER NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        source_id TEXT,
+        isrc TEXT,
+        PRIMARY KEY (playlist_id, position)
+    );
+    ",
+    // 9: the download manager's jobs, with the search result they download stored as json
+    "
+    CREATE TABLE downloads (
+        id INTEGER PRIMARY KEY,
+        result TEXT NOT NULL,
+        playlist TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0
+    );
+    ",
+];
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.pragma_query_value(None, "user_version", |r| r.get(0))
+}
+
+// the version that added tracks.file, along with renaming the managed files
+const FILE_MIGRATION: usize = 5;
+
+// Managed files used to be named after the track's name, with / dropped, and id. They're renamed
+// the way the default template sanitizes them, so they already are where the default layout
+// wants them
+fn name_legacy_files(tx: &Transaction, music_dir: &Path) -> rusqlite::Result<()> {
+    let layout = Layout {
+        root: music_dir.to_path_buf(),
+        template: DEFAULT_PATH_TEMPLATE.to_string(),
+    };
+    let tracks = tx
+        .prepare("SELECT id, name, extension FROM tracks WHERE path IS NULL")?
+        .query_map([], |r| {
+            Ok(TrackInfo {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                extension: r.get(2)?,
+                ..Default::default()
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<TrackInfo>>>()?;
+
+    for t in tracks {
+        let legacy = format!("{} - {}.{}", t.name, t.id, t.extension).replace('/', "");
+        let legacy = PathBuf::from(legacy);
+        let mut file = layout.render(&t);
+        let (from, to) = (music_dir.join(&legacy), music_dir.join(&file));
+        if file != legacy && from.exists() {
+            if to.exists() {
+                file = legacy;
+            } else if let Err(e) = rename(&from, &to) {
+                warn!("Could not rename {:?} : {}", from, e);
+                file = legacy;
+            }
+        }
+        tx.execute(
+            "UPDATE tracks SET file = ?1 WHERE id = ?2",
+            params![file.to_string_lossy(), t.id],
+        )?;
+    }
+    Ok(())
+}
+
+// brings the schema up to date, each migration running in its own transaction
+pub fn migrate(conn: &mut Connection, music_dir: &Path) -> rusqlite::Result<()> {
+    let version = schema_version(conn)?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        debug!("Migrating track db to version {}", i + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        if i + 1 == FILE_MIGRATION {
+            name_legacy_files(&tx, music_dir)?;
+        }
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TrackDB;
+    use tempfile::TempDir;
+
+    #[test]
+    fn migrates_fresh_db_to_latest_and_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, Path::new("/nonexistent")).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        migrate(&mut conn, Path::new("/nonexistent")).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn legacy_files_end_up_where_the_default_layout_wants_them() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..FILE_MIGRATION - 1] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", FILE_MIGRATION - 1)
+            .unwrap();
+        conn.execute(
+            "INSERT INTO tracks (name, duration) VALUES ('AC/DC: Live?', 100)",
+            [],
+        )
+        .unwrap();
+        std::fs::write(root.join("ACDC: Live? - 1.mp3"), b"a").unwrap();
+
+        let layout = Layout {
+            root: root.to_path_buf(),
+            template: DEFAULT_PATH_TEMPLATE.to_string(),
+        };
+        let tdb = TrackDB::from_connection(conn, layout);
+        let t = tdb.get_track(1).unwrap();
+        assert_eq!(t.file, Some(PathBuf::from("AC_DC_ Live_ - 1.mp3")));
+        assert!(root.join("AC_DC_ Live_ - 1.mp3").exists());
+        assert!(tdb.plan_reorganize().is_empty());
+    }
+}
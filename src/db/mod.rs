@@ -0,0 +1,1036 @@
+pub mod import;
+pub mod layout;
+pub mod migrations;
+pub mod playlist_file;
+pub mod smart;
+pub mod sync;
+pub mod downloads;
+
+use anyhow::{anyhow, Context};
+use log::{debug, warn};
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use serde::{Deserialize, Serialize};
+use std::fs::{copy, create_dir_all, remove_dir, remove_dir_all, remove_file, rename};
+use std::time::Duration;
+use crate::{config::Config, state::filter_state::F1State, utils::{get_cache_file_path, get_db_file_path, get_metadata, get_music_dir, get_newtracks_dir, read_tags, write_tags, TagEdit}};
+use layout::Layout;
+use std::{collections::BTreeSet, fs::read_dir, path::{Path, PathBuf}};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TrackInfo {
+    pub id: u32,
+    pub name: String,
+    pub artists: Option<Vec<String>>,
+    pub album: Option<String>,
+    pub duration: u32,
+    // where the track was downloaded from, ie: spotify:track:<id>
+    pub source_id: Option<String>,
+    pub isrc: Option<String>,
+    pub release_date: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    // utc, as YYYY-MM-DD HH:MM:SS. Set by the db when the track is added
+    pub date_added: Option<String>,
+    // set for files referenced in place, otherwise the file lives in the music dir
+    pub path: Option<PathBuf>,
+    // where the file is relative to the music dir, unset for files referenced in place
+    pub file: Option<PathBuf>,
+    // of the file, without the dot
+    pub extension: String,
+    pub codec: Option<String>,
+}
+
+// how add_track_file gets the file into the library
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackFileMode {
+    Move,
+    Copy,
+    Reference,
+}
+
+impl TrackInfo {
+    pub fn get_file_path(&self) -> PathBuf {
+        if let Some(p) = &self.path {
+            return p.clone();
+        }
+        let mut p = get_music_dir();
+        p.push(self.file.clone().unwrap_or_default());
+        p
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReorganizeReport {
+    pub moved: u32,
+    // tracks whose file could not be moved, with the reason why
+    pub failed: Vec<(u32, String)>,
+}
+
+// The track library, stored in a sqlite db in the music dir. Playlists are ordered lists of
+// references to library tracks, so a track can be in any number of them. Smart playlists are
+// listed along with them, their tracks being whatever matches their rules. Tracks without
+// artists or an album are listed under "None" in those categories
+pub struct TrackDB {
+    conn: Connection,
+    layout: Layout,
+    // orders smart playlists sorted by random
+    shuffle_seed: u64,
+}
+
+impl TrackDB {
+    pub fn init() -> Self {
+        let mut tdb = TrackDB::open(&get_db_file_path());
+        let legacy_path = get_cache_file_path();
+        if legacy_path.exists() {
+            import::import_legacy_file(&mut tdb, &legacy_path);
+        }
+        tdb
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let layout = Layout::from_config(&Config::load());
+        Self::from_connection(Connection::open(path).unwrap(), layout)
+    }
+
+    pub fn open_in_memory() -> Self {
+        let layout = Layout::from_config(&Config::default());
+        Self::from_connection(Connection::open_in_memory().unwrap(), layout)
+    }
+
+    fn from_connection(mut conn: Connection, layout: Layout) -> Self {
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        // imports run on their own connection while the ui keeps reading
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        conn.busy_timeout(Duration::from_secs(5)).unwrap();
+        migrations::migrate(&mut conn, &layout.root).unwrap();
+        TrackDB {
+            conn,
+            layout,
+            shuffle_seed: rand::random(),
+        }
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    fn query_strings<P: Params>(&self, sql: &str, p: P) -> Vec<String> {
+        let mut stmt = self.conn.prepare_cached(sql).unwrap();
+        let rows = stmt.query_map(p, |r| r.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    fn query_ids<P: Params>(&self, sql: &str, p: P) -> Vec<u32> {
+        let mut stmt = self.conn.prepare_cached(sql).unwrap();
+        let rows = stmt.query_map(p, |r| r.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    // Changes whenever the db is written to, by this connection or another one like an import's,
+    // so what's read from it can be kept until then
+    pub fn library_version(&self) -> (i64, i64) {
+        self.conn
+            .query_row(
+                "SELECT total_changes(), data_version FROM pragma_data_version",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap()
+    }
+
+    // the values listed in the f2 panel for a category, sorted by name
+    pub fn filter_values(&self, f1_state: &F1State) -> Vec<String> {
+        let mut values = match f1_state {
+            F1State::All => return vec!["All".to_string()],
+            F1State::Playlists => self.query_strings(
+                "SELECT name FROM playlists UNION SELECT name FROM smart_playlists",
+                [],
+            ),
+            F1State::Artists => self.query_strings("SELECT name FROM artists", []),
+            F1State::Albums => self.query_strings("SELECT name FROM albums", []),
+            F1State::Genres => self.query_strings(
+                "SELECT DISTINCT genre FROM tracks WHERE genre IS NOT NULL",
+                [],
+            ),
+            F1State::Years => self.query_strings(
+                "SELECT DISTINCT substr(release_date, 1, 4) FROM tracks
+                 WHERE release_date IS NOT NULL",
+                [],
+            ),
+        }
+        .into_iter()
+        .collect::<BTreeSet<String>>();
+
+        if let F1State::Artists | F1State::Albums | F1State::Genres | F1State::Years = f1_state {
+            values.insert("None".to_string());
+        }
+        values.into_iter().collect()
+    }
+
+    // ids of the tracks listed under value in a category, in playlist order for playlists, in
+    // disc and track order for albums and in the order they were added otherwise
+    pub fn filter_tracks(&self, f1_state: &F1State, value: &str) -> Vec<u32> {
+        match (f1_state, value) {
+            (F1State::All, _) => self.query_ids("SELECT id FROM tracks ORDER BY id", []),
+            (F1State::Playlists, _) if self.is_smart_playlist(value) => {
+                self.smart_playlist_tracks(value)
+            }
+            (F1State::Playlists, _) => self.query_ids(
+                "SELECT pe.track_id FROM playlist_entries pe
+                 JOIN playlists p ON p.id = pe.playlist_id
+                 WHERE p.name = ?1 ORDER BY pe.position",
+                [value],
+            ),
+            (F1State::Artists, "None") => self.query_ids(
+                "SELECT t.id FROM tracks t
+                 WHERE NOT EXISTS (SELECT 1 FROM track_artists ta WHERE ta.track_id = t.id)
+                 ORDER BY t.id",
+                [],
+            ),
+            (F1State::Artists, _) => self.query_ids(
+                "SELECT ta.track_id FROM track_artists ta
+                 JOIN artists ar ON ar.id = ta.artist_id
+                 WHERE ar.name = ?1 ORDER BY ta.track_id",
+                [value],
+            ),
+            (F1State::Albums, "None") => self.query_ids(
+                "SELECT id FROM tracks WHERE album_id IS NULL ORDER BY id",
+                [],
+            ),
+            (F1State::Albums, _) => self.query_ids(
+                "SELECT t.id FROM tracks t
+                 JOIN albums al ON al.id = t.album_id
+                 WHERE al.name = ?1
+                 ORDER BY COALESCE(t.disc_number, 1), t.track_number IS NULL, t.track_number, t.id",
+                [value],
+            ),
+            (F1State::Genres, "None") => self.query_ids(
+                "SELECT id FROM tracks WHERE genre IS NULL ORDER BY id",
+                [],
+            ),
+            (F1State::Genres, _) => self.query_ids(
+                "SELECT id FROM tracks WHERE genre = ?1 ORDER BY id",
+                [value],
+            ),
+            (F1State::Years, "None") => self.query_ids(
+                "SELECT id FROM tracks WHERE release_date IS NULL ORDER BY id",
+                [],
+            ),
+            (F1State::Years, _) => self.query_ids(
+                "SELECT id FROM tracks WHERE substr(release_date, 1, 4) = ?1 ORDER BY id",
+                [value],
+            ),
+        }
+    }
+
+    // the playlists tracks can be added to, so no smart ones
+    pub fn playlist_names(&self) -> Vec<String> {
+        self.query_strings("SELECT name FROM playlists ORDER BY name", [])
+    }
+
+    pub fn get_track(&self, id: u32) -> Option<TrackInfo> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT t.name, al.name, t.duration, t.source_id, t.isrc, t.release_date,
+                 t.track_number, t.disc_number, t.path, t.file, t.extension, t.codec,
+                 t.album_artist, t.genre, t.date_added
+                 FROM tracks t
+                 LEFT JOIN albums al ON al.id = t.album_id
+                 WHERE t.id = ?1",
+            )
+            .unwrap();
+        let mut t_info = stmt
+            .query_row([id], |r| {
+                Ok(TrackInfo {
+                    id,
+                    name: r.get(0)?,
+                    artists: None,
+                    album: r.get(1)?,
+                    duration: r.get(2)?,
+                    source_id: r.get(3)?,
+                    isrc: r.get(4)?,
+                    release_date: r.get(5)?,
+                    track_number: r.get(6)?,
+                    disc_number: r.get(7)?,
+                    path: r.get::<_, Option<String>>(8)?.map(PathBuf::from),
+                    file: r.get::<_, Option<String>>(9)?.map(PathBuf::from),
+                    extension: r.get(10)?,
+                    codec: r.get(11)?,
+                    album_artist: r.get(12)?,
+                    genre: r.get(13)?,
+                    date_added: r.get(14)?,
+                })
+            })
+            .optional()
+            .unwrap()?;
+
+        let artists = self.query_strings(
+            "SELECT ar.name FROM track_artists ta
+             JOIN artists ar ON ar.id = ta.artist_id
+             WHERE ta.track_id = ?1 ORDER BY ta.position",
+            [id],
+        );
+        if !artists.is_empty() {
+            t_info.artists = Some(artists);
+        }
+        Some(t_info)
+    }
+
+    pub fn get_track_playlists(&self, id: u32) -> Vec<String> {
+        self.query_strings(
+            "SELECT p.name FROM playlist_entries pe
+             JOIN playlists p ON p.id = pe.playlist_id
+             WHERE pe.track_id = ?1 ORDER BY p.name",
+            [id],
+        )
+    }
+
+    // the track downloaded from source_id, or failing that one with the same isrc
+    pub fn find_track(&self, source_id: Option<&str>, isrc: Option<&str>) -> Option<u32> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT id FROM tracks WHERE source_id = ?1 OR isrc = ?2
+                 ORDER BY source_id = ?1 DESC, id LIMIT 1",
+            )
+            .unwrap();
+        stmt.query_row(params![source_id, isrc], |r| r.get(0))
+            .optional()
+            .unwrap()
+    }
+
+    pub fn find_track_by_path(&self, path: &Path) -> Option<u32> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id FROM tracks WHERE path = ?1")
+            .unwrap();
+        stmt.query_row([path.to_string_lossy()], |r| r.get(0))
+            .optional()
+            .unwrap()
+    }
+
+    pub fn contains_track(&self, id: u32) -> bool {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT 1 FROM tracks WHERE id = ?1")
+            .unwrap();
+        stmt.exists([id]).unwrap()
+    }
+
+    pub fn track_count(&self) -> u32 {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM tracks", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    // Adds all tracks in the newtracks directory to DB
+    pub fn add_all_tracks(&mut self, playlist: Option<String>) {
+        let newtracks_path = get_newtracks_dir();
+        let newtracks = read_dir(&newtracks_path).unwrap();
+        for t in newtracks {
+            let tp = t.unwrap().path();
+            if tp.is_dir() || tp.file_name().unwrap().to_str().unwrap().starts_with(".") {
+                continue;
+            }
+            let res = self.add_track_file(
+                &tp,
+                TrackInfo::default(),
+                Some(playlist.as_deref().unwrap_or("Liked")),
+                TrackFileMode::Move,
+            );
+            if let Err(e) = res {
+                warn!("Skipped {:?} : {:#}", tp, e);
+            }
+        }
+    }
+
+    // Adds the track a download left in download_dir to DB, with the source info in source
+    // that the file's tags don't carry. Returns None if the download left no usable file
+    pub fn add_downloaded_track(
+        &mut self,
+        download_dir: &Path,
+        source: TrackInfo,
+        playlist: &str,
+    ) -> Option<u32> {
+        let track_path = read_dir(download_dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .find(|p| p.is_file() && !p.file_name().unwrap().to_str().unwrap().starts_with("."));
+
+        let id = track_path.and_then(|tp| {
+            self.add_track_file(&tp, source, Some(playlist), TrackFileMode::Move)
+                .map_err(|e| warn!("Skipped {:?} : {:#}", tp, e))
+                .ok()
+        });
+        if let Err(e) = remove_dir_all(download_dir) {
+            warn!("Could not remove {:?} : {}", download_dir, e);
+        }
+        id
+    }
+
+    // Adds the file at track_path to DB, taking name, artists, duration and format from the file
+    // and everything else from t_info. Fails if the tags can't be read
+    pub fn add_track_file(
+        &mut self,
+        track_path: &Path,
+        mut t_info: TrackInfo,
+        playlist: Option<&str>,
+        mode: TrackFileMode,
+    ) -> anyhow::Result<u32> {
+        let meta = get_metadata(&track_path.to_path_buf())
+            .ok_or(anyhow!("missing or unreadable tags"))?;
+        t_info.name = meta.title;
+        t_info.artists = meta.artists;
+        t_info.duration = meta.duration;
+        t_info.codec = Some(meta.codec);
+        // the source knows these better than tags, if it knows them at all
+        t_info.album = t_info.album.or(meta.album);
+        t_info.album_artist = t_info.album_artist.or(meta.album_artist);
+        t_info.track_number = t_info.track_number.or(meta.track_number);
+        t_info.disc_number = t_info.disc_number.or(meta.disc_number);
+        t_info.release_date = t_info.release_date.or(meta.year.map(|y| y.to_string()));
+        t_info.genre = t_info.genre.or(meta.genre);
+        t_info.extension = track_path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if mode == TrackFileMode::Reference {
+            t_info.path = Some(track_path.canonicalize()?);
+        }
+
+        t_info.file = None;
+
+        t_info.id = self.add_track(&t_info, playlist);
+        debug!("Adding track : {:?}", t_info);
+
+        let res = match mode {
+            TrackFileMode::Move => self.place_file(track_path, &mut t_info, false),
+            TrackFileMode::Copy => self.place_file(track_path, &mut t_info, true),
+            TrackFileMode::Reference => Ok(()),
+        };
+        if let Err(e) = res {
+            self.conn
+                .execute("DELETE FROM tracks WHERE id = ?1", [t_info.id])
+                .unwrap();
+            return Err(e).context("could not add file to the music dir");
+        }
+        Ok(t_info.id)
+    }
+
+    // where the layout wants t_info's file, unless some other file is already there
+    fn target_path(&self, t_info: &TrackInfo) -> PathBuf {
+        self.layout
+            .free_path(&self.layout.render(t_info), t_info.file.as_deref())
+    }
+
+    // Puts the file at src where the layout wants t_info's file, moved or copied there, and
+    // records where it ended up
+    fn place_file(&self, src: &Path, t_info: &mut TrackInfo, keep_src: bool) -> anyhow::Result<()> {
+        let rel = self.target_path(t_info);
+        let dest = self.layout.root.join(&rel);
+        create_dir_all(dest.parent().unwrap())?;
+        if keep_src {
+            copy(src, &dest)?;
+        } else {
+            move_file(src, &dest)?;
+        }
+
+        self.conn.execute(
+            "UPDATE tracks SET file = ?1 WHERE id = ?2",
+            params![rel.to_string_lossy(), t_info.id],
+        )?;
+        t_info.file = Some(rel);
+        Ok(())
+    }
+
+    // where the file is now paired with where the layout wants it, for every managed file
+    // that isn't there yet
+    pub fn plan_reorganize(&self) -> Vec<(u32, PathBuf, PathBuf)> {
+        self.query_ids("SELECT id FROM tracks WHERE path IS NULL ORDER BY id", [])
+            .into_iter()
+            .filter_map(|id| {
+                let t_info = self.get_track(id)?;
+                let current = t_info.file.clone()?;
+                let target = self.target_path(&t_info);
+                Some((id, current, target)).filter(|(_, c, t)| c != t)
+            })
+            .collect()
+    }
+
+    // Moves the track's file to where the layout wants it, returns false if it already is
+    // there or was referenced in place
+    pub fn relocate_track(&mut self, id: u32) -> anyhow::Result<bool> {
+        let mut t_info = self.get_track(id).ok_or(anyhow!("no track with id {}", id))?;
+        let current = match &t_info.file {
+            Some(f) if t_info.path.is_none() => f.clone(),
+            _ => return Ok(false),
+        };
+        if current == self.target_path(&t_info) {
+            return Ok(false);
+        }
+
+        let src = self.layout.root.join(&current);
+        self.place_file(&src, &mut t_info, false)?;
+        remove_empty_dirs(&self.layout.root, src.parent().unwrap());
+        debug!("Moved {:?} to {:?}", current, t_info.file);
+        Ok(true)
+    }
+
+    // Moves every managed file to match the layout. Each track's row is updated right after
+    // its file is moved, so stopping halfway leaves the db in sync with the disk
+    pub fn reorganize(&mut self) -> ReorganizeReport {
+        let mut report = ReorganizeReport::default();
+        for (id, _, _) in self.plan_reorganize() {
+            match self.relocate_track(id) {
+                Ok(true) => report.moved += 1,
+                Ok(false) => {}
+                Err(e) => report.failed.push((id, format!("{:#}", e))),
+            }
+        }
+        report
+    }
+
+    // inserts t_info into the db under a new id, which is returned, adding it to playlist
+    pub fn add_track(&mut self, t_info: &TrackInfo, playlist: Option<&str>) -> u32 {
+        let tx = self.conn.transaction().unwrap();
+        let id = insert_track(&tx, t_info, None);
+        if let Some(p) = playlist {
+            add_to_playlist(&tx, id, p);
+        }
+        tx.commit().unwrap();
+        id
+    }
+
+    // deletes the track from the library and every playlist, along with its file unless it was
+    // referenced in place
+    pub fn remove_track(&mut self, track_id: u32) {
+        let t_info = match self.get_track(track_id) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let tx = self.conn.transaction().unwrap();
+        tx.execute("DELETE FROM tracks WHERE id = ?1", [track_id]).unwrap();
+        prune_orphans(&tx);
+        tx.commit().unwrap();
+
+        if let (None, Some(f)) = (&t_info.path, &t_info.file) {
+            let p = self.layout.root.join(f);
+            match remove_file(&p) {
+                Ok(_) => remove_empty_dirs(&self.layout.root, p.parent().unwrap()),
+                Err(e) => warn!("Could not remove {:?} : {}", p, e),
+            }
+        }
+        debug!("Removed track : {:?}", t_info);
+    }
+
+    // where the track's file is, referenced in place or under the music dir
+    pub fn file_path(&self, t_info: &TrackInfo) -> PathBuf {
+        match &t_info.path {
+            Some(p) => p.clone(),
+            None => self.layout.root.join(t_info.file.clone().unwrap_or_default()),
+        }
+    }
+
+    // Writes the fields of new_trackinfo that files have tags for into the track's file, other
+    // tags are kept, then saves it to the db. Nothing changes in the db if the file can't be
+    // written. new_trackinfo.duration can be whatever, since this cannot be edited on the file
+    pub fn edit_track(&mut self, new_trackinfo: TrackInfo) -> anyhow::Result<()> {
+        let p = self.file_path(&new_trackinfo);
+        let mut tags = read_tags(&p).with_context(|| format!("could not read {:?}", p))?;
+        tags.title = new_trackinfo.name.clone();
+        tags.artists = new_trackinfo.artists.clone().unwrap_or_default();
+        tags.album = new_trackinfo.album.clone().unwrap_or_default();
+        tags.album_artist = new_trackinfo.album_artist.clone().unwrap_or_default();
+        tags.track_number = new_trackinfo.track_number;
+        tags.year = release_year(&new_trackinfo.release_date);
+        tags.genre = new_trackinfo.genre.clone().unwrap_or_default();
+        tags.cover = None;
+        write_tags(&p, &tags).with_context(|| format!("could not write tags to {:?}", p))?;
+
+        self.save_track_info(new_trackinfo);
+        Ok(())
+    }
+
+    // Writes tags into the track's file and then whatever the db keeps of them into the db.
+    // Nothing changes in the db if the file can't be written
+    pub fn edit_track_tags(&mut self, track_id: u32, tags: &TagEdit) -> anyhow::Result<()> {
+        let mut t_info = self
+            .get_track(track_id)
+            .ok_or(anyhow!("no track with id {}", track_id))?;
+        let p = self.file_path(&t_info);
+        write_tags(&p, tags).with_context(|| format!("could not write tags to {:?}", p))?;
+
+        t_info.name = tags.title.clone();
+        t_info.artists = Some(tags.artists.clone()).filter(|a| !a.is_empty());
+        t_info.album = Some(tags.album.clone()).filter(|a| !a.is_empty());
+        t_info.album_artist = Some(tags.album_artist.clone()).filter(|a| !a.is_empty());
+        t_info.track_number = tags.track_number;
+        t_info.genre = Some(tags.genre.clone()).filter(|g| !g.is_empty());
+        // the full date is kept as long as the year still matches
+        if release_year(&t_info.release_date) != tags.year {
+            t_info.release_date = tags.year.map(|y| y.to_string());
+        }
+        self.save_track_info(t_info);
+        Ok(())
+    }
+
+    fn save_track_info(&mut self, new_trackinfo: TrackInfo) {
+        let id = new_trackinfo.id;
+
+        let tx = self.conn.transaction().unwrap();
+        let album_id = new_trackinfo
+            .album
+            .as_ref()
+            .map(|a| get_or_insert_named(&tx, "albums", a));
+        tx.execute(
+            "UPDATE tracks SET name = ?1, album_id = ?2, source_id = ?3, isrc = ?4,
+             release_date = ?5, track_number = ?6, disc_number = ?7, album_artist = ?8,
+             genre = ?9 WHERE id = ?10",
+            params![
+                new_trackinfo.name,
+                album_id,
+                new_trackinfo.source_id,
+                new_trackinfo.isrc,
+                new_trackinfo.release_date,
+                new_trackinfo.track_number,
+                new_trackinfo.disc_number,
+                new_trackinfo.album_artist,
+                new_trackinfo.genre,
+                id
+            ],
+        )
+        .unwrap();
+        set_track_artists(&tx, id, &new_trackinfo.artists);
+        prune_orphans(&tx);
+        tx.commit().unwrap();
+
+        // managed files follow their tags to wherever the layout puts them
+        if let Err(e) = self.relocate_track(id) {
+            warn!("Could not move the file of track {} : {:#}", id, e);
+        }
+        debug!("Edited track : {:?}", new_trackinfo);
+    }
+
+    pub fn change_title(&mut self, track_id: u32, new_title: String) -> anyhow::Result<()> {
+        let mut t_info = self.get_track(track_id).unwrap();
+        t_info.name = new_title;
+        self.edit_track(t_info)
+    }
+
+    pub fn change_artists(
+        &mut self,
+        track_id: u32,
+        new_artists: Option<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        let mut t_info = self.get_track(track_id).unwrap();
+        t_info.artists = new_artists;
+        self.edit_track(t_info)
+    }
+
+    pub fn change_album(&mut self, track_id: u32, new_album: Option<String>) -> anyhow::Result<()> {
+        let mut t_info = self.get_track(track_id).unwrap();
+        t_info.album = new_album;
+        self.edit_track(t_info)
+    }
+
+    // appends the track to playlist, does nothing if it's already in there
+    pub fn add_to_playlist(&mut self, track_id: u32, playlist: &str) {
+        add_to_playlist(&self.conn, track_id, playlist);
+    }
+
+    // takes the track out of playlist, it stays in the library
+    pub fn remove_from_playlist(&mut self, track_id: u32, playlist: &str) {
+        self.conn
+            .execute(
+                "DELETE FROM playlist_entries WHERE track_id = ?1
+                 AND playlist_id = (SELECT id FROM playlists WHERE name = ?2)",
+                params![track_id, playlist],
+            )
+            .unwrap();
+    }
+
+    // deletes the playlist, the tracks in it stay in the library
+    pub fn remove_playlist(&mut self, playlist_name: &String) {
+        if playlist_name == "Liked" {
+            return;
+        }
+
+        self.conn
+            .execute("DELETE FROM playlists WHERE name = ?1", [playlist_name])
+            .unwrap();
+        self.conn
+            .execute("DELETE FROM smart_playlists WHERE name = ?1", [playlist_name])
+            .unwrap();
+    }
+
+    pub fn create_playlist(&mut self, playlist_name: String) {
+        if self.is_smart_playlist(&playlist_name) {
+            return;
+        }
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO playlists (name) VALUES (?1)",
+                [playlist_name],
+            )
+            .unwrap();
+    }
+}
+
+// returns the id of the row called name in table, creating it if needed
+fn get_or_insert_named(conn: &Connection, table: &str, name: &str) -> i64 {
+    conn.prepare_cached(&format!("INSERT OR IGNORE INTO {} (name) VALUES (?1)", table))
+        .unwrap()
+        .execute([name])
+        .unwrap();
+    conn.prepare_cached(&format!("SELECT id FROM {} WHERE name = ?1", table))
+        .unwrap()
+        .query_row([name], |r| r.get(0))
+        .unwrap()
+}
+
+// inserts the track without adding it to any playlist, under id if given
+fn insert_track(conn: &Connection, t_info: &TrackInfo, id: Option<u32>) -> u32 {
+    let album_id = t_info
+        .album
+        .as_ref()
+        .map(|a| get_or_insert_named(conn, "albums", a));
+    conn.prepare_cached(
+        "INSERT INTO tracks (id, name, album_id, duration, source_id, isrc, release_date,
+         track_number, disc_number, path, file, extension, codec, album_artist, genre, date_added)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+         COALESCE(?16, datetime('now')))",
+    )
+    .unwrap()
+    .execute(params![
+        id,
+        t_info.name,
+        album_id,
+        t_info.duration,
+        t_info.source_id,
+        t_info.isrc,
+        t_info.release_date,
+        t_info.track_number,
+        t_info.disc_number,
+        t_info.path.as_ref().map(|p| p.to_string_lossy()),
+        t_info.file.as_ref().map(|p| p.to_string_lossy()),
+        t_info.extension,
+        t_info.codec,
+        t_info.album_artist,
+        t_info.genre,
+        t_info.date_added
+    ])
+    .unwrap();
+
+    let id = conn.last_insert_rowid() as u32;
+    set_track_artists(conn, id, &t_info.artists);
+    id
+}
+
+fn set_track_artists(conn: &Connection, track_id: u32, artists: &Option<Vec<String>>) {
+    conn.execute("DELETE FROM track_artists WHERE track_id = ?1", [track_id])
+        .unwrap();
+    for (i, a) in artists.iter().flatten().enumerate() {
+        let artist_id = get_or_insert_named(conn, "artists", a);
+        conn.prepare_cached(
+            "INSERT OR IGNORE INTO track_artists (track_id, artist_id, position) VALUES (?1, ?2, ?3)",
+        )
+        .unwrap()
+        .execute(params![track_id, artist_id, i])
+        .unwrap();
+    }
+}
+
+// appends the track to the end of a playlist, creating the playlist if needed. Smart playlists
+// hold whatever their rules match, so adding to one is skipped instead of making a static twin
+fn add_to_playlist(conn: &Connection, track_id: u32, playlist: &str) {
+    let is_smart = conn
+        .prepare_cached("SELECT 1 FROM smart_playlists WHERE name = ?1")
+        .unwrap()
+        .exists([playlist])
+        .unwrap();
+    if is_smart {
+        warn!("Not adding track {} to {}, it's a smart playlist", track_id, playlist);
+        return;
+    }
+    let playlist_id = get_or_insert_named(conn, "playlists", playlist);
+    conn.prepare_cached(
+        "INSERT OR IGNORE INTO playlist_entries (playlist_id, track_id, position)
+         SELECT ?1, ?2, COALESCE(MAX(position), -1) + 1 FROM playlist_entries WHERE playlist_id = ?1",
+    )
+    .unwrap()
+    .execute(params![playlist_id, track_id])
+    .unwrap();
+}
+
+// the year a release_date like 2001-05-04 starts with
+fn release_year(release_date: &Option<String>) -> Option<u32> {
+    release_date.as_ref()?.get(..4)?.parse().ok()
+}
+
+// rename only works within a filesystem, so fall back to copying
+fn move_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if rename(src, dest).is_ok() {
+        return Ok(());
+    }
+    copy(src, dest)?;
+    remove_file(src)
+}
+
+// removes dir and then its parents as long as they are empty, stopping at root
+fn remove_empty_dirs(root: &Path, dir: &Path) {
+    let mut dir = dir;
+    while dir != root && dir.starts_with(root) {
+        if remove_dir(dir).is_err() {
+            break;
+        }
+        dir = dir.parent().unwrap();
+    }
+}
+
+// drops artists and albums no track refers to anymore
+fn prune_orphans(conn: &Connection) {
+    conn.execute_batch(
+        "DELETE FROM artists WHERE id NOT IN (SELECT artist_id FROM track_artists);
+         DELETE FROM albums WHERE id NOT IN (SELECT album_id FROM tracks WHERE album_id IS NOT NULL);",
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn track(name: &str, artists: &[&str], album: Option<&str>) -> TrackInfo {
+        TrackInfo {
+            id: 0,
+            name: name.to_string(),
+            artists: if artists.is_empty() {
+                None
+            } else {
+                Some(artists.iter().map(|a| a.to_string()).collect())
+            },
+            album: album.map(|a| a.to_string()),
+            duration: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn library_version_changes_on_writes_only() {
+        let mut tdb = TrackDB::open_in_memory();
+        let v = tdb.library_version();
+        tdb.filter_values(&F1State::Artists);
+        assert_eq!(tdb.library_version(), v);
+        tdb.add_track(&track("a", &["x"], None), Some("Liked"));
+        assert_ne!(tdb.library_version(), v);
+    }
+
+    #[test]
+    fn filters_by_each_category() {
+        let mut tdb = TrackDB::open_in_memory();
+        let a = tdb.add_track(&track("a", &["x", "y"], Some("al")), Some("Liked"));
+        let b = tdb.add_track(&track("b", &["y"], None), Some("Mix"));
+        let c = tdb.add_track(&track("c", &[], Some("al")), Some("Liked"));
+
+        assert_eq!(tdb.filter_values(&F1State::Playlists), vec!["Liked", "Mix"]);
+        assert_eq!(tdb.filter_values(&F1State::Artists), vec!["None", "x", "y"]);
+        assert_eq!(tdb.filter_values(&F1State::Albums), vec!["None", "al"]);
+
+        assert_eq!(tdb.filter_tracks(&F1State::All, "All"), vec![a, b, c]);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Liked"), vec![a, c]);
+        assert_eq!(tdb.filter_tracks(&F1State::Artists, "y"), vec![a, b]);
+        assert_eq!(tdb.filter_tracks(&F1State::Artists, "None"), vec![c]);
+        assert_eq!(tdb.filter_tracks(&F1State::Albums, "None"), vec![b]);
+        assert_eq!(tdb.filter_tracks(&F1State::Albums, "missing"), Vec::<u32>::new());
+
+        let t = tdb.get_track(a).unwrap();
+        assert_eq!(t.artists, Some(vec!["x".to_string(), "y".to_string()]));
+        assert_eq!(t.album.as_deref(), Some("al"));
+        assert!(tdb.get_track(c + 1).is_none());
+    }
+
+    #[test]
+    fn albums_are_in_track_order_and_genres_and_years_browse() {
+        let mut tdb = TrackDB::open_in_memory();
+        let numbered = |name: &str, disc: Option<u32>, number: Option<u32>| TrackInfo {
+            disc_number: disc,
+            track_number: number,
+            ..track(name, &[], Some("al"))
+        };
+        let a = tdb.add_track(&numbered("a", Some(2), Some(1)), None);
+        let b = tdb.add_track(&numbered("b", None, None), None);
+        let c = tdb.add_track(&numbered("c", Some(1), Some(2)), None);
+        let d = tdb.add_track(&numbered("d", None, Some(1)), None);
+        assert_eq!(tdb.filter_tracks(&F1State::Albums, "al"), vec![d, c, b, a]);
+
+        let mut t = track("e", &[], None);
+        t.genre = Some("Jazz".to_string());
+        t.release_date = Some("1959-08-17".to_string());
+        let e = tdb.add_track(&t, None);
+        assert_eq!(tdb.filter_values(&F1State::Genres), vec!["Jazz", "None"]);
+        assert_eq!(tdb.filter_values(&F1State::Years), vec!["1959", "None"]);
+        assert_eq!(tdb.filter_tracks(&F1State::Genres, "Jazz"), vec![e]);
+        assert_eq!(tdb.filter_tracks(&F1State::Years, "1959"), vec![e]);
+        assert_eq!(tdb.filter_tracks(&F1State::Years, "None"), vec![a, b, c, d]);
+        assert!(tdb.get_track(e).unwrap().date_added.is_some());
+    }
+
+    #[test]
+    fn removing_last_track_of_an_artist_drops_the_artist() {
+        let mut tdb = TrackDB::open_in_memory();
+        let a = tdb.add_track(&track("a", &["x"], Some("al")), Some("Liked"));
+        tdb.add_track(&track("b", &["y"], None), Some("Liked"));
+
+        tdb.remove_track(a);
+        assert!(!tdb.contains_track(a));
+        assert_eq!(tdb.filter_values(&F1State::Artists), vec!["None", "y"]);
+        assert_eq!(tdb.filter_values(&F1State::Albums), vec!["None"]);
+        // ids are never reused
+        assert!(tdb.add_track(&track("c", &[], None), Some("Liked")) > a + 1);
+    }
+
+    #[test]
+    fn playlists_only_hold_references() {
+        let mut tdb = TrackDB::open_in_memory();
+        tdb.create_playlist("Mix".to_string());
+        let a = tdb.add_track(&track("a", &[], None), Some("Liked"));
+        let b = tdb.add_track(&track("b", &[], None), Some("Mix"));
+        tdb.add_to_playlist(a, "Mix");
+        tdb.add_to_playlist(a, "Mix");
+
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Mix"), vec![b, a]);
+        assert_eq!(tdb.get_track_playlists(a), vec!["Liked", "Mix"]);
+
+        tdb.remove_from_playlist(a, "Liked");
+        assert!(tdb.filter_tracks(&F1State::Playlists, "Liked").is_empty());
+        assert!(tdb.contains_track(a));
+
+        tdb.remove_playlist(&"Mix".to_string());
+        assert_eq!(tdb.filter_values(&F1State::Playlists), vec!["Liked"]);
+        assert_eq!(tdb.filter_tracks(&F1State::All, "All"), vec![a, b]);
+    }
+
+    #[test]
+    fn finds_tracks_by_source_id_then_isrc() {
+        let mut tdb = TrackDB::open_in_memory();
+        let mut t = track("a", &[], None);
+        t.source_id = Some("spotify:track:a".to_string());
+        t.isrc = Some("US1234".to_string());
+        t.track_number = Some(3);
+        let a = tdb.add_track(&t, Some("Liked"));
+
+        assert_eq!(tdb.find_track(Some("spotify:track:a"), None), Some(a));
+        assert_eq!(tdb.find_track(Some("spotify:track:b"), Some("US1234")), Some(a));
+        assert_eq!(tdb.find_track(Some("spotify:track:b"), Some("GB9999")), None);
+        assert_eq!(tdb.find_track(None, None), None);
+        assert_eq!(tdb.get_track(a).unwrap().track_number, Some(3));
+    }
+
+    #[test]
+    fn reorganize_moves_files_to_match_the_template() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        create_dir_all(root.join("old")).unwrap();
+        let layout = |template: &str| Layout {
+            root: root.to_path_buf(),
+            template: template.to_string(),
+        };
+        let mut tdb = TrackDB::open_in_memory().with_layout(layout("{album}/{title}.{ext}"));
+
+        let mut t = track("a/b", &["x"], Some("al"));
+        t.extension = "flac".to_string();
+        t.codec = Some("FLAC".to_string());
+        t.file = Some(PathBuf::from("old/a.flac"));
+        let a = tdb.add_track(&t, None);
+        std::fs::write(root.join("old/a.flac"), b"a").unwrap();
+        // something else already sits where the track should go
+        create_dir_all(root.join("al")).unwrap();
+        std::fs::write(root.join("al/a_b.flac"), b"other").unwrap();
+
+        assert_eq!(tdb.plan_reorganize().len(), 1);
+        let report = tdb.reorganize();
+        assert_eq!(report.moved, 1);
+        assert!(report.failed.is_empty());
+
+        let t = tdb.get_track(a).unwrap();
+        assert_eq!(t.file, Some(PathBuf::from("al/a_b (2).flac")));
+        assert_eq!(t.codec.as_deref(), Some("FLAC"));
+        assert!(root.join("al/a_b (2).flac").exists());
+        assert!(!root.join("old").exists());
+        // the suffixed file is where it belongs, so reorganizing again moves nothing
+        assert!(tdb.plan_reorganize().is_empty());
+        assert_eq!(tdb.reorganize().moved, 0);
+        assert!(!tdb.relocate_track(a).unwrap());
+
+        // not a real flac, so its tags can't be written and the edit is dropped
+        assert!(tdb.change_album(a, Some("new".to_string())).is_err());
+        assert_eq!(tdb.get_track(a).unwrap().album.as_deref(), Some("al"));
+    }
+
+    // a tenth of a second of silence
+    pub fn write_wav(p: &Path) {
+        let (rate, samples) = (8000u32, 800u32);
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + samples * 2).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&rate.to_le_bytes());
+        wav.extend_from_slice(&(rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(samples * 2).to_le_bytes());
+        wav.resize(wav.len() + samples as usize * 2, 0);
+        std::fs::write(p, wav).unwrap();
+    }
+
+    #[test]
+    fn tag_edits_are_written_into_the_file() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let mut tdb = TrackDB::open_in_memory().with_layout(Layout {
+            root: root.to_path_buf(),
+            template: "{album}/{title}.{ext}".to_string(),
+        });
+
+        let src = root.join("in.wav");
+        write_wav(&src);
+        let mut tags = TagEdit {
+            title: "t".to_string(),
+            ..Default::default()
+        };
+        write_tags(&src, &tags).unwrap();
+        let a = tdb
+            .add_track_file(&src, TrackInfo::default(), None, TrackFileMode::Move)
+            .unwrap();
+        assert_eq!(tdb.get_track(a).unwrap().file, Some(PathBuf::from("Unknown Album/t.wav")));
+
+        tags.artists = vec!["x".to_string(), "y".to_string()];
+        tags.album = "al".to_string();
+        tags.album_artist = "aa".to_string();
+        tags.track_number = Some(4);
+        tags.year = Some(1999);
+        tags.genre = "g".to_string();
+        tdb.edit_track_tags(a, &tags).unwrap();
+
+        let t = tdb.get_track(a).unwrap();
+        assert_eq!(t.album.as_deref(), Some("al"));
+        assert_eq!(t.artists, Some(vec!["x".to_string(), "y".to_string()]));
+        assert_eq!(t.release_date.as_deref(), Some("1999"));
+        assert_eq!(t.track_number, Some(4));
+        assert_eq!(read_tags(&root.join("al/t.wav")).unwrap(), tags);
+
+        tdb.change_title(a, "u".to_string()).unwrap();
+        assert_eq!(read_tags(&root.join("al/u.wav")).unwrap().genre, "g");
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use url::Url;
+
+use super::{TrackDB, TrackInfo};
+use crate::{state::filter_state::F1State, utils::get_metadata};
+
+// Playlist files mprs can read and write, picked by the file's extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaylistFileFormat {
+    M3u8,
+    Xspf,
+}
+
+impl PlaylistFileFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFileFormat::M3u8),
+            "xspf" => Some(PlaylistFileFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+// One track as a playlist file lists it. Any of these can be missing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistEntry {
+    // a path or file uri
+    pub location: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    // in seconds
+    pub duration: Option<u32>,
+}
+
+impl PlaylistEntry {
+    fn from_track(t: &TrackInfo, location: String) -> Self {
+        PlaylistEntry {
+            location: Some(location),
+            title: Some(t.name.clone()),
+            artist: t.artists.as_ref().map(|a| a.join(", ")),
+            album: t.album.clone(),
+            duration: Some(t.duration).filter(|d| *d > 0),
+        }
+    }
+
+    // what to call the entry when it can't be resolved
+    fn describe(&self) -> String {
+        match (&self.artist, &self.title, &self.location) {
+            (Some(a), Some(t), _) => format!("{} - {}", a, t),
+            (None, Some(t), _) => t.clone(),
+            (_, None, Some(l)) => l.clone(),
+            _ => "Empty entry".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PlaylistImportReport {
+    pub playlist: String,
+    pub added: u32,
+    pub unresolved: Vec<String>,
+}
+
+pub fn write_m3u8(entries: &[PlaylistEntry]) -> String {
+    let mut s = String::from("#EXTM3U\n");
+    for e in entries {
+        let title = match (&e.artist, &e.title) {
+            (Some(a), Some(t)) => format!("{} - {}", a, t),
+            (None, Some(t)) => t.clone(),
+            _ => String::new(),
+        };
+        // -1 is what players expect for an unknown length
+        let duration = e.duration.map(|d| d as i64).unwrap_or(-1);
+        s.push_str(&format!("#EXTINF:{},{}\n", duration, title.replace('\n', " ")));
+        s.push_str(e.location.as_deref().unwrap_or_default());
+        s.push('\n');
+    }
+    s
+}
+
+// Reads plain and extended m3u. The #EXTINF title is split into artist and title on the
+// first " - ", as most players write it
+pub fn parse_m3u(contents: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut info = PlaylistEntry::default();
+    for line in contents.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = PlaylistEntry::default();
+            let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            info.duration = duration.trim().parse::<u32>().ok();
+            match title.split_once(" - ") {
+                Some((a, t)) => {
+                    info.artist = Some(a.trim().to_string());
+                    info.title = Some(t.trim().to_string());
+                }
+                None if !title.trim().is_empty() => info.title = Some(title.trim().to_string()),
+                None => {}
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        entries.push(PlaylistEntry {
+            location: Some(line.to_string()),
+            ..std::mem::take(&mut info)
+        });
+    }
+    entries
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn write_xspf(title: &str, entries: &[PlaylistEntry]) -> String {
+    let mut s = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    s.push_str(&format!("  <title>{}</title>\n  <trackList>\n", escape_xml(title)));
+    for e in entries {
+        s.push_str("    <track>\n");
+        let fields = [
+            ("location", e.location.clone()),
+            ("title", e.title.clone()),
+            ("creator", e.artist.clone()),
+            ("album", e.album.clone()),
+            // xspf durations are in milliseconds
+            ("duration", e.duration.map(|d| (d as u64 * 1000).to_string())),
+        ];
+        for (tag, value) in fields {
+            if let Some(v) = value {
+                s.push_str(&format!("      <{0}>{1}</{0}>\n", tag, escape_xml(&v)));
+            }
+        }
+        s.push_str("    </track>\n");
+    }
+    s.push_str("  </trackList>\n</playlist>\n");
+    s
+}
+
+pub fn parse_xspf(contents: &str) -> anyhow::Result<Vec<PlaylistEntry>> {
+    let doc = roxmltree::Document::parse(contents).context("Invalid xspf")?;
+    let child_text = |node: roxmltree::Node, tag: &str| {
+        node.children()
+            .find(|c| c.has_tag_name(tag))
+            .and_then(|c| c.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+
+    let entries = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("track"))
+        .map(|n| PlaylistEntry {
+            location: child_text(n, "location"),
+            title: child_text(n, "title"),
+            artist: child_text(n, "creator"),
+            album: child_text(n, "album"),
+            duration: child_text(n, "duration")
+                .and_then(|d| d.parse::<u64>().ok())
+                .map(|ms| (ms / 1000) as u32),
+        })
+        .collect();
+    Ok(entries)
+}
+
+// removes . and .. without touching the filesystem, for paths that may not exist
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// the same file can be reached through symlinks, so existing paths are compared canonicalized
+fn comparable_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| normalize(path))
+}
+
+// path relative to dir, both being absolute
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let path = normalize(path);
+    let dir = normalize(dir);
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut rel = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        rel.push("..");
+    }
+    for c in path.components().skip(common) {
+        rel.push(c);
+    }
+    rel
+}
+
+// where a playlist location points to, relative ones being relative to the playlist's dir
+fn entry_path(location: &str, base_dir: &Path) -> Option<PathBuf> {
+    if location.starts_with("file:") {
+        return Url::parse(location).ok()?.to_file_path().ok();
+    }
+    // other uris, like streams, aren't files
+    if location.contains("://") {
+        return None;
+    }
+    let p = PathBuf::from(location);
+    Some(match p.is_absolute() {
+        true => p,
+        false => base_dir.join(p),
+    })
+}
+
+fn same_text(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+impl TrackDB {
+    // Writes the tracks to dest, in the format its extension asks for. With relative set, m3u8
+    // paths are relative to dest's dir, xspf always uses absolute file uris
+    pub fn export_tracks(
+        &self,
+        tids: &[u32],
+        title: &str,
+        dest: &Path,
+        relative: bool,
+    ) -> anyhow::Result<()> {
+        let format = PlaylistFileFormat::from_path(dest)
+            .ok_or(anyhow!("{:?} is not a .m3u8 or .xspf file", dest))?;
+        let dest = std::path::absolute(dest)?;
+        let dest_dir = dest.parent().unwrap();
+
+        let mut entries = Vec::new();
+        for tid in tids {
+            let t = match self.get_track(*tid) {
+                Some(t) => t,
+                None => continue,
+            };
+            let path = self.file_path(&t);
+            let location = match format {
+                PlaylistFileFormat::M3u8 if relative => {
+                    relative_path(&path, dest_dir).to_string_lossy().to_string()
+                }
+                PlaylistFileFormat::M3u8 => path.to_string_lossy().to_string(),
+                PlaylistFileFormat::Xspf => Url::from_file_path(&path)
+                    .map_err(|_| anyhow!("Can't make a file uri of {:?}", path))?
+                    .to_string(),
+            };
+            entries.push(PlaylistEntry::from_track(&t, location));
+        }
+
+        let contents = match format {
+            PlaylistFileFormat::M3u8 => write_m3u8(&entries),
+            PlaylistFileFormat::Xspf => write_xspf(title, &entries),
+        };
+        write(&dest, contents).with_context(|| format!("Could not write {:?}", dest))
+    }
+
+    // exports the playlist as it is now, smart playlists included
+    pub fn export_playlist(&self, name: &str, dest: &Path, relative: bool) -> anyhow::Result<()> {
+        let tids = self.filter_tracks(&F1State::Playlists, name);
+        self.export_tracks(&tids, name, dest, relative)
+    }
+
+    // Adds the tracks src lists to the playlist called name, or named after the file if None.
+    // Entries resolve to library tracks by path, then by title and artist
+    pub fn import_playlist_file(
+        &mut self,
+        src: &Path,
+        name: Option<&str>,
+    ) -> anyhow::Result<PlaylistImportReport> {
+        let format = PlaylistFileFormat::from_path(src)
+            .ok_or(anyhow!("{:?} is not a .m3u, .m3u8 or .xspf file", src))?;
+        let contents = read_to_string(src).with_context(|| format!("Could not read {:?}", src))?;
+        let entries = match format {
+            PlaylistFileFormat::M3u8 => parse_m3u(&contents),
+            PlaylistFileFormat::Xspf => parse_xspf(&contents)?,
+        };
+
+        let playlist = match name {
+            Some(n) => n.trim().to_string(),
+            None => src.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        };
+        if playlist.is_empty() {
+            bail!("Playlist name can't be empty");
+        }
+        if self.is_smart_playlist(&playlist) {
+            bail!("{} is a smart playlist", playlist);
+        }
+
+        let base_dir = std::path::absolute(src)?.parent().unwrap().to_path_buf();
+        let tracks = self
+            .filter_tracks(&F1State::All, "All")
+            .into_iter()
+            .filter_map(|tid| self.get_track(tid))
+            .collect::<Vec<TrackInfo>>();
+        let by_path = tracks
+            .iter()
+            .map(|t| (comparable_path(&self.file_path(t)), t.id))
+            .collect::<HashMap<PathBuf, u32>>();
+
+        let mut report = PlaylistImportReport {
+            playlist: playlist.clone(),
+            ..Default::default()
+        };
+        self.create_playlist(playlist.clone());
+        for e in entries.iter() {
+            match resolve_entry(e, &base_dir, &by_path, &tracks) {
+                Some(tid) => {
+                    self.add_to_playlist(tid, &playlist);
+                    report.added += 1;
+                }
+                None => report.unresolved.push(e.describe()),
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn resolve_entry(
+    e: &PlaylistEntry,
+    base_dir: &Path,
+    by_path: &HashMap<PathBuf, u32>,
+    tracks: &[TrackInfo],
+) -> Option<u32> {
+    let path = e.location.as_deref().and_then(|l| entry_path(l, base_dir));
+    if let Some(tid) = path.as_ref().and_then(|p| by_path.get(&comparable_path(p))) {
+        return Some(*tid);
+    }
+
+    // a file outside the library can still be matched by its tags
+    let mut e = e.clone();
+    if let Some(meta) = path.as_ref().filter(|p| p.is_file()).and_then(get_metadata) {
+        e.title = e.title.or(Some(meta.title));
+        e.artist = e.artist.or(meta.artists.and_then(|a| a.first().cloned()));
+        e.duration = e.duration.or(Some(meta.duration).filter(|d| *d > 0));
+    }
+
+    let title = e.title.as_deref()?;
+    let artist_matches = |t: &TrackInfo| match (&e.artist, &t.artists) {
+        (None, _) => true,
+        (Some(a), Some(ars)) => same_text(a, &ars.join(", ")) || ars.iter().any(|x| same_text(a, x)),
+        (Some(_), None) => false,
+    };
+    // with several matches, the one closest in length is most likely the same recording
+    tracks
+        .iter()
+        .filter(|t| same_text(title, &t.name) && artist_matches(t))
+        .min_by_key(|t| match e.duration {
+            Some(d) => d.abs_diff(t.duration),
+            None => u32::MAX,
+        })
+        .map(|t| t.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::layout::Layout;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parses_m3u_and_xspf() {
+        let m3u = "\u{feff}#EXTM3U\n#EXTINF:215,Artist A - Song A\n../a.mp3\n\n# note\nb.flac\n";
+        let entries = parse_m3u(m3u);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].artist.as_deref(), Some("Artist A"));
+        assert_eq!(entries[0].title.as_deref(), Some("Song A"));
+        assert_eq!(entries[0].duration, Some(215));
+        assert_eq!(entries[1], PlaylistEntry {
+            location: Some("b.flac".to_string()),
+            ..Default::default()
+        });
+
+        let written = write_xspf("Mix & Match", &entries);
+        assert!(written.contains("<title>Mix &amp; Match</title>"));
+        assert_eq!(parse_xspf(&written).unwrap(), entries);
+        assert!(parse_xspf("<playlist>").is_err());
+    }
+
+    #[test]
+    fn relative_paths_climb_out_of_the_playlist_dir() {
+        assert_eq!(
+            relative_path(Path::new("/m/a/b.mp3"), Path::new("/m/lists")),
+            PathBuf::from("../a/b.mp3")
+        );
+        assert_eq!(
+            relative_path(Path::new("/m/b.mp3"), Path::new("/m")),
+            PathBuf::from("b.mp3")
+        );
+    }
+
+    #[test]
+    fn round_trips_through_the_library() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("lists")).unwrap();
+        let mut tdb = TrackDB::open_in_memory();
+        let track = |name: &str, artist: &str, path: &str| TrackInfo {
+            name: name.to_string(),
+            artists: Some(vec![artist.to_string()]),
+            duration: 100,
+            path: Some(dir.join(path)),
+            extension: "mp3".to_string(),
+            ..Default::default()
+        };
+        let a = tdb.add_track(&track("Song A", "X", "a.mp3"), Some("Mix"));
+        let b = tdb.add_track(&track("Song B", "Y", "b.mp3"), Some("Mix"));
+
+        for (file, relative) in [("lists/mix.m3u8", true), ("mix.xspf", false)] {
+            let dest = dir.join(file);
+            tdb.export_playlist("Mix", &dest, relative).unwrap();
+            let report = tdb.import_playlist_file(&dest, None).unwrap();
+            assert_eq!(report.added, 2);
+            assert!(report.unresolved.is_empty());
+            assert_eq!(tdb.filter_tracks(&F1State::Playlists, "mix"), vec![a, b]);
+            tdb.remove_playlist(&"mix".to_string());
+        }
+        let m3u8 = read_to_string(dir.join("lists/mix.m3u8")).unwrap();
+        assert!(m3u8.contains("\n../a.mp3\n"));
+
+        // files that aren't there anymore still match by their tags
+        let other = dir.join("other.m3u");
+        write(&other, "#EXTINF:99,y - song b\n/gone/b.mp3\n#EXTINF:1,Nobody - Nothing\nc.mp3\n")
+            .unwrap();
+        let report = tdb.import_playlist_file(&other, Some("Other")).unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.unresolved, vec!["Nobody - Nothing"]);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Other"), vec![b]);
+    }
+
+    #[test]
+    fn exports_managed_and_referenced_tracks_where_they_are() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        let mut tdb = TrackDB::open_in_memory().with_layout(Layout {
+            root: dir.join("music"),
+            template: "{title}.{ext}".to_string(),
+        });
+        let track = |name: &str, file: Option<&str>, path: Option<&str>| TrackInfo {
+            name: name.to_string(),
+            duration: 100,
+            file: file.map(PathBuf::from),
+            path: path.map(|p| dir.join(p)),
+            extension: "mp3".to_string(),
+            ..Default::default()
+        };
+        let a = tdb.add_track(&track("Song A", Some("a.mp3"), None), Some("Mix"));
+        let b = tdb.add_track(&track("Song B", None, Some("elsewhere/b.mp3")), Some("Mix"));
+
+        let dest = dir.join("mix.m3u8");
+        tdb.export_playlist("Mix", &dest, false).unwrap();
+        let m3u8 = read_to_string(&dest).unwrap();
+        assert!(m3u8.contains(&format!("\n{}\n", dir.join("music/a.mp3").display())));
+        assert!(m3u8.contains(&format!("\n{}\n", dir.join("elsewhere/b.mp3").display())));
+
+        let report = tdb.import_playlist_file(&dest, None).unwrap();
+        assert_eq!(report.added, 2);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "mix"), vec![a, b]);
+    }
+}
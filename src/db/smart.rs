@@ -0,0 +1,522 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rusqlite::{params, params_from_iter, types::Value, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::TrackDB;
+use crate::state::filter_state::F1State;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RuleField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Year,
+    // in seconds
+    Duration,
+    PlayCount,
+    DateAdded,
+    LastPlayed,
+    Playlist,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RuleOp {
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    LessThan,
+    GreaterThan,
+    InLastDays,
+    NotInLastDays,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SmartOrder {
+    Random,
+    Title,
+    Artist,
+    Album,
+    Year,
+    DateAdded,
+    PlayCount,
+    LastPlayed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub field: RuleField,
+    pub op: RuleOp,
+    pub value: String,
+}
+
+// A playlist whose tracks are whatever in the library matches its rules at the time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub name: String,
+    // all rules have to match, otherwise any of them
+    pub match_all: bool,
+    pub rules: Vec<Rule>,
+    pub order: SmartOrder,
+    pub descending: bool,
+    pub limit: Option<u32>,
+}
+
+impl RuleField {
+    pub const ALL: [RuleField; 11] = [
+        RuleField::Title,
+        RuleField::Artist,
+        RuleField::Album,
+        RuleField::AlbumArtist,
+        RuleField::Genre,
+        RuleField::Year,
+        RuleField::Duration,
+        RuleField::PlayCount,
+        RuleField::DateAdded,
+        RuleField::LastPlayed,
+        RuleField::Playlist,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleField::Title => "Title",
+            RuleField::Artist => "Artist",
+            RuleField::Album => "Album",
+            RuleField::AlbumArtist => "Album artist",
+            RuleField::Genre => "Genre",
+            RuleField::Year => "Year",
+            RuleField::Duration => "Duration (s)",
+            RuleField::PlayCount => "Play count",
+            RuleField::DateAdded => "Date added",
+            RuleField::LastPlayed => "Last played",
+            RuleField::Playlist => "Playlist",
+        }
+    }
+
+    // the operators that make sense for the field, the first one is the default
+    pub fn ops(&self) -> &'static [RuleOp] {
+        match self {
+            RuleField::Title
+            | RuleField::Artist
+            | RuleField::Album
+            | RuleField::AlbumArtist
+            | RuleField::Genre => &[RuleOp::Is, RuleOp::IsNot, RuleOp::Contains, RuleOp::NotContains],
+            RuleField::Year | RuleField::Duration | RuleField::PlayCount => {
+                &[RuleOp::Is, RuleOp::IsNot, RuleOp::LessThan, RuleOp::GreaterThan]
+            }
+            RuleField::DateAdded | RuleField::LastPlayed => &[RuleOp::InLastDays, RuleOp::NotInLastDays],
+            RuleField::Playlist => &[RuleOp::Is, RuleOp::IsNot],
+        }
+    }
+
+    // numeric fields take a number, which is a count of days for dates
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            RuleField::Year
+                | RuleField::Duration
+                | RuleField::PlayCount
+                | RuleField::DateAdded
+                | RuleField::LastPlayed
+        )
+    }
+
+    // the sql for the field's value on a track row, None for fields that need a subquery
+    fn column(&self) -> Option<&'static str> {
+        match self {
+            RuleField::Title => Some("t.name"),
+            RuleField::Album => Some("al.name"),
+            RuleField::AlbumArtist => Some("t.album_artist"),
+            RuleField::Genre => Some("t.genre"),
+            RuleField::Year => Some("CAST(substr(t.release_date, 1, 4) AS INTEGER)"),
+            RuleField::Duration => Some("t.duration"),
+            RuleField::PlayCount => Some("t.play_count"),
+            RuleField::DateAdded => Some("t.date_added"),
+            RuleField::LastPlayed => Some("t.last_played"),
+            RuleField::Artist | RuleField::Playlist => None,
+        }
+    }
+}
+
+impl RuleOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleOp::Is => "is",
+            RuleOp::IsNot => "is not",
+            RuleOp::Contains => "contains",
+            RuleOp::NotContains => "doesn't contain",
+            RuleOp::LessThan => "is less than",
+            RuleOp::GreaterThan => "is greater than",
+            RuleOp::InLastDays => "in the last days",
+            RuleOp::NotInLastDays => "not in the last days",
+        }
+    }
+
+    fn is_negated(&self) -> bool {
+        matches!(self, RuleOp::IsNot | RuleOp::NotContains | RuleOp::NotInLastDays)
+    }
+}
+
+impl SmartOrder {
+    pub const ALL: [SmartOrder; 8] = [
+        SmartOrder::Random,
+        SmartOrder::Title,
+        SmartOrder::Artist,
+        SmartOrder::Album,
+        SmartOrder::Year,
+        SmartOrder::DateAdded,
+        SmartOrder::PlayCount,
+        SmartOrder::LastPlayed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SmartOrder::Random => "Random",
+            SmartOrder::Title => "Title",
+            SmartOrder::Artist => "Artist",
+            SmartOrder::Album => "Album",
+            SmartOrder::Year => "Year",
+            SmartOrder::DateAdded => "Date added",
+            SmartOrder::PlayCount => "Play count",
+            SmartOrder::LastPlayed => "Last played",
+        }
+    }
+
+    // the sort keys, each of them going in direction. Random is shuffled once the ids are in
+    fn sql(&self, direction: &str) -> String {
+        let keys = match self {
+            SmartOrder::Random => vec!["t.id".to_string()],
+            SmartOrder::Title => vec!["t.name COLLATE NOCASE".to_string()],
+            SmartOrder::Artist => vec!["(SELECT ar.name FROM track_artists ta
+                JOIN artists ar ON ar.id = ta.artist_id
+                WHERE ta.track_id = t.id ORDER BY ta.position LIMIT 1) COLLATE NOCASE"
+                .to_string()],
+            SmartOrder::Album => vec![
+                "al.name COLLATE NOCASE".to_string(),
+                "COALESCE(t.disc_number, 1)".to_string(),
+                "t.track_number".to_string(),
+            ],
+            SmartOrder::Year => vec!["substr(t.release_date, 1, 4)".to_string()],
+            SmartOrder::DateAdded => vec!["t.date_added".to_string()],
+            SmartOrder::PlayCount => vec!["t.play_count".to_string()],
+            SmartOrder::LastPlayed => vec!["t.last_played".to_string()],
+        };
+        keys.iter()
+            .map(|k| format!("{} {}", k, direction))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl Rule {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.field.ops().contains(&self.op) {
+            return Err(format!("{} can't use \"{}\"", self.field.name(), self.op.name()));
+        }
+        if self.field.is_numeric() && self.value.trim().parse::<i64>().is_err() {
+            return Err(format!("{} needs a number", self.field.name()));
+        }
+        Ok(())
+    }
+
+    // the condition on a track row t, with its parameter appended to params
+    fn sql(&self, params: &mut Vec<Value>) -> String {
+        let value = self.value.trim();
+        let param = match self.field.is_numeric() {
+            true => Value::Integer(value.parse().unwrap_or(0)),
+            false => Value::Text(value.to_string()),
+        };
+        params.push(param);
+        let p = format!("?{}", params.len());
+
+        // text matches ignore case, missing values count as empty
+        let cmp = |col: &str| match self.op {
+            RuleOp::Is | RuleOp::IsNot if self.field.is_numeric() => format!("{} = {}", col, p),
+            RuleOp::Is | RuleOp::IsNot => format!("COALESCE({}, '') = {} COLLATE NOCASE", col, p),
+            RuleOp::Contains | RuleOp::NotContains => {
+                format!("instr(lower(COALESCE({}, '')), lower({})) > 0", col, p)
+            }
+            RuleOp::LessThan => format!("{} < {}", col, p),
+            RuleOp::GreaterThan => format!("{} > {}", col, p),
+            RuleOp::InLastDays | RuleOp::NotInLastDays => {
+                format!("{} >= datetime('now', '-' || {} || ' days')", col, p)
+            }
+        };
+        let cond = match self.field {
+            RuleField::Artist => format!(
+                "EXISTS (SELECT 1 FROM track_artists ta JOIN artists ar ON ar.id = ta.artist_id
+                 WHERE ta.track_id = t.id AND {})",
+                cmp("ar.name")
+            ),
+            RuleField::Playlist => format!(
+                "EXISTS (SELECT 1 FROM playlist_entries pe JOIN playlists p ON p.id = pe.playlist_id
+                 WHERE pe.track_id = t.id AND {})",
+                cmp("p.name")
+            ),
+            _ => cmp(self.field.column().unwrap()),
+        };
+
+        match self.op.is_negated() {
+            true => format!("NOT COALESCE({}, 0)", cond),
+            false => format!("COALESCE({}, 0)", cond),
+        }
+    }
+}
+
+impl SmartPlaylist {
+    pub fn new(name: String) -> Self {
+        SmartPlaylist {
+            name,
+            match_all: true,
+            rules: Vec::new(),
+            order: SmartOrder::Random,
+            descending: false,
+            limit: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Name can't be empty".to_string());
+        }
+        self.rules.iter().try_for_each(|r| r.validate())
+    }
+
+    // the query for the ids of the matching tracks, along with its parameters
+    fn sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let conds = self
+            .rules
+            .iter()
+            .map(|r| r.sql(&mut params))
+            .collect::<Vec<String>>();
+        let cond = match conds.is_empty() {
+            true => "1".to_string(),
+            false => conds.join(if self.match_all { " AND " } else { " OR " }),
+        };
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        // the limit of a random order applies to the shuffled ids
+        let limit = match self.order {
+            SmartOrder::Random => None,
+            _ => self.limit,
+        };
+
+        let sql = format!(
+            "SELECT t.id FROM tracks t LEFT JOIN albums al ON al.id = t.album_id
+             WHERE {} ORDER BY {}, t.id LIMIT {}",
+            cond,
+            self.order.sql(direction),
+            limit.map(|l| l as i64).unwrap_or(-1)
+        );
+        (sql, params)
+    }
+}
+
+impl TrackDB {
+    pub fn smart_playlist_names(&self) -> Vec<String> {
+        self.query_strings("SELECT name FROM smart_playlists ORDER BY name", [])
+    }
+
+    pub fn is_smart_playlist(&self, name: &str) -> bool {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT 1 FROM smart_playlists WHERE name = ?1")
+            .unwrap();
+        stmt.exists([name]).unwrap()
+    }
+
+    pub fn get_smart_playlist(&self, name: &str) -> Option<SmartPlaylist> {
+        let definition: String = self
+            .conn
+            .query_row(
+                "SELECT definition FROM smart_playlists WHERE name = ?1",
+                [name],
+                |r| r.get(0),
+            )
+            .optional()
+            .unwrap()?;
+        serde_json::from_str(&definition).ok()
+    }
+
+    // saves sp, replacing the smart playlist called prev_name if given
+    pub fn save_smart_playlist(
+        &mut self,
+        sp: &SmartPlaylist,
+        prev_name: Option<&str>,
+    ) -> Result<(), String> {
+        sp.validate()?;
+        let renamed = prev_name != Some(sp.name.as_str());
+        if renamed && self.filter_values(&F1State::Playlists).contains(&sp.name) {
+            return Err(format!("There already is a playlist called {}", sp.name));
+        }
+
+        let tx = self.conn.transaction().unwrap();
+        if let Some(prev) = prev_name {
+            tx.execute("DELETE FROM smart_playlists WHERE name = ?1", [prev])
+                .unwrap();
+        }
+        tx.execute(
+            "INSERT INTO smart_playlists (name, definition) VALUES (?1, ?2)",
+            params![sp.name, serde_json::to_string(sp).unwrap()],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        Ok(())
+    }
+
+    // tracks matching the smart playlist called name right now
+    pub fn smart_playlist_tracks(&self, name: &str) -> Vec<u32> {
+        let sp = match self.get_smart_playlist(name) {
+            Some(sp) => sp,
+            None => return Vec::new(),
+        };
+        let (sql, params) = sp.sql();
+
+        let mut stmt = self.conn.prepare(&sql).unwrap();
+        let rows = stmt.query_map(params_from_iter(params), |r| r.get(0)).unwrap();
+        let mut ids = rows.map(|r| r.unwrap()).collect::<Vec<u32>>();
+        if sp.order == SmartOrder::Random {
+            // the same seed gives the same order, until the playlists are reshuffled
+            ids.shuffle(&mut StdRng::seed_from_u64(self.shuffle_seed));
+            ids.truncate(sp.limit.map_or(usize::MAX, |l| l as usize));
+        }
+        ids
+    }
+
+    // gives smart playlists sorted by random a new order
+    pub fn reshuffle_smart_playlists(&mut self) {
+        self.shuffle_seed = rand::random();
+    }
+
+    pub fn record_play(&mut self, track_id: u32) {
+        self.conn
+            .execute(
+                "UPDATE tracks SET play_count = play_count + 1, last_played = datetime('now')
+                 WHERE id = ?1",
+                [track_id],
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{layout::Layout, tests::write_wav, TrackInfo},
+        utils::{write_tags, TagEdit},
+    };
+    use std::fs::create_dir_all;
+    use tempfile::TempDir;
+
+    fn rule(field: RuleField, op: RuleOp, value: &str) -> Rule {
+        Rule {
+            field,
+            op,
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn evaluates_rules_against_the_library() {
+        let mut tdb = TrackDB::open_in_memory();
+        let add = |tdb: &mut TrackDB, name: &str, genre: Option<&str>, artist: &str| {
+            let t = TrackInfo {
+                name: name.to_string(),
+                artists: Some(vec![artist.to_string()]),
+                genre: genre.map(|g| g.to_string()),
+                ..Default::default()
+            };
+            tdb.add_track(&t, Some("Mix"))
+        };
+        let a = add(&mut tdb, "a", Some("Jazz"), "Miles");
+        let b = add(&mut tdb, "b", Some("jazz fusion"), "Herbie");
+        let c = add(&mut tdb, "c", None, "Miles");
+        for _ in 0..3 {
+            tdb.record_play(b);
+        }
+
+        let mut sp = SmartPlaylist::new("Smart".to_string());
+        sp.rules = vec![
+            rule(RuleField::Genre, RuleOp::Contains, "JAZZ"),
+            rule(RuleField::DateAdded, RuleOp::InLastDays, "30"),
+            rule(RuleField::PlayCount, RuleOp::LessThan, "3"),
+        ];
+        sp.order = SmartOrder::Title;
+        tdb.save_smart_playlist(&sp, None).unwrap();
+        assert_eq!(tdb.smart_playlist_tracks("Smart"), vec![a]);
+
+        sp.match_all = false;
+        sp.rules = vec![
+            rule(RuleField::Genre, RuleOp::IsNot, "jazz"),
+            rule(RuleField::Artist, RuleOp::Is, "herbie"),
+        ];
+        sp.descending = true;
+        tdb.save_smart_playlist(&sp, Some("Smart")).unwrap();
+        assert_eq!(tdb.smart_playlist_tracks("Smart"), vec![c, b]);
+
+        sp.rules = vec![rule(RuleField::Playlist, RuleOp::Is, "Mix")];
+        sp.order = SmartOrder::Random;
+        sp.limit = Some(2);
+        tdb.save_smart_playlist(&sp, Some("Smart")).unwrap();
+        let shuffled = tdb.smart_playlist_tracks("Smart");
+        assert_eq!(shuffled.len(), 2);
+        assert_eq!(tdb.smart_playlist_tracks("Smart"), shuffled);
+        // a reshuffle gives a new order rather than a rotation of the same one
+        sp.limit = None;
+        tdb.save_smart_playlist(&sp, Some("Smart")).unwrap();
+        let ids = (0..20).map(|i| add(&mut tdb, &i.to_string(), None, "x")).collect::<Vec<_>>();
+        let orders = (0..5)
+            .map(|_| {
+                tdb.reshuffle_smart_playlists();
+                tdb.smart_playlist_tracks("Smart")
+            })
+            .collect::<Vec<Vec<u32>>>();
+        let rotations = |o: &Vec<u32>| {
+            (0..o.len()).map(|i| [&o[i..], &o[..i]].concat()).collect::<Vec<Vec<u32>>>()
+        };
+        assert!(orders[1..].iter().any(|o| !rotations(&orders[0]).contains(o)));
+        let mut sorted = orders[0].clone();
+        sorted.sort();
+        assert_eq!(sorted, [vec![a, b, c], ids].concat());
+
+        assert!(tdb.filter_values(&F1State::Playlists).contains(&"Smart".to_string()));
+        assert!(tdb.save_smart_playlist(&SmartPlaylist::new("Mix".to_string()), None).is_err());
+        sp.rules = vec![rule(RuleField::Year, RuleOp::Is, "nineties")];
+        assert!(tdb.save_smart_playlist(&sp, Some("Smart")).is_err());
+    }
+
+    #[test]
+    fn downloads_into_a_smart_playlist_make_no_static_one() {
+        let tmp = TempDir::new().unwrap();
+        let download_dir = tmp.path().join("job-1");
+        create_dir_all(&download_dir).unwrap();
+        let file = download_dir.join("a.wav");
+        write_wav(&file);
+        let tags = TagEdit {
+            title: "a".to_string(),
+            ..Default::default()
+        };
+        write_tags(&file, &tags).unwrap();
+
+        let mut tdb = TrackDB::open_in_memory().with_layout(Layout {
+            root: tmp.path().join("music"),
+            template: "{title}.{ext}".to_string(),
+        });
+        tdb.save_smart_playlist(&SmartPlaylist::new("Smart".to_string()), None)
+            .unwrap();
+        let id = tdb.add_downloaded_track(&download_dir, TrackInfo::default(), "Smart");
+        assert!(id.is_some());
+
+        let n_static: u32 = tdb
+            .conn
+            .query_row("SELECT count(*) FROM playlists WHERE name = 'Smart'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(n_static, 0);
+        assert_eq!(tdb.smart_playlist_tracks("Smart"), vec![id.unwrap()]);
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::{params, OptionalExtension, Row};
+
+use super::{get_or_insert_named, TrackDB, TrackInfo};
+
+// Where a synced playlist comes from and how its last sync went
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistSource {
+    pub url: String,
+    // utc
+    pub last_synced: Option<String>,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncReport {
+    // library tracks newly added to the playlist
+    pub linked: u32,
+    // indices into the remote tracks of the ones that need downloading
+    pub to_download: Vec<usize>,
+    // entries the remote no longer has, flagged or removed
+    pub gone: u32,
+    pub removed: bool,
+}
+
+impl SyncReport {
+    pub fn summary(&self) -> String {
+        let gone = match self.removed {
+            true => "removed",
+            false => "no longer in the remote",
+        };
+        format!(
+            "{} added from the library, {} to download, {} {}",
+            self.linked,
+            self.to_download.len(),
+            self.gone,
+            gone
+        )
+    }
+}
+
+fn source_from_row(r: &Row) -> rusqlite::Result<PlaylistSource> {
+    Ok(PlaylistSource {
+        url: r.get(0)?,
+        last_synced: r.get(1)?,
+        summary: r.get(2)?,
+    })
+}
+
+impl TrackDB {
+    // remembers the url the playlist syncs from, None stops syncing it
+    pub fn set_playlist_source(&mut self, playlist: &str, url: Option<&str>) {
+        let playlist_id = get_or_insert_named(&self.conn, "playlists", playlist);
+        self.conn
+            .execute(
+                "UPDATE playlists SET source_url = ?2, last_synced = NULL, sync_summary = NULL
+                 WHERE id = ?1",
+                params![playlist_id, url],
+            )
+            .unwrap();
+        if url.is_none() {
+            self.conn
+                .execute(
+                    "DELETE FROM remote_playlist_tracks WHERE playlist_id = ?1",
+                    [playlist_id],
+                )
+                .unwrap();
+            self.conn
+                .execute(
+                    "UPDATE playlist_entries SET stale = 0 WHERE playlist_id = ?1",
+                    [playlist_id],
+                )
+                .unwrap();
+        }
+    }
+
+    pub fn playlist_source(&self, playlist: &str) -> Option<PlaylistSource> {
+        self.conn
+            .query_row(
+                "SELECT source_url, last_synced, sync_summary FROM playlists
+                 WHERE name = ?1 AND source_url IS NOT NULL",
+                [playlist],
+                source_from_row,
+            )
+            .optional()
+            .unwrap()
+    }
+
+    // the source of every synced playlist, by name
+    pub fn playlist_sources(&self) -> HashMap<String, PlaylistSource> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT source_url, last_synced, sync_summary, name FROM playlists
+                 WHERE source_url IS NOT NULL",
+            )
+            .unwrap();
+        stmt.query_map([], |r| Ok((r.get(3)?, source_from_row(r)?)))
+            .unwrap()
+            .map(|s| s.unwrap())
+            .collect()
+    }
+
+    // names of the playlists with a source, and not synced in the last max_age_hours if given
+    pub fn synced_playlists(&self, max_age_hours: Option<u32>) -> Vec<String> {
+        let max_age = max_age_hours.map(|h| format!("-{} hours", h));
+        self.query_strings(
+            "SELECT name FROM playlists WHERE source_url IS NOT NULL
+             AND (?1 IS NULL OR last_synced IS NULL OR last_synced < datetime('now', ?1))
+             ORDER BY name",
+            [max_age],
+        )
+    }
+
+    // entries of the playlist its remote no longer has
+    pub fn stale_tracks(&self, playlist: &str) -> Vec<u32> {
+        self.query_ids(
+            "SELECT pe.track_id FROM playlist_entries pe
+             JOIN playlists p ON p.id = pe.playlist_id
+             WHERE p.name = ?1 AND pe.stale = 1",
+            [playlist],
+        )
+    }
+
+    // Brings the playlist in line with the remote tracks, which only need source_id and isrc.
+    // Tracks the library has are added right away, the rest are left for the caller to
+    // download. Entries the remote doesn't have are flagged stale, or removed
+    pub fn sync_playlist(
+        &mut self,
+        playlist: &str,
+        remote: &[TrackInfo],
+        remove_missing: bool,
+    ) -> SyncReport {
+        let playlist_id = get_or_insert_named(&self.conn, "playlists", playlist);
+        let before = self.query_ids(
+            "SELECT track_id FROM playlist_entries WHERE playlist_id = ?1",
+            [playlist_id],
+        );
+
+        let tx = self.conn.transaction().unwrap();
+        tx.execute(
+            "DELETE FROM remote_playlist_tracks WHERE playlist_id = ?1",
+            [playlist_id],
+        )
+        .unwrap();
+        for (i, t) in remote.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO remote_playlist_tracks (playlist_id, position, source_id, isrc)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![playlist_id, i, t.source_id, t.isrc],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+
+        let mut report = SyncReport {
+            removed: remove_missing,
+            ..Default::default()
+        };
+        let mut present = HashSet::new();
+        for (i, t) in remote.iter().enumerate() {
+            match self.find_track(t.source_id.as_deref(), t.isrc.as_deref()) {
+                Some(tid) => {
+                    if present.insert(tid) && !before.contains(&tid) {
+                        self.add_to_playlist(tid, playlist);
+                        report.linked += 1;
+                    }
+                }
+                None => report.to_download.push(i),
+            }
+        }
+
+        for tid in before.iter().filter(|t| !present.contains(t)) {
+            if remove_missing {
+                self.remove_from_playlist(*tid, playlist);
+            } else {
+                self.conn
+                    .execute(
+                        "UPDATE playlist_entries SET stale = 1
+                         WHERE playlist_id = ?1 AND track_id = ?2",
+                        params![playlist_id, tid],
+                    )
+                    .unwrap();
+            }
+            report.gone += 1;
+        }
+        // tracks the remote got back aren't stale anymore
+        for tid in present.iter() {
+            self.conn
+                .execute(
+                    "UPDATE playlist_entries SET stale = 0 WHERE playlist_id = ?1 AND track_id = ?2",
+                    params![playlist_id, tid],
+                )
+                .unwrap();
+        }
+
+        self.apply_remote_order(playlist);
+        self.conn
+            .execute(
+                "UPDATE playlists SET last_synced = datetime('now'), sync_summary = ?2
+                 WHERE id = ?1",
+                params![playlist_id, report.summary()],
+            )
+            .unwrap();
+        report
+    }
+
+    // Orders the playlist like its remote, with entries the remote doesn't have kept after
+    // those in the order they were in. Does nothing for playlists that aren't synced
+    pub fn apply_remote_order(&mut self, playlist: &str) {
+        self.conn
+            .execute(
+                "UPDATE playlist_entries SET position = COALESCE(
+                    (SELECT MIN(r.position) FROM remote_playlist_tracks r
+                     JOIN tracks t ON t.source_id = r.source_id OR t.isrc = r.isrc
+                     WHERE r.playlist_id = playlist_entries.playlist_id
+                     AND t.id = playlist_entries.track_id),
+                    (SELECT COUNT(*) FROM remote_playlist_tracks r
+                     WHERE r.playlist_id = playlist_entries.playlist_id) + position)
+                 WHERE playlist_id = (SELECT id FROM playlists WHERE name = ?1)
+                 AND EXISTS (SELECT 1 FROM remote_playlist_tracks r
+                             WHERE r.playlist_id = playlist_entries.playlist_id)",
+                [playlist],
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::filter_state::F1State;
+
+    fn remote(source: &str) -> TrackInfo {
+        TrackInfo {
+            name: source.to_string(),
+            source_id: Some(format!("spotify:track:{}", source)),
+            extension: "mp3".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn syncs_with_the_remote_order() {
+        let mut tdb = TrackDB::open_in_memory();
+        let add = |tdb: &mut TrackDB, s: &str, p: Option<&str>| {
+            let t = TrackInfo {
+                path: Some(format!("/music/{}.mp3", s).into()),
+                ..remote(s)
+            };
+            tdb.add_track(&t, p)
+        };
+        let a = add(&mut tdb, "a", Some("Mix"));
+        let b = add(&mut tdb, "b", Some("Mix"));
+        let c = add(&mut tdb, "c", None);
+        tdb.set_playlist_source("Mix", Some("https://open.spotify.com/playlist/x"));
+
+        // the remote dropped a, moved c in front of b and has d, which isn't downloaded
+        let r = [remote("c"), remote("d"), remote("b")];
+        let report = tdb.sync_playlist("Mix", &r, false);
+        assert_eq!(
+            report,
+            SyncReport {
+                linked: 1,
+                to_download: vec![1],
+                gone: 1,
+                removed: false
+            }
+        );
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Mix"), vec![c, b, a]);
+        assert_eq!(tdb.stale_tracks("Mix"), vec![a]);
+
+        // d arriving later goes where the remote has it
+        let d = add(&mut tdb, "d", Some("Mix"));
+        tdb.apply_remote_order("Mix");
+        assert_eq!(
+            tdb.filter_tracks(&F1State::Playlists, "Mix"),
+            vec![c, d, b, a]
+        );
+
+        let source = tdb.playlist_source("Mix").unwrap();
+        assert!(source.last_synced.is_some());
+        assert_eq!(source.summary, Some(report.summary()));
+        assert_eq!(tdb.playlist_sources().get("Mix"), Some(&source));
+        assert_eq!(tdb.synced_playlists(None), vec!["Mix"]);
+        assert!(tdb.synced_playlists(Some(1)).is_empty());
+
+        let report = tdb.sync_playlist("Mix", &r, true);
+        assert_eq!(report.gone, 1);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Mix"), vec![c, d, b]);
+        assert!(tdb.stale_tracks("Mix").is_empty());
+
+        tdb.set_playlist_source("Mix", None);
+        assert_eq!(tdb.playlist_source("Mix"), None);
+    }
+}
@@ -0,0 +1,693 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{create_dir_all, remove_dir_all},
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    config::Config,
+    db::{
+        downloads::{DownloadJob, DownloadStatus},
+        TrackDB,
+    },
+    downloader::Downloader,
+    spotdl::SearchResult,
+    utils::get_newtracks_dir,
+    DOWNLOAD_OUTPUT_LINES, DOWNLOAD_OUTPUT_WAIT_MS,
+};
+
+// what's been read of a download's output so far
+#[derive(Default)]
+struct DownloadOutput {
+    lines: VecDeque<String>,
+    progress: Option<f32>,
+}
+
+impl DownloadOutput {
+    fn push(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if let Some(p) = parse_progress(line) {
+            self.progress = Some(p);
+        }
+        self.lines.push_back(line.to_string());
+        if self.lines.len() > DOWNLOAD_OUTPUT_LINES {
+            self.lines.pop_front();
+        }
+    }
+}
+
+struct RunningDownload {
+    // the name of the downloader
+    downloader: String,
+    child: Child,
+    output: Arc<Mutex<DownloadOutput>>,
+    // one per pipe, done once the downloader and whatever it started closed it
+    readers: Vec<JoinHandle<()>>,
+    exited_at: Option<Instant>,
+}
+
+impl RunningDownload {
+    // what's been read so far, the readers are never waited for
+    fn output(&self) -> String {
+        let output = self.output.lock().unwrap();
+        output
+            .lines
+            .iter()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// Runs the download jobs, at most max_concurrent_downloads at once, with the downloaders in
+// their fallback order. Jobs are kept in the db so they survive a restart
+pub struct DownloadManager {
+    pub jobs: Vec<DownloadJob>,
+    running: HashMap<u32, RunningDownload>,
+    downloaders: Vec<Box<dyn Downloader>>,
+    // the jobs' staging dirs go in here
+    staging_root: PathBuf,
+}
+
+impl DownloadManager {
+    // jobs that were running when the app last closed are queued again
+    pub fn load(trackdb: &mut TrackDB, downloaders: Vec<Box<dyn Downloader>>) -> Self {
+        let mut jobs = trackdb.downloads();
+        for job in jobs.iter_mut() {
+            if let DownloadStatus::Downloading | DownloadStatus::Tagging = job.status {
+                job.status = DownloadStatus::Queued;
+                trackdb.update_download(job);
+            }
+        }
+        DownloadManager {
+            jobs,
+            running: HashMap::new(),
+            downloaders,
+            staging_root: get_newtracks_dir(),
+        }
+    }
+
+    pub fn with_staging_root(mut self, root: PathBuf) -> Self {
+        self.staging_root = root;
+        self
+    }
+
+    pub fn enqueue(&mut self, trackdb: &mut TrackDB, result: SearchResult, playlist: &str) {
+        self.jobs.push(trackdb.add_download(result, playlist));
+    }
+
+    // queued or running
+    pub fn n_active(&self) -> usize {
+        self.jobs.iter().filter(|j| !j.status.is_finished()).count()
+    }
+
+    pub fn n_failed(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.status == DownloadStatus::Failed)
+            .count()
+    }
+
+    // Follows the running downloads, adding the finished ones to the library, and starts queued
+    // ones while there's room. Returns the jobs that finished
+    pub fn update(&mut self, trackdb: &mut TrackDB, config: &Config) -> Vec<DownloadJob> {
+        let mut finished = Vec::new();
+        for job in self.jobs.iter_mut() {
+            let running = match self.running.get_mut(&job.id) {
+                Some(r) => r,
+                None => continue,
+            };
+
+            if let Some(p) = running.output.lock().unwrap().progress {
+                job.progress = p;
+                // the downloaders embed the tags once the file is there
+                if p >= 100.0 && job.status == DownloadStatus::Downloading {
+                    job.status = DownloadStatus::Tagging;
+                    trackdb.update_download(job);
+                }
+            }
+
+            let exit = match running.child.try_wait().unwrap() {
+                Some(e) => e,
+                None => continue,
+            };
+            // The tools the downloader started can keep its pipes open after it exits, so the
+            // last of its output only gets a moment to come in
+            let exited_at = *running.exited_at.get_or_insert_with(Instant::now);
+            let drained = running.readers.iter().all(|r| r.is_finished());
+            if !drained && exited_at.elapsed() < Duration::from_millis(DOWNLOAD_OUTPUT_WAIT_MS) {
+                continue;
+            }
+            let running = self.running.remove(&job.id).unwrap();
+            let downloader = running.downloader.clone();
+            let output = running.output();
+            let download_dir = job.staging_dir(&self.staging_root);
+
+            let added = match exit.success() {
+                true => trackdb.add_downloaded_track(
+                    &download_dir,
+                    job.result.to_track_info(),
+                    &job.playlist,
+                ),
+                false => {
+                    let _ = remove_dir_all(&download_dir);
+                    None
+                }
+            };
+            match added {
+                Some(_) => {
+                    job.status = DownloadStatus::Done;
+                    job.progress = 100.0;
+                    job.error = None;
+                }
+                None => {
+                    warn!("Download of {} failed :\n{}", job.result.get_url(), output);
+                    let error = match exit.success() {
+                        true => format!("{} left no usable file\n{}", downloader, output),
+                        false => format!("{} exited with {}\n{}", downloader, exit, output),
+                    };
+                    let n_downloaders = self
+                        .downloaders
+                        .iter()
+                        .filter(|d| d.can_download(&job.result))
+                        .count();
+                    fail_attempt(job, n_downloaders, error, config.download_retries);
+                }
+            }
+            trackdb.update_download(job);
+            if job.status.is_finished() {
+                finished.push(job.clone());
+            }
+        }
+
+        for i in 0..self.jobs.len() {
+            if self.running.len() >= config.max_concurrent_downloads.max(1) {
+                break;
+            }
+            if self.jobs[i].status != DownloadStatus::Queued {
+                continue;
+            }
+            // a job for a track that's already downloading waits for it, to be linked after
+            let source_id = self.jobs[i].result.get_source_id();
+            let downloading = self
+                .jobs
+                .iter()
+                .any(|j| j.result.get_source_id() == source_id && self.running.contains_key(&j.id));
+            if downloading {
+                continue;
+            }
+
+            let job = &mut self.jobs[i];
+            let started =
+                start_download(job, trackdb, config, &self.downloaders, &self.staging_root);
+            match started {
+                Some(r) => {
+                    self.running.insert(job.id, r);
+                }
+                None if job.status.is_finished() => finished.push(job.clone()),
+                None => {}
+            }
+            trackdb.update_download(job);
+        }
+        finished
+    }
+
+    // queues a failed or cancelled job again
+    pub fn retry(&mut self, trackdb: &mut TrackDB, id: u32) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            if let DownloadStatus::Failed | DownloadStatus::Cancelled = job.status {
+                job.status = DownloadStatus::Queued;
+                job.progress = 0.0;
+                job.error = None;
+                job.attempts = 0;
+                job.fallback = 0;
+                trackdb.update_download(job);
+            }
+        }
+    }
+
+    pub fn cancel(&mut self, trackdb: &mut TrackDB, id: u32) {
+        let job = match self.jobs.iter_mut().find(|j| j.id == id) {
+            Some(j) if !j.status.is_finished() => j,
+            _ => return,
+        };
+        if let Some(mut r) = self.running.remove(&id) {
+            if let Err(e) = r.child.kill() {
+                warn!(
+                    "Could not stop the download of {} : {}",
+                    job.result.get_url(),
+                    e
+                );
+            }
+            // The tools the downloader started, like ffmpeg, can keep its pipes open after it's
+            // killed, so it's reaped off the ui thread and its readers are left to finish alone
+            let url = job.result.get_url();
+            thread::spawn(move || {
+                if let Err(e) = r.child.wait() {
+                    warn!("Could not reap the cancelled download of {} : {}", url, e);
+                }
+            });
+            let _ = remove_dir_all(job.staging_dir(&self.staging_root));
+        }
+        debug!("Cancelled the download of {}", job.result.get_url());
+        job.status = DownloadStatus::Cancelled;
+        trackdb.update_download(job);
+    }
+
+    // forgets a job that isn't running
+    pub fn remove(&mut self, trackdb: &mut TrackDB, id: u32) {
+        if self.running.contains_key(&id) {
+            return;
+        }
+        self.jobs.retain(|j| j.id != id);
+        trackdb.remove_download(id);
+    }
+
+    pub fn clear_done(&mut self, trackdb: &mut TrackDB) {
+        let done = self
+            .jobs
+            .iter()
+            .filter(|j| j.status == DownloadStatus::Done)
+            .map(|j| j.id)
+            .collect::<Vec<u32>>();
+        for id in done {
+            self.remove(trackdb, id);
+        }
+    }
+
+    // Stops the running downloads, which stay marked as running so they're queued again on the
+    // next start
+    pub fn shutdown(&mut self) {
+        for (_, mut r) in self.running.drain() {
+            let _ = r.child.kill();
+            let _ = r.child.wait();
+        }
+    }
+}
+
+// Moves the job on to the next downloader that can download its track, or back to the first one
+// once they all failed, which counts as an attempt
+fn fail_attempt(job: &mut DownloadJob, n_downloaders: usize, error: String, retries: u32) {
+    job.error = Some(error);
+    job.fallback += 1;
+    if job.fallback >= n_downloaders {
+        job.fallback = 0;
+        job.attempts += 1;
+    }
+    job.status = match job.attempts > retries {
+        true => DownloadStatus::Failed,
+        false => DownloadStatus::Queued,
+    };
+}
+
+// Starts downloading the job's track, or only adds it to the playlist if another job or playlist
+// got it into the library meanwhile. None if there's nothing to follow, the job's either done,
+// failed or queued for the next downloader then
+fn start_download(
+    job: &mut DownloadJob,
+    trackdb: &mut TrackDB,
+    config: &Config,
+    downloaders: &[Box<dyn Downloader>],
+    staging_root: &Path,
+) -> Option<RunningDownload> {
+    let existing = trackdb.find_track(
+        Some(&job.result.get_source_id()),
+        job.result.isrc.as_deref(),
+    );
+    if let Some(tid) = existing {
+        trackdb.add_to_playlist(tid, &job.playlist);
+        job.status = DownloadStatus::Done;
+        job.progress = 100.0;
+        return None;
+    }
+
+    let candidates = downloaders
+        .iter()
+        .filter(|d| d.can_download(&job.result))
+        .collect::<Vec<&Box<dyn Downloader>>>();
+    let downloader = match candidates.get(job.fallback % candidates.len().max(1)) {
+        Some(d) => d,
+        None => {
+            job.status = DownloadStatus::Failed;
+            job.error = Some("None of the configured downloaders can download this".to_string());
+            return None;
+        }
+    };
+
+    debug!(
+        "Downloading {} with {}",
+        job.result.get_url(),
+        downloader.name()
+    );
+    job.progress = 0.0;
+    // whatever an interrupted attempt left there would be picked up otherwise
+    let staging_dir = job.staging_dir(staging_root);
+    let _ = remove_dir_all(&staging_dir);
+    let spawned = create_dir_all(&staging_dir).and_then(|_| {
+        downloader
+            .command(&job.result, config.download_format, &staging_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    });
+    let mut child = match spawned {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Could not run {} : {}", downloader.name(), e);
+            let error = format!("Could not run {} : {}", downloader.name(), e);
+            fail_attempt(job, candidates.len(), error, config.download_retries);
+            return None;
+        }
+    };
+    job.status = DownloadStatus::Downloading;
+
+    let output = Arc::new(Mutex::new(DownloadOutput::default()));
+    let readers = vec![
+        follow_output(child.stdout.take().unwrap(), Arc::clone(&output)),
+        follow_output(child.stderr.take().unwrap(), Arc::clone(&output)),
+    ];
+    Some(RunningDownload {
+        downloader: downloader.name().to_string(),
+        child,
+        output,
+        readers,
+        exited_at: None,
+    })
+}
+
+// reads the pipe into output until the downloader closes it
+fn follow_output<R: Read + Send + 'static>(
+    mut pipe: R,
+    output: Arc<Mutex<DownloadOutput>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut pending = String::new();
+        let mut chunk = [0; 1024];
+        loop {
+            let n = match pipe.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            pending.push_str(&String::from_utf8_lossy(&chunk[..n]));
+            // progress bars redraw their line with \r
+            while let Some(i) = pending.find(['\r', '\n']) {
+                let line = pending.drain(..=i).collect::<String>();
+                output.lock().unwrap().push(&line);
+            }
+        }
+        output.lock().unwrap().push(&pending);
+    })
+}
+
+// the last percentage on the line, or 100 once spotdl says the track is downloaded
+fn parse_progress(line: &str) -> Option<f32> {
+    if line.starts_with("Downloaded ") {
+        return Some(100.0);
+    }
+    line.split(|c: char| c.is_whitespace() || c == '|')
+        .filter_map(|w| w.strip_suffix('%'))
+        .filter_map(|n| n.parse::<f32>().ok())
+        .next_back()
+        .map(|p| p.clamp(0.0, 100.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::DownloadFormat,
+        db::{layout::Layout, tests::write_wav, TrackInfo},
+        state::filter_state::F1State,
+        utils::{write_tags, TagEdit},
+    };
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    // copies the fixture into the download dir like a real download would, fails for the tracks
+    // named missing and hangs for those named hang. Those named linger are copied, but leave a
+    // child behind on the pipes
+    struct FakeDownloader {
+        name: &'static str,
+        fixture: PathBuf,
+    }
+
+    impl Downloader for FakeDownloader {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn can_download(&self, _result: &SearchResult) -> bool {
+            true
+        }
+
+        fn command(
+            &self,
+            result: &SearchResult,
+            _format: DownloadFormat,
+            download_dir: &Path,
+        ) -> Command {
+            if result.name == "hang" {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg("sleep 5 & sleep 5");
+                return cmd;
+            }
+            if result.name == "linger" {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg("cp \"$0\" \"$1\"; sleep 5 &").arg(&self.fixture);
+                cmd.arg(download_dir);
+                return cmd;
+            }
+            let mut cmd = Command::new("cp");
+            match result.name.as_str() {
+                "missing" => cmd.arg(self.fixture.with_extension("missing")),
+                _ => cmd.arg(&self.fixture),
+            };
+            cmd.arg(download_dir);
+            cmd
+        }
+    }
+
+    fn result(id: &str) -> SearchResult {
+        SearchResult {
+            name: id.to_string(),
+            artists: vec!["Artist".to_string()],
+            album: "Album".to_string(),
+            id: id.to_string(),
+            duration: 180,
+            isrc: None,
+            release_date: None,
+            track_number: 1,
+            disc_number: 1,
+            album_artist: None,
+            url: None,
+        }
+    }
+
+    #[test]
+    fn parses_progress() {
+        assert_eq!(
+            parse_progress("Song - Artist  45%|████      | 1.2M/2.7M"),
+            Some(45.0)
+        );
+        assert_eq!(parse_progress("Total 3/10 complete 12.5%"), Some(12.5));
+        assert_eq!(
+            parse_progress("Downloaded \"Song\": https://music.youtube.com/watch?v=x"),
+            Some(100.0)
+        );
+        assert_eq!(
+            parse_progress("Processing query: https://open.spotify.com/track/x"),
+            None
+        );
+    }
+
+    #[test]
+    fn jobs_survive_a_restart() {
+        let mut tdb = TrackDB::open_in_memory();
+        let mut dm = DownloadManager::load(&mut tdb, Vec::new());
+        dm.enqueue(&mut tdb, result("a"), "Mix");
+        dm.enqueue(&mut tdb, result("b"), "Other");
+        dm.jobs[0].status = DownloadStatus::Downloading;
+        tdb.update_download(&dm.jobs[0]);
+        dm.jobs[1].status = DownloadStatus::Failed;
+        dm.jobs[1].error = Some("no results".to_string());
+        dm.jobs[1].attempts = 2;
+        tdb.update_download(&dm.jobs[1]);
+
+        let mut dm = DownloadManager::load(&mut tdb, Vec::new());
+        assert_eq!(dm.jobs.len(), 2);
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Queued);
+        assert_eq!(dm.jobs[0].playlist, "Mix");
+        assert_eq!(dm.jobs[1].status, DownloadStatus::Failed);
+        assert_eq!(dm.jobs[1].error.as_deref(), Some("no results"));
+        assert_eq!(dm.n_active(), 1);
+
+        let id = dm.jobs[1].id;
+        dm.retry(&mut tdb, id);
+        dm.cancel(&mut tdb, dm.jobs[0].id);
+        let dm = DownloadManager::load(&mut tdb, Vec::new());
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Cancelled);
+        assert_eq!(dm.jobs[1].status, DownloadStatus::Queued);
+        assert_eq!(dm.jobs[1].attempts, 0);
+    }
+
+    #[test]
+    fn cancelling_does_not_wait_for_the_downloader() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let downloader = FakeDownloader {
+            name: "fake",
+            fixture: root.join("fixture.wav"),
+        };
+        let mut tdb = TrackDB::open_in_memory();
+        let mut dm = DownloadManager::load(&mut tdb, vec![Box::new(downloader)])
+            .with_staging_root(root.to_path_buf());
+        dm.enqueue(&mut tdb, result("hang"), "Mix");
+        dm.update(&mut tdb, &Config::default());
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Downloading);
+        // lets it start the child
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let start = std::time::Instant::now();
+        dm.cancel(&mut tdb, dm.jobs[0].id);
+        assert!(start.elapsed().as_secs() < 2);
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Cancelled);
+    }
+
+    #[test]
+    fn finishing_does_not_wait_for_what_the_downloader_left_behind() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let fixture = root.join("fixture.wav");
+        write_wav(&fixture);
+        let tags = TagEdit {
+            title: "Song".to_string(),
+            ..Default::default()
+        };
+        write_tags(&fixture, &tags).unwrap();
+
+        let mut tdb = TrackDB::open_in_memory().with_layout(Layout {
+            root: root.join("music"),
+            template: "{title}.{ext}".to_string(),
+        });
+        let downloader = FakeDownloader {
+            name: "fake",
+            fixture,
+        };
+        let mut dm = DownloadManager::load(&mut tdb, vec![Box::new(downloader)])
+            .with_staging_root(root.join("newtracks"));
+        dm.enqueue(&mut tdb, result("linger"), "Mix");
+
+        let start = std::time::Instant::now();
+        for _ in 0..100 {
+            let update = std::time::Instant::now();
+            dm.update(&mut tdb, &Config::default());
+            assert!(update.elapsed().as_millis() < 500);
+            if dm.n_active() == 0 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Done);
+        assert!(start.elapsed().as_secs() < 4);
+    }
+
+    #[test]
+    fn jobs_keep_their_playlist_and_staging_dir() {
+        let mut tdb = TrackDB::open_in_memory();
+        let mut dm = DownloadManager::load(&mut tdb, Vec::new());
+        dm.enqueue(&mut tdb, result("a"), "Mix");
+        dm.enqueue(&mut tdb, result("a"), "Other");
+        let root = Path::new("/tmp");
+        assert_ne!(dm.jobs[0].staging_dir(root), dm.jobs[1].staging_dir(root));
+
+        // once one of them got the track into the library the other only links it
+        let t = TrackInfo {
+            path: Some("/music/a.mp3".into()),
+            ..result("a").to_track_info()
+        };
+        let tid = tdb.add_track(&t, Some("Mix"));
+        let finished = dm.update(&mut tdb, &Config::default());
+        assert_eq!(finished.len(), 2);
+        assert!(finished.iter().all(|j| j.status == DownloadStatus::Done));
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Other"), vec![tid]);
+        assert_eq!(tdb.filter_tracks(&F1State::Playlists, "Mix"), vec![tid]);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_downloader() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let fixture = root.join("fixture.wav");
+        write_wav(&fixture);
+        let tags = TagEdit {
+            title: "Song".to_string(),
+            ..Default::default()
+        };
+        write_tags(&fixture, &tags).unwrap();
+
+        let mut tdb = TrackDB::open_in_memory().with_layout(Layout {
+            root: root.join("music"),
+            template: "{title}.{ext}".to_string(),
+        });
+        let downloaders: Vec<Box<dyn Downloader>> = vec![
+            Box::new(FakeDownloader {
+                name: "broken",
+                fixture: root.join("nothing.wav"),
+            }),
+            Box::new(FakeDownloader {
+                name: "fake",
+                fixture,
+            }),
+        ];
+        let mut dm =
+            DownloadManager::load(&mut tdb, downloaders).with_staging_root(root.join("newtracks"));
+        dm.enqueue(&mut tdb, result("a"), "Mix");
+        dm.enqueue(&mut tdb, result("missing"), "Mix");
+
+        let config = Config {
+            download_retries: 0,
+            ..Default::default()
+        };
+        let mut finished = Vec::new();
+        for _ in 0..100 {
+            finished.extend(dm.update(&mut tdb, &config));
+            if dm.n_active() == 0 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        assert_eq!(finished.len(), 2);
+        assert_eq!(dm.jobs[0].status, DownloadStatus::Done);
+        let tid = tdb.filter_tracks(&F1State::Playlists, "Mix");
+        assert_eq!(tid.len(), 1);
+        let t = tdb.get_track(tid[0]).unwrap();
+        assert_eq!(t.name, "Song");
+        assert_eq!(t.source_id.as_deref(), Some("spotify:track:a"));
+        assert!(root.join("music/Song.wav").exists());
+
+        // both downloaders failed it, with no retries left
+        assert_eq!(dm.jobs[1].status, DownloadStatus::Failed);
+        assert_eq!(dm.jobs[1].attempts, 1);
+        assert!(dm.jobs[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("fake exited with"));
+        assert!(!root
+            .join("newtracks")
+            .join(format!("job-{}", dm.jobs[1].id))
+            .exists());
+    }
+}
@@ -0,0 +1,198 @@
+use std::{path::Path, process::Command};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, DownloadFormat},
+    spotdl::SearchResult,
+};
+
+// A tool that downloads tracks. It runs as its own process, which the download manager follows
+pub trait Downloader: Send {
+    fn name(&self) -> &str;
+    fn can_download(&self, result: &SearchResult) -> bool;
+    // the command that downloads the track into download_dir, which only the track should end
+    // up in
+    fn command(
+        &self,
+        result: &SearchResult,
+        format: DownloadFormat,
+        download_dir: &Path,
+    ) -> Command;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DownloaderKind {
+    Spotdl,
+    YtDlp,
+    // the user's download_command
+    Command,
+}
+
+// the downloaders in the configured fallback order
+pub fn downloaders_from_config(config: &Config) -> Vec<Box<dyn Downloader>> {
+    let mut downloaders: Vec<Box<dyn Downloader>> = Vec::new();
+    for kind in config.downloaders.iter() {
+        match (kind, &config.download_command) {
+            (DownloaderKind::Spotdl, _) => downloaders.push(Box::new(Spotdl)),
+            (DownloaderKind::YtDlp, _) => downloaders.push(Box::new(YtDlp)),
+            (DownloaderKind::Command, Some(template)) => {
+                downloaders.push(Box::new(CustomCommand {
+                    template: template.clone(),
+                }))
+            }
+            (DownloaderKind::Command, None) => {
+                warn!("The command downloader is configured but download_command isn't set")
+            }
+        }
+    }
+    downloaders
+}
+
+// artists and title, for the downloaders that search for the track
+fn search_query(result: &SearchResult) -> String {
+    match result.artists.is_empty() {
+        true => result.name.clone(),
+        false => format!("{} - {}", result.artists.join(" "), result.name),
+    }
+}
+
+// only spotify tracks, found by search or linked
+pub struct Spotdl;
+
+impl Downloader for Spotdl {
+    fn name(&self) -> &str {
+        "spotdl"
+    }
+
+    fn can_download(&self, result: &SearchResult) -> bool {
+        match &result.url {
+            Some(url) => url.contains("open.spotify.com/track/"),
+            None => true,
+        }
+    }
+
+    fn command(
+        &self,
+        result: &SearchResult,
+        format: DownloadFormat,
+        download_dir: &Path,
+    ) -> Command {
+        let mut cmd = Command::new("spotdl");
+        cmd.arg(result.get_url())
+            .arg("--format")
+            .arg(format.as_str())
+            .arg("--output")
+            .arg(download_dir);
+        cmd
+    }
+}
+
+// Links to anything yt-dlp supports, like youtube, soundcloud or bandcamp. Spotify tracks are
+// searched for on youtube
+pub struct YtDlp;
+
+impl Downloader for YtDlp {
+    fn name(&self) -> &str {
+        "yt-dlp"
+    }
+
+    fn can_download(&self, _result: &SearchResult) -> bool {
+        true
+    }
+
+    fn command(
+        &self,
+        result: &SearchResult,
+        format: DownloadFormat,
+        download_dir: &Path,
+    ) -> Command {
+        let input = match &result.url {
+            Some(url) => url.clone(),
+            None => format!("ytsearch1:{}", search_query(result)),
+        };
+        let format = match format {
+            DownloadFormat::Ogg => "vorbis",
+            f => f.as_str(),
+        };
+        let mut cmd = Command::new("yt-dlp");
+        cmd.arg("--extract-audio")
+            .arg("--audio-format")
+            .arg(format)
+            .arg("--embed-metadata")
+            .arg("--no-playlist")
+            .arg("--output")
+            .arg(download_dir.join("%(title)s.%(ext)s"))
+            .arg(input);
+        cmd
+    }
+}
+
+// Runs download_command from the config, with {url}, {query}, {dir} and {format} in its
+// arguments filled in. {query} is the track's artists and title
+pub struct CustomCommand {
+    pub template: String,
+}
+
+impl Downloader for CustomCommand {
+    fn name(&self) -> &str {
+        "command"
+    }
+
+    fn can_download(&self, _result: &SearchResult) -> bool {
+        true
+    }
+
+    fn command(
+        &self,
+        result: &SearchResult,
+        format: DownloadFormat,
+        download_dir: &Path,
+    ) -> Command {
+        // split before filling in, so values with spaces stay one argument
+        let mut args = self.template.split_whitespace().map(|a| {
+            a.replace("{url}", &result.get_url())
+                .replace("{query}", &search_query(result))
+                .replace("{dir}", &download_dir.to_string_lossy())
+                .replace("{format}", format.as_str())
+        });
+        let mut cmd = Command::new(args.next().unwrap_or_default());
+        cmd.args(args);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_command_fills_in_the_template() {
+        let d = CustomCommand {
+            template: "dl {url} -o {dir}/out.{format} --search {query}".to_string(),
+        };
+        let r = SearchResult::from_url("https://soundcloud.com/a/b");
+        let cmd = d.command(&r, DownloadFormat::Flac, Path::new("/tmp/job 1"));
+        assert_eq!(cmd.get_program(), "dl");
+        let args = cmd.get_args().collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            [
+                "https://soundcloud.com/a/b",
+                "-o",
+                "/tmp/job 1/out.flac",
+                "--search",
+                "https://soundcloud.com/a/b"
+            ]
+        );
+
+        assert!(!Spotdl.can_download(&r));
+        let cmd = YtDlp.command(&r, DownloadFormat::Ogg, Path::new("/d"));
+        assert_eq!(
+            cmd.get_args().last(),
+            Some("https://soundcloud.com/a/b".as_ref())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::Context;
+use log::debug;
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    db::{TrackDB, TrackFileMode, TrackInfo},
+    AUDIO_EXTENSIONS,
+};
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: u32,
+    // audio files that were not added, with the reason why
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+fn is_audio_file(p: &Path) -> bool {
+    match p.extension().and_then(|e| e.to_str()) {
+        Some(ext) => AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// collects the audio files under dir, skipping hidden files and directories
+fn find_audio_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+
+    for p in entries {
+        if p.file_name().unwrap().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if p.is_dir() {
+            find_audio_files(&p, files)?;
+        } else if is_audio_file(&p) {
+            files.push(p);
+        }
+    }
+    Ok(())
+}
+
+// Adds every audio file under root to the library, either copied into the music dir or
+// referenced where it is. Files that can't be added are reported instead of failing the import
+pub fn import_folder(
+    tdb: &mut TrackDB,
+    root: &Path,
+    mode: TrackFileMode,
+    playlist: Option<&str>,
+) -> anyhow::Result<ImportReport> {
+    let mut files = Vec::new();
+    find_audio_files(root, &mut files).with_context(|| format!("could not read {:?}", root))?;
+    debug!("Importing {} files from {:?}", files.len(), root);
+
+    let mut report = ImportReport::default();
+    for p in files {
+        if mode == TrackFileMode::Reference {
+            let existing = p.canonicalize().ok().and_then(|c| tdb.find_track_by_path(&c));
+            if existing.is_some() {
+                report.skipped.push((p, "already in the library".to_string()));
+                continue;
+            }
+        }
+
+        match tdb.add_track_file(&p, TrackInfo::default(), playlist, mode) {
+            Ok(_) => report.imported += 1,
+            Err(e) => report.skipped.push((p, format!("{:#}", e))),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn reports_untagged_files_as_skipped() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        create_dir_all(root.join("album")).unwrap();
+        write(root.join("album").join("untagged.mp3"), b"not really audio").unwrap();
+        write(root.join("cover.jpg"), b"").unwrap();
+        write(root.join(".hidden.mp3"), b"").unwrap();
+
+        let mut tdb = TrackDB::open_in_memory();
+        let report = import_folder(&mut tdb, root, TrackFileMode::Reference, None).unwrap();
+
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].0.ends_with("album/untagged.mp3"));
+        assert_eq!(tdb.track_count(), 0);
+    }
+
+    #[test]
+    fn missing_folder_is_an_error() {
+        let mut tdb = TrackDB::open_in_memory();
+        let res = import_folder(&mut tdb, Path::new("/nonexistent/mprs"), TrackFileMode::Copy, None);
+        assert!(res.is_err());
+    }
+}
@@ -1,8 +1,15 @@
 #![allow(dead_code, unused_mut, unused_variables, unused_imports)]
 
+mod cli;
+mod config;
 mod db;
-mod mpv;
+mod download_manager;
+mod downloader;
+mod import;
+mod playback;
+mod player;
 mod spotdl;
+mod spotify_link;
 mod track_queue;
 mod utils;
 mod ui;
@@ -10,9 +17,10 @@ mod state;
 
 use db::TrackDB;
 use egui_extras::install_image_loaders;
-use mpv::player_handler;
-use spotdl::{download_track, init_spotify_client, search_tracks};
-use state::{filter_state::F1State, state::{AppState, AppStateWrapper}, tracklist_state::TracklistItem};
+use playback::player_handler;
+use player::init_player;
+use spotdl::init_spotify_client;
+use state::{filter_state::F1State, session::restore_session, state::{AppState, AppStateWrapper}, tracklist_state::TracklistItem};
 use eframe::{egui::{self, FontData, FontFamily}, NativeOptions};
 use tokio::runtime::Runtime;
 use std::{
@@ -22,20 +30,38 @@ use stopwatch::Stopwatch;
 use track_queue::TrackQueue;
 use utils::init_functions;
 
+// in the home dir, unless music_dir is set in the config
 const MUSIC_DIR: &str = "mprs-tracks";
-const MPV_STATUS_IPC_FILENAME: &str = ".mpv_status.txt";
-const MPV_LUASCRIPT_FILENAME: &str = "status_update.lua";
+// where files go under the music dir, the way they were named before this was configurable
+const DEFAULT_PATH_TEMPLATE: &str = "{title} - {id}.{ext}";
+const MPV_IPC_SOCKET_FILENAME: &str = ".mpv_socket";
 
 const TRACKLIST_ROW_HEIGHT: f32 = 30.0;
 const F2_PANEL_ROW_HEIGHT: f32 = 20.0;
+const TRANSPORT_BUTTON_SIZE: f32 = 32.0;
 
 const PLAYER_HANDLER_TIMEOUT_MS: u64 = 20;
+const MPV_IPC_CONNECT_TIMEOUT_MS: u64 = 2000;
+const PLAYER_PRELOAD_COUNT: usize = 3;
+const DEFAULT_PLAYER_BACKEND: &str = "mpv";
 const UI_SLEEP_DURATION_MS: u64 = 1000;
 const PREV_SAME_TRACK_TIMEOUT_S: u64 = 3;
+const SEEK_SHORT_S: f64 = 5.0;
+const SEEK_LONG_S: f64 = 30.0;
 const KEY_INPUT_POLL_TIMEOUT_MS: u64 = 250;
 const NOTIFICATION_TIMEOUT_S: u64 = 3;
 
-const NUM_SEARCH_RESULTS: u32 = 10;
+// per page, more are loaded on demand
+const NUM_SEARCH_RESULTS: u32 = 20;
+const DEFAULT_CONCURRENT_DOWNLOADS: usize = 3;
+// automatic retries of a failed download, before it's left for the user to retry
+const DEFAULT_DOWNLOAD_RETRIES: u32 = 1;
+// lines of the downloader's output kept per download, shown when it fails
+const DOWNLOAD_OUTPUT_LINES: usize = 20;
+// how long the rest of a download's output is waited for once the downloader exited
+const DOWNLOAD_OUTPUT_WAIT_MS: u64 = 1000;
+// file types picked up when importing a folder
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "aac", "wav"];
 
 // Do now
 // TODO: Clean up code and create functions for common operations (accessing curr track list)
@@ -52,33 +78,36 @@ const NUM_SEARCH_RESULTS: u32 = 10;
 // TODO: Unicode font rendering
 // TODO: Find out how to change color scheme to black/dark blue palete and not beige ish
 // TODO: Add feature to edit playlist name
-// TODO: Create playlist with spotify link to pull all tracks (or add import playlist button to add
-// multiple tracks to existing playlist)
 // TODO: Add option to normalize track volume
-// TODO: Set a consistent audio format for song downloads with spotdl
-// TODO: Store spotify trackid with each track to identify when a track is already downloaded, so
-// adding the same track to multiple playlists does not do multiple downloads
 // TODO: Add playlist stats in bottom bar (total number of tracks + total duration)
 // TODO: Maybe add a script to download deps (spotdl/python which is needed for spotdl)
 // TODO: VISUALIZER
 // TODO: Make queue and visualizer windows optional (keybind toggle?)
-// TODO: Add a way to see the current queue and played tracks history
 // TODO: Make panel sizing a fraction of window sizing for more consistency (or allow resizing)
 // TODO: Figure out how to package into standalone binary
-// TODO: Add pausing with space bar
 // TODO: Add search for tracks in current playlist and search for artists/albums in f2 panel
 // TODO: Change dock icon
 // TODO: Maybe add album covers to tracklist? (May affect performance poorly)
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if cli::run_cli(&args) {
+        return;
+    }
+
     init_functions();
     let mut app_inner = AppState::default();
+    let backend = std::env::var("MPRS_PLAYER").unwrap_or(DEFAULT_PLAYER_BACKEND.to_string());
+    app_inner.player = init_player(&backend);
+    restore_session(&mut app_inner);
 
     let mut app = AppStateWrapper { app_state: Arc::new(Mutex::new(app_inner))};
     
     let app_state_rc = Arc::clone(&app.app_state);
     
     let rt = Runtime::new().unwrap();
+    // lets the ui spawn its searches on the runtime
+    let _rt_guard = rt.enter();
     let player_update_handle = rt.spawn(async move {
         player_handler(app_state_rc, PLAYER_HANDLER_TIMEOUT_MS).await;
     });
@@ -0,0 +1,272 @@
+use log::debug;
+use crate::player::PlayerEvent;
+use crate::utils::get_album_cover;
+use crate::{PLAYER_PRELOAD_COUNT, PREV_SAME_TRACK_TIMEOUT_S};
+use crate::state::state::AppState;
+use std::thread::sleep;
+use std::time::Duration;
+use std::{
+    mem::drop,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+// replaces whatever the player is playing with the current track of the queue
+pub fn play_track(app_state: &mut AppState) {
+    let tp_opt = app_state.get_curr_track_path();
+    let track_path: PathBuf;
+    if let Some(tp) = tp_opt {
+        track_path = tp
+    } else {
+        // reached the end of the queue without repeating
+        app_state.curr_trackinfo = None;
+        app_state.player.set_paused(true);
+        return;
+    }
+
+    debug!(
+        "Now playing : {}",
+        track_path.file_name().unwrap().to_str().unwrap()
+    );
+
+    app_state.player.load(&track_path);
+    if let Some(tid) = app_state.trackqueue.get_curr_track() {
+        app_state.trackdb.record_play(tid);
+    }
+    on_track_changed(app_state);
+    sync_upcoming(app_state, true);
+}
+
+// loads the current track of the queue without playing it, ready to resume from secs
+pub fn resume_track_at(app_state: &mut AppState, secs: f64) {
+    let track_path = match app_state.get_curr_track_path() {
+        Some(tp) => tp,
+        None => return,
+    };
+
+    app_state.player.load_paused_at(&track_path, secs);
+    on_track_changed(app_state);
+    sync_upcoming(app_state, true);
+}
+
+// refreshes everything derived from the current track once the player starts a new one
+fn on_track_changed(app_state: &mut AppState) {
+    app_state.curr_trackinfo = app_state.get_curr_track_info();
+
+    let v = get_album_cover(&app_state.get_curr_track_path().unwrap());
+    app_state.curr_albumcover = Some(Arc::from(v));
+
+    if let Some(ctx) = &app_state.ctx {
+        ctx.request_repaint();
+    }
+}
+
+// hands the upcoming tracks in the queue to the player so it can transition to them gaplessly.
+// With force, they are handed over even if they look unchanged (ie: after a load or an
+// automatic transition)
+pub fn sync_upcoming(app_state: &mut AppState, force: bool) {
+    let upcoming = app_state.trackqueue.upcoming_tracks(PLAYER_PRELOAD_COUNT);
+    if !force && upcoming == app_state.preloaded_tracks {
+        return;
+    }
+
+    let paths = upcoming
+        .iter()
+        .filter_map(|tid| app_state.trackdb.get_track(*tid))
+        .map(|tinfo| tinfo.get_file_path())
+        .collect::<Vec<PathBuf>>();
+    app_state.player.set_upcoming(&paths);
+    app_state.preloaded_tracks = upcoming;
+}
+
+pub fn next_track(app_state: &mut AppState) {
+    app_state.trackqueue.next_track();
+    play_track(app_state);
+}
+
+pub fn prev_track(app_state: &mut AppState) {
+    app_state.trackqueue.prev_track();
+    play_track(app_state);
+}
+
+// restarts the current track if it has been playing for a while, otherwise goes to the previous one
+pub fn restart_or_prev_track(app_state: &mut AppState) {
+    if app_state.player.position() as u64 > PREV_SAME_TRACK_TIMEOUT_S {
+        play_track(app_state);
+    } else {
+        prev_track(app_state);
+    }
+}
+
+pub fn toggle_pause(app_state: &mut AppState) {
+    if app_state.trackqueue.get_curr_track().is_none() {
+        return;
+    }
+    let paused = app_state.player.paused();
+    app_state.player.set_paused(!paused);
+}
+
+// applies the volume and mute settings in app_state to the player
+pub fn update_volume(app_state: &mut AppState) {
+    let volume = if app_state.muted { 0.0 } else { app_state.volume };
+    app_state.player.set_volume(volume);
+}
+
+// duration of the current track, preferring what the player reports over the db
+pub fn curr_track_duration(app_state: &AppState) -> f64 {
+    let d = app_state.player.duration();
+    if d > 0.0 {
+        return d;
+    }
+    match &app_state.curr_trackinfo {
+        Some(t) => t.duration as f64,
+        None => 0.0,
+    }
+}
+
+pub fn seek_to(app_state: &mut AppState, secs: f64) {
+    if app_state.trackqueue.get_curr_track().is_none() {
+        return;
+    }
+    let secs = secs.clamp(0.0, curr_track_duration(app_state));
+    app_state.player.seek(secs);
+}
+
+pub fn seek_relative(app_state: &mut AppState, delta_secs: f64) {
+    let pos = app_state.player.position();
+    seek_to(app_state, pos + delta_secs);
+}
+
+// the player moved on to the first preloaded track by itself, so advance the queue to match
+fn on_advanced_to_next(app_state: &mut AppState) {
+    let expected = app_state.preloaded_tracks.first().copied();
+    app_state.trackqueue.auto_advance();
+
+    match expected {
+        Some(tid) if expected == app_state.trackqueue.get_curr_track() => {
+            app_state.trackdb.record_play(tid);
+            on_track_changed(app_state);
+            sync_upcoming(app_state, true);
+        }
+        _ => play_track(app_state),
+    }
+}
+
+pub fn handle_player_event(app_state: &mut AppState, event: PlayerEvent) {
+    match event {
+        PlayerEvent::Paused(_) => {
+            if let Some(ctx) = &app_state.ctx {
+                ctx.request_repaint();
+            }
+        }
+        PlayerEvent::AdvancedToNext => on_advanced_to_next(app_state),
+        PlayerEvent::EndOfTrack => {
+            app_state.trackqueue.auto_advance();
+            play_track(app_state);
+        }
+        PlayerEvent::NextRequested => next_track(app_state),
+        PlayerEvent::PrevRequested => restart_or_prev_track(app_state),
+    }
+}
+
+pub async fn player_handler(app_state: Arc<Mutex<AppState>>, sleep_millis: u64) {
+    loop {
+        let mut app_state_rc = app_state.lock().unwrap();
+
+        let events = app_state_rc.player.poll_events();
+        for event in events {
+            handle_player_event(&mut app_state_rc, event);
+        }
+
+        sync_upcoming(&mut app_state_rc, false);
+
+        drop(app_state_rc);
+        sleep(Duration::from_millis(sleep_millis));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        db::{TrackDB, TrackInfo},
+        player::null::{NullPlayer, NullPlayerState},
+        track_queue::RepeatMode,
+    };
+
+    // n tracks queued in order with repeat off, played through a null player
+    fn state_with_tracks(n: u32) -> (AppState, Arc<Mutex<NullPlayerState>>, Vec<u32>) {
+        let config = Config {
+            spotify_client_id: Some("id".to_string()),
+            spotify_client_secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut app_state = AppState::new(config, TrackDB::open_in_memory());
+        let player = NullPlayer::new();
+        let handle = player.handle();
+        app_state.player = Box::new(player);
+
+        let ids = (0..n)
+            .map(|i| {
+                let t = TrackInfo {
+                    name: i.to_string(),
+                    path: Some(path(i)),
+                    ..Default::default()
+                };
+                app_state.trackdb.add_track(&t, None)
+            })
+            .collect::<Vec<u32>>();
+        app_state.trackqueue.reg_queue = ids.clone();
+        app_state.trackqueue.set_repeat_mode(RepeatMode::Off);
+        (app_state, handle, ids)
+    }
+
+    fn path(i: u32) -> PathBuf {
+        PathBuf::from(format!("/music/{}.mp3", i))
+    }
+
+    // what player_handler does with the player's events
+    fn handle_events(app_state: &mut AppState) {
+        for event in app_state.player.poll_events() {
+            handle_player_event(app_state, event);
+        }
+    }
+
+    #[test]
+    fn plays_through_the_queue() {
+        let (mut app_state, player, ids) = state_with_tracks(3);
+        next_track(&mut app_state);
+        assert_eq!(player.lock().unwrap().loaded, Some(path(0)));
+        assert_eq!(player.lock().unwrap().upcoming, vec![path(1), path(2)]);
+        assert!(!app_state.player.paused());
+        assert_eq!(app_state.curr_trackinfo.as_ref().unwrap().id, ids[0]);
+
+        // the player moved on by itself, the queue follows without reloading
+        player.lock().unwrap().finish_track();
+        handle_events(&mut app_state);
+        assert_eq!(app_state.trackqueue.get_curr_track(), Some(ids[1]));
+        assert_eq!(player.lock().unwrap().loaded, Some(path(1)));
+        assert_eq!(player.lock().unwrap().upcoming, vec![path(2)]);
+        assert_eq!(app_state.curr_trackinfo.as_ref().unwrap().id, ids[1]);
+
+        next_track(&mut app_state);
+        assert_eq!(player.lock().unwrap().loaded, Some(path(2)));
+        prev_track(&mut app_state);
+        assert_eq!(player.lock().unwrap().loaded, Some(path(1)));
+
+        // a while into the track, going back restarts it
+        next_track(&mut app_state);
+        player.lock().unwrap().position = 30.0;
+        restart_or_prev_track(&mut app_state);
+        assert_eq!(app_state.trackqueue.get_curr_track(), Some(ids[2]));
+        assert_eq!(player.lock().unwrap().position, 0.0);
+
+        // the end of the queue stops playback with repeat off
+        player.lock().unwrap().finish_track();
+        handle_events(&mut app_state);
+        assert_eq!(app_state.trackqueue.get_curr_track(), None);
+        assert!(app_state.curr_trackinfo.is_none());
+        assert!(app_state.player.paused());
+    }
+}
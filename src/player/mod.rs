@@ -0,0 +1,71 @@
+pub mod mpv;
+pub mod mpv_ipc;
+#[cfg(feature = "native-audio")]
+pub mod native;
+pub mod null;
+
+use log::warn;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    Paused(bool),
+    // the player moved on to the first of the upcoming tracks by itself
+    AdvancedToNext,
+    // the loaded track finished and there was nothing upcoming to move on to
+    EndOfTrack,
+    // next/prev requested from outside mprs, ie: media keys
+    NextRequested,
+    PrevRequested,
+}
+
+// An audio backend. Tracks are loaded one at a time, with the tracks that follow handed over
+// through set_upcoming so backends that can play gaplessly get the chance to prepare them.
+pub trait Player: Send {
+    // stops whatever is playing and starts playing path from the beginning
+    fn load(&mut self, path: &Path);
+
+    // loads path without starting playback, ready to be resumed from secs
+    fn load_paused_at(&mut self, path: &Path, secs: f64) {
+        self.load(path);
+        self.set_paused(true);
+        self.seek(secs);
+    }
+
+    // replaces the tracks to be played after the current one
+    fn set_upcoming(&mut self, paths: &[PathBuf]);
+
+    fn set_paused(&mut self, paused: bool);
+    fn paused(&self) -> bool;
+
+    // seeks to an absolute position in seconds within the current track
+    fn seek(&mut self, secs: f64);
+
+    // volume in percent, 100 being unchanged
+    fn set_volume(&mut self, volume: f64);
+
+    // playback position in seconds within the current track
+    fn position(&self) -> f64;
+
+    // duration in seconds of the current track, 0 if unknown
+    fn duration(&self) -> f64;
+
+    // returns all events since the last call without blocking
+    fn poll_events(&mut self) -> Vec<PlayerEvent>;
+
+    fn shutdown(&mut self);
+}
+
+// creates the backend with the given name, falling back to mpv for unknown names
+pub fn init_player(name: &str) -> Box<dyn Player> {
+    match name {
+        "null" => Box::new(null::NullPlayer::new()),
+        #[cfg(feature = "native-audio")]
+        "native" => Box::new(native::NativePlayer::new()),
+        "mpv" => Box::new(mpv::MpvPlayer::new()),
+        _ => {
+            warn!("Unknown or unavailable player backend {:?}, using mpv", name);
+            Box::new(mpv::MpvPlayer::new())
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use log::{debug, warn};
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::Duration,
+};
+
+use super::{
+    mpv_ipc::{
+        LoadFileMode, MpvCommand, MpvEvent, MpvIpcClient, SeekMode, NEXT_MESSAGE, PREV_MESSAGE,
+    },
+    Player, PlayerEvent,
+};
+use crate::{utils::get_ipc_path, MPV_IPC_CONNECT_TIMEOUT_MS};
+
+// Plays everything through one long-lived mpv process, controlled over its json ipc socket.
+// The current track always sits at index 0 of mpv's playlist with the upcoming tracks after it.
+pub struct MpvPlayer {
+    child: Option<Child>,
+    ipc: Option<MpvIpcClient>,
+    paused: bool,
+    time_pos: f64,
+    duration: f64,
+    volume: f64,
+    // mpv can't seek before the file is loaded, so this is sent once its duration is known
+    pending_seek: Option<f64>,
+}
+
+impl MpvPlayer {
+    pub fn new() -> Self {
+        let mut player = MpvPlayer {
+            child: None,
+            ipc: None,
+            paused: true,
+            time_pos: 0.0,
+            duration: 0.0,
+            volume: 100.0,
+            pending_seek: None,
+        };
+        player.spawn();
+        player
+    }
+
+    fn spawn(&mut self) {
+        self.kill();
+
+        let ipc_path = get_ipc_path();
+        self.child = Some(
+            Command::new("mpv")
+                .arg("--idle=yes")
+                .arg("--no-terminal")
+                .arg("--no-audio-display")
+                .arg("--keep-open=yes")
+                .arg("--gapless-audio=yes")
+                .arg("--prefetch-playlist=yes")
+                .arg(format!("--volume={}", self.volume))
+                .arg(format!("--input-ipc-server={}", ipc_path.to_str().unwrap()))
+                .spawn()
+                .unwrap(),
+        );
+        self.ipc = connect_ipc(&ipc_path);
+    }
+
+    fn kill(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.child = None;
+        self.ipc = None;
+    }
+
+    fn send(&mut self, command: MpvCommand) {
+        if let Some(client) = &mut self.ipc {
+            if let Err(e) = client.send(command) {
+                warn!("Could not send command to mpv : {}", e);
+            }
+        }
+    }
+
+    fn translate_event(&mut self, event: MpvEvent) -> Option<PlayerEvent> {
+        match event {
+            MpvEvent::PauseChanged(paused) => {
+                self.paused = paused;
+                Some(PlayerEvent::Paused(paused))
+            }
+            MpvEvent::TimePosChanged(t) => {
+                self.time_pos = t.unwrap_or(0.0);
+                None
+            }
+            MpvEvent::DurationChanged(d) => {
+                self.duration = d.unwrap_or(0.0);
+                if d.is_some() {
+                    if let Some(secs) = self.pending_seek.take() {
+                        self.send(MpvCommand::Seek(secs, SeekMode::Absolute));
+                    }
+                }
+                None
+            }
+            // the current track always sits at index 0, so anything past it is a transition
+            MpvEvent::PlaylistPosChanged(pos) if pos > 0 => Some(PlayerEvent::AdvancedToNext),
+            // only reached at the end of the playlist, thanks to --keep-open
+            MpvEvent::EofReachedChanged(true) => Some(PlayerEvent::EndOfTrack),
+            MpvEvent::ClientMessage(args) => match args.first().map(|s| s.as_str()) {
+                Some(NEXT_MESSAGE) => Some(PlayerEvent::NextRequested),
+                Some(PREV_MESSAGE) => Some(PlayerEvent::PrevRequested),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Default for MpvPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn connect_ipc(ipc_path: &Path) -> Option<MpvIpcClient> {
+    let timeout = Duration::from_millis(MPV_IPC_CONNECT_TIMEOUT_MS);
+    let mut client = match MpvIpcClient::connect(ipc_path, timeout) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Could not connect to mpv ipc socket : {}", e);
+            return None;
+        }
+    };
+
+    let setup = client.observe_properties().and_then(|_| {
+        client.send(MpvCommand::KeyBind(
+            "NEXT".to_string(),
+            format!("script-message {}", NEXT_MESSAGE),
+        ))?;
+        client.send(MpvCommand::KeyBind(
+            "PREV".to_string(),
+            format!("script-message {}", PREV_MESSAGE),
+        ))
+    });
+    if let Err(e) = setup {
+        warn!("Could not set up mpv ipc client : {}", e);
+    }
+    Some(client)
+}
+
+impl Player for MpvPlayer {
+    fn load(&mut self, path: &Path) {
+        self.send(MpvCommand::LoadFile(path.to_path_buf(), LoadFileMode::Replace));
+        self.send(MpvCommand::SetPause(false));
+        self.time_pos = 0.0;
+        self.pending_seek = None;
+    }
+
+    fn load_paused_at(&mut self, path: &Path, secs: f64) {
+        self.send(MpvCommand::SetPause(true));
+        self.send(MpvCommand::LoadFile(path.to_path_buf(), LoadFileMode::Replace));
+        self.time_pos = secs;
+        self.pending_seek = Some(secs);
+    }
+
+    // playlist-clear also drops a finished entry still sitting before the current one
+    fn set_upcoming(&mut self, paths: &[PathBuf]) {
+        self.send(MpvCommand::PlaylistClear);
+        for p in paths.iter() {
+            self.send(MpvCommand::LoadFile(p.clone(), LoadFileMode::Append));
+        }
+        debug!("Synced mpv playlist : {:?}", paths);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.send(MpvCommand::SetPause(paused));
+    }
+
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn seek(&mut self, secs: f64) {
+        self.send(MpvCommand::Seek(secs, SeekMode::Absolute));
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        self.volume = volume;
+        self.send(MpvCommand::SetVolume(volume));
+    }
+
+    fn position(&self) -> f64 {
+        self.time_pos
+    }
+
+    fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    fn poll_events(&mut self) -> Vec<PlayerEvent> {
+        // restart mpv if it exited on its own, and report the track as over so the caller
+        // moves on to the next one
+        let exited = match &mut self.child {
+            Some(child) => child.try_wait().unwrap().is_some(),
+            None => false,
+        };
+        if exited {
+            warn!("mpv exited unexpectedly, restarting");
+            self.spawn();
+            return vec![PlayerEvent::EndOfTrack];
+        }
+
+        let events = match &self.ipc {
+            Some(client) => client.poll_events(),
+            None => Vec::new(),
+        };
+        events
+            .into_iter()
+            .filter_map(|e| self.translate_event(e))
+            .collect()
+    }
+
+    fn shutdown(&mut self) {
+        self.send(MpvCommand::Quit);
+        self.kill();
+    }
+}
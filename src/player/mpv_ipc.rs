@@ -0,0 +1,251 @@
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+type IpcStream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type IpcStream = std::fs::File;
+
+const CONNECT_RETRY_INTERVAL_MS: u64 = 20;
+
+// script-message names that the NEXT/PREV keybinds send back to us as client-message events
+pub const NEXT_MESSAGE: &str = "mprs-next";
+pub const PREV_MESSAGE: &str = "mprs-prev";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadFileMode {
+    Replace,
+    Append,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekMode {
+    Absolute,
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MpvProperty {
+    Pause,
+    TimePos,
+    EofReached,
+    Duration,
+    PlaylistPos,
+}
+
+impl MpvProperty {
+    pub const ALL: [MpvProperty; 5] = [
+        MpvProperty::Pause,
+        MpvProperty::TimePos,
+        MpvProperty::EofReached,
+        MpvProperty::Duration,
+        MpvProperty::PlaylistPos,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MpvProperty::Pause => "pause",
+            MpvProperty::TimePos => "time-pos",
+            MpvProperty::EofReached => "eof-reached",
+            MpvProperty::Duration => "duration",
+            MpvProperty::PlaylistPos => "playlist-pos",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        MpvProperty::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MpvCommand {
+    LoadFile(PathBuf, LoadFileMode),
+    // removes every playlist entry except the one currently playing
+    PlaylistClear,
+    SetPause(bool),
+    Seek(f64, SeekMode),
+    SetVolume(f64),
+    ObserveProperty(u64, MpvProperty),
+    KeyBind(String, String),
+    Quit,
+}
+
+impl MpvCommand {
+    fn to_args(&self) -> Value {
+        match self {
+            MpvCommand::LoadFile(p, mode) => {
+                let mode = match mode {
+                    LoadFileMode::Replace => "replace",
+                    LoadFileMode::Append => "append",
+                };
+                json!(["loadfile", p.to_string_lossy(), mode])
+            }
+            MpvCommand::PlaylistClear => json!(["playlist-clear"]),
+            MpvCommand::SetPause(p) => json!(["set_property", "pause", p]),
+            MpvCommand::Seek(secs, mode) => {
+                let mode = match mode {
+                    SeekMode::Absolute => "absolute",
+                    SeekMode::Relative => "relative",
+                };
+                json!(["seek", secs, mode])
+            }
+            MpvCommand::SetVolume(v) => json!(["set_property", "volume", v]),
+            MpvCommand::ObserveProperty(id, prop) => json!(["observe_property", id, prop.name()]),
+            MpvCommand::KeyBind(key, cmd) => json!(["keybind", key, cmd]),
+            MpvCommand::Quit => json!(["quit"]),
+        }
+    }
+}
+
+// time-pos and duration are unavailable (null) while no file is loaded,
+// playlist-pos is -1 in that case
+#[derive(Debug, Clone, PartialEq)]
+pub enum MpvEvent {
+    PauseChanged(bool),
+    TimePosChanged(Option<f64>),
+    EofReachedChanged(bool),
+    DurationChanged(Option<f64>),
+    PlaylistPosChanged(i64),
+    ClientMessage(Vec<String>),
+    EndFile(String),
+    Shutdown,
+}
+
+pub struct MpvIpcClient {
+    stream: IpcStream,
+    events: Receiver<MpvEvent>,
+    next_request_id: u64,
+}
+
+impl MpvIpcClient {
+    // mpv creates the socket shortly after being spawned, so keep retrying until timeout
+    pub fn connect(path: &Path, timeout: Duration) -> io::Result<Self> {
+        let start = Instant::now();
+        let stream = loop {
+            match open_stream(path) {
+                Ok(s) => break s,
+                Err(e) if start.elapsed() < timeout => {
+                    debug!("Waiting for mpv ipc socket : {}", e);
+                    sleep(Duration::from_millis(CONNECT_RETRY_INTERVAL_MS));
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let (tx, rx) = channel();
+        let reader = stream.try_clone()?;
+        spawn(move || read_events(reader, tx));
+
+        Ok(MpvIpcClient {
+            stream,
+            events: rx,
+            next_request_id: 1,
+        })
+    }
+
+    pub fn send(&mut self, command: MpvCommand) -> io::Result<()> {
+        let msg = json!({
+            "command": command.to_args(),
+            "request_id": self.next_request_id,
+        });
+        self.next_request_id += 1;
+        debug!("mpv ipc -> {}", msg);
+        writeln!(self.stream, "{}", msg)
+    }
+
+    // observes every property in MpvProperty::ALL, using the index as the observer id
+    pub fn observe_properties(&mut self) -> io::Result<()> {
+        for (i, prop) in MpvProperty::ALL.into_iter().enumerate() {
+            self.send(MpvCommand::ObserveProperty(i as u64 + 1, prop))?;
+        }
+        Ok(())
+    }
+
+    // returns all events received since the last call without blocking
+    pub fn poll_events(&self) -> Vec<MpvEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+#[cfg(unix)]
+fn open_stream(path: &Path) -> io::Result<IpcStream> {
+    IpcStream::connect(path)
+}
+
+#[cfg(windows)]
+fn open_stream(path: &Path) -> io::Result<IpcStream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(path)
+}
+
+fn read_events(stream: IpcStream, tx: Sender<MpvEvent>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                debug!("mpv ipc read failed : {}", e);
+                break;
+            }
+        };
+
+        if let Some(event) = parse_message(&line) {
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = tx.send(MpvEvent::Shutdown);
+}
+
+fn parse_message(line: &str) -> Option<MpvEvent> {
+    let msg: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Could not parse mpv ipc message {:?} : {}", line, e);
+            return None;
+        }
+    };
+
+    // replies to our own commands carry a request_id instead of an event name
+    if msg.get("request_id").is_some() {
+        if msg["error"] != "success" {
+            warn!("mpv ipc command failed : {}", msg);
+        }
+        return None;
+    }
+
+    match msg["event"].as_str()? {
+        "property-change" => {
+            let data = &msg["data"];
+            match MpvProperty::from_name(msg["name"].as_str()?)? {
+                MpvProperty::Pause => Some(MpvEvent::PauseChanged(data.as_bool()?)),
+                MpvProperty::TimePos => Some(MpvEvent::TimePosChanged(data.as_f64())),
+                MpvProperty::EofReached => {
+                    Some(MpvEvent::EofReachedChanged(data.as_bool().unwrap_or(false)))
+                }
+                MpvProperty::Duration => Some(MpvEvent::DurationChanged(data.as_f64())),
+                MpvProperty::PlaylistPos => {
+                    Some(MpvEvent::PlaylistPosChanged(data.as_i64().unwrap_or(-1)))
+                }
+            }
+        }
+        "client-message" => Some(MpvEvent::ClientMessage(
+            msg["args"]
+                .as_array()?
+                .iter()
+                .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                .collect(),
+        )),
+        "end-file" => Some(MpvEvent::EndFile(
+            msg["reason"].as_str().unwrap_or("unknown").to_string(),
+        )),
+        "shutdown" => Some(MpvEvent::Shutdown),
+        _ => None,
+    }
+}
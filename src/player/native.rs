@@ -0,0 +1,543 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{debug, warn};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn, JoinHandle},
+    time::Duration,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::Time,
+};
+
+use super::{Player, PlayerEvent};
+
+// how much decoded audio to keep ahead of the output device
+const BUFFER_AHEAD_S: f64 = 0.5;
+const DECODER_IDLE_SLEEP_MS: u64 = 5;
+
+enum Command {
+    Load(PathBuf),
+    SetUpcoming(Vec<PathBuf>),
+    Seek(f64),
+    Shutdown,
+}
+
+// what the decoder thread hands to the output callback, in playback order
+enum Chunk {
+    Samples(Vec<f32>),
+    // a new track starts here, advanced is set when it follows the previous one gaplessly
+    TrackStart { advanced: bool, duration: f64 },
+    TrackEnd,
+}
+
+struct Shared {
+    chunks: VecDeque<Chunk>,
+    chunk_offset: usize,
+    buffered_samples: usize,
+    // set while a gaplessly queued track is buffered but not playing yet
+    pending_advance: Option<PathBuf>,
+    frames_played: u64,
+    duration: f64,
+    paused: bool,
+    volume: f32,
+    events: Vec<PlayerEvent>,
+}
+
+impl Shared {
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.chunk_offset = 0;
+        self.buffered_samples = 0;
+        self.pending_advance = None;
+    }
+
+    // fills one interleaved output frame, returns false if nothing was buffered
+    fn next_frame(&mut self, frame: &mut [f32]) -> bool {
+        let mut written = 0;
+        while written < frame.len() {
+            match self.chunks.front() {
+                Some(Chunk::Samples(s)) => {
+                    let n = (frame.len() - written).min(s.len() - self.chunk_offset);
+                    for (out, sample) in frame[written..written + n]
+                        .iter_mut()
+                        .zip(&s[self.chunk_offset..self.chunk_offset + n])
+                    {
+                        *out = sample * self.volume;
+                    }
+                    written += n;
+                    self.chunk_offset += n;
+                    self.buffered_samples -= n;
+                    if self.chunk_offset == s.len() {
+                        self.chunks.pop_front();
+                        self.chunk_offset = 0;
+                    }
+                }
+                Some(Chunk::TrackStart { advanced, duration }) => {
+                    if *advanced {
+                        self.events.push(PlayerEvent::AdvancedToNext);
+                        self.pending_advance = None;
+                    }
+                    self.duration = *duration;
+                    self.frames_played = 0;
+                    self.chunks.pop_front();
+                }
+                Some(Chunk::TrackEnd) => {
+                    self.events.push(PlayerEvent::EndOfTrack);
+                    self.chunks.pop_front();
+                }
+                None => break,
+            }
+        }
+        frame[written..].fill(0.0);
+        written > 0
+    }
+}
+
+// Decodes files in-process with symphonia and plays them through the default cpal output
+// device. Upcoming tracks are decoded into the same buffer right after the current one, so
+// transitions are gapless.
+pub struct NativePlayer {
+    shared: Arc<Mutex<Shared>>,
+    commands: Sender<Command>,
+    decoder_thread: Option<JoinHandle<()>>,
+    sample_rate: u32,
+}
+
+impl NativePlayer {
+    pub fn new() -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            chunks: VecDeque::new(),
+            chunk_offset: 0,
+            buffered_samples: 0,
+            pending_advance: None,
+            frames_played: 0,
+            duration: 0.0,
+            paused: true,
+            volume: 1.0,
+            events: Vec::new(),
+        }));
+
+        let (tx, rx) = channel();
+        let (config_tx, config_rx) = channel();
+        let shared_rc = Arc::clone(&shared);
+        let decoder_thread = spawn(move || run_decoder(shared_rc, rx, config_tx));
+        // falls back to a nominal rate if no output device could be opened, nothing plays then
+        let sample_rate = config_rx.recv().map(|(r, _)| r).unwrap_or(44100);
+
+        NativePlayer {
+            shared,
+            commands: tx,
+            decoder_thread: Some(decoder_thread),
+            sample_rate,
+        }
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            warn!("Native player decoder thread is not running");
+        }
+    }
+}
+
+impl Default for NativePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for NativePlayer {
+    fn load(&mut self, path: &Path) {
+        self.shared.lock().unwrap().paused = false;
+        self.send(Command::Load(path.to_path_buf()));
+    }
+
+    fn load_paused_at(&mut self, path: &Path, secs: f64) {
+        self.shared.lock().unwrap().paused = true;
+        self.send(Command::Load(path.to_path_buf()));
+        self.send(Command::Seek(secs.max(0.0)));
+    }
+
+    fn set_upcoming(&mut self, paths: &[PathBuf]) {
+        self.send(Command::SetUpcoming(paths.to_vec()));
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let mut s = self.shared.lock().unwrap();
+        if s.paused != paused {
+            s.paused = paused;
+            s.events.push(PlayerEvent::Paused(paused));
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.shared.lock().unwrap().paused
+    }
+
+    fn seek(&mut self, secs: f64) {
+        self.send(Command::Seek(secs.max(0.0)));
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        self.shared.lock().unwrap().volume = (volume / 100.0) as f32;
+    }
+
+    fn position(&self) -> f64 {
+        self.shared.lock().unwrap().frames_played as f64 / self.sample_rate as f64
+    }
+
+    fn duration(&self) -> f64 {
+        self.shared.lock().unwrap().duration
+    }
+
+    fn poll_events(&mut self) -> Vec<PlayerEvent> {
+        std::mem::take(&mut self.shared.lock().unwrap().events)
+    }
+
+    fn shutdown(&mut self) {
+        self.send(Command::Shutdown);
+        if let Some(t) = self.decoder_thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+// opens the default output device, the stream has to stay on the thread that created it
+fn open_output(shared: Arc<Mutex<Shared>>) -> Option<(cpal::Stream, u32, usize)> {
+    let device = cpal::default_host().default_output_device()?;
+    let config: cpal::StreamConfig = match device.default_output_config() {
+        Ok(c) => c.into(),
+        Err(e) => {
+            warn!("Could not get output config : {}", e);
+            return None;
+        }
+    };
+    let channels = config.channels as usize;
+
+    let stream = device.build_output_stream(
+        &config,
+        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            let mut s = shared.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                if s.paused || !s.next_frame(frame) {
+                    frame.fill(0.0);
+                } else {
+                    s.frames_played += 1;
+                }
+            }
+        },
+        |e| warn!("Audio output error : {}", e),
+        None,
+    );
+    let stream = match stream {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Could not open output stream : {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = stream.play() {
+        warn!("Could not start output stream : {}", e);
+        return None;
+    }
+    Some((stream, config.sample_rate.0, channels))
+}
+
+fn run_decoder(
+    shared: Arc<Mutex<Shared>>,
+    commands: Receiver<Command>,
+    config_tx: Sender<(u32, usize)>,
+) {
+    let (_stream, out_rate, out_channels) = match open_output(Arc::clone(&shared)) {
+        Some(o) => o,
+        None => return,
+    };
+    let _ = config_tx.send((out_rate, out_channels));
+
+    let max_buffered = (BUFFER_AHEAD_S * (out_rate as usize * out_channels) as f64) as usize;
+    let mut current: Option<TrackDecoder> = None;
+    let mut upcoming: Vec<PathBuf> = Vec::new();
+
+    loop {
+        let command = if current.is_none() {
+            match commands.recv() {
+                Ok(c) => Some(c),
+                Err(_) => return,
+            }
+        } else {
+            commands.try_recv().ok()
+        };
+
+        match command {
+            Some(Command::Load(p)) => {
+                let mut s = shared.lock().unwrap();
+                s.clear();
+                current = TrackDecoder::open(&p, out_rate, out_channels);
+                match &current {
+                    Some(d) => s.chunks.push_back(Chunk::TrackStart {
+                        advanced: false,
+                        duration: d.duration,
+                    }),
+                    None => s.chunks.push_back(Chunk::TrackEnd),
+                }
+                continue;
+            }
+            Some(Command::SetUpcoming(mut paths)) => {
+                // the first upcoming track may already be buffered behind the current one
+                let s = shared.lock().unwrap();
+                if s.pending_advance.is_some() && paths.first() == s.pending_advance.as_ref() {
+                    paths.remove(0);
+                }
+                upcoming = paths;
+                continue;
+            }
+            Some(Command::Seek(secs)) => {
+                if let Some(d) = &mut current {
+                    let mut s = shared.lock().unwrap();
+                    if s.pending_advance.is_none() && d.seek(secs) {
+                        s.clear();
+                        // the track start marker may have been cleared before being played
+                        s.duration = d.duration;
+                        s.frames_played = (secs * out_rate as f64) as u64;
+                    }
+                }
+                continue;
+            }
+            Some(Command::Shutdown) => return,
+            None => {}
+        }
+
+        let decoder = match &mut current {
+            Some(d) => d,
+            None => continue,
+        };
+
+        if shared.lock().unwrap().buffered_samples >= max_buffered {
+            sleep(Duration::from_millis(DECODER_IDLE_SLEEP_MS));
+            continue;
+        }
+
+        match decoder.next_samples() {
+            Some(samples) => {
+                let mut s = shared.lock().unwrap();
+                s.buffered_samples += samples.len();
+                s.chunks.push_back(Chunk::Samples(samples));
+            }
+            None => {
+                current = None;
+                while current.is_none() && !upcoming.is_empty() {
+                    let next = upcoming.remove(0);
+                    current = TrackDecoder::open(&next, out_rate, out_channels);
+                    if let Some(d) = &current {
+                        let mut s = shared.lock().unwrap();
+                        s.chunks.push_back(Chunk::TrackStart {
+                            advanced: true,
+                            duration: d.duration,
+                        });
+                        s.pending_advance = Some(next);
+                    }
+                }
+                if current.is_none() {
+                    shared.lock().unwrap().chunks.push_back(Chunk::TrackEnd);
+                }
+            }
+        }
+    }
+}
+
+// decodes a single file into interleaved f32 samples in the output's format
+struct TrackDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    duration: f64,
+    out_channels: usize,
+    resampler: Resampler,
+}
+
+impl TrackDecoder {
+    fn open(path: &Path, out_rate: u32, out_channels: usize) -> Option<Self> {
+        debug!("Decoding : {:?}", path);
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Could not open {:?} : {}", path, e);
+                return None;
+            }
+        };
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(|e| warn!("Could not probe {:?} : {}", path, e))
+            .ok()?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+        let params = &track.codec_params;
+        let in_rate = params.sample_rate.unwrap_or(out_rate);
+        let duration = match (params.n_frames, params.time_base) {
+            (Some(n), Some(tb)) => {
+                let t = tb.calc_time(n);
+                t.seconds as f64 + t.frac
+            }
+            _ => 0.0,
+        };
+
+        let decoder = symphonia::default::get_codecs()
+            .make(params, &DecoderOptions::default())
+            .map_err(|e| warn!("Could not create decoder for {:?} : {}", path, e))
+            .ok()?;
+
+        Some(TrackDecoder {
+            track_id: track.id,
+            format,
+            decoder,
+            duration,
+            out_channels,
+            resampler: Resampler::new(in_rate, out_rate, out_channels),
+        })
+    }
+
+    fn seek(&mut self, secs: f64) -> bool {
+        let to = SeekTo::Time {
+            time: Time::from(secs),
+            track_id: Some(self.track_id),
+        };
+        match self.format.seek(SeekMode::Accurate, to) {
+            Ok(_) => {
+                self.decoder.reset();
+                self.resampler.reset();
+                true
+            }
+            Err(e) => {
+                warn!("Seek failed : {}", e);
+                false
+            }
+        }
+    }
+
+    // returns None at the end of the track
+    fn next_samples(&mut self) -> Option<Vec<f32>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(SymphoniaError::IoError(_)) => return None,
+                Err(e) => {
+                    warn!("Could not read packet : {}", e);
+                    return None;
+                }
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(d) => d,
+                Err(SymphoniaError::DecodeError(e)) => {
+                    debug!("Skipping undecodable packet : {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Could not decode packet : {}", e);
+                    return None;
+                }
+            };
+
+            let spec = *decoded.spec();
+            let in_channels = spec.channels.count();
+            let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buf.copy_interleaved_ref(decoded);
+
+            // map onto the output channels, repeating the last input channel if there are fewer
+            let mut mapped = Vec::with_capacity(buf.samples().len() / in_channels * self.out_channels);
+            for frame in buf.samples().chunks(in_channels) {
+                for c in 0..self.out_channels {
+                    mapped.push(frame[c.min(in_channels - 1)]);
+                }
+            }
+            return Some(self.resampler.process(mapped));
+        }
+    }
+}
+
+// linear interpolation resampler that carries its state across packets
+struct Resampler {
+    step: f64,
+    pos: f64,
+    prev: Vec<f32>,
+    channels: usize,
+}
+
+impl Resampler {
+    fn new(in_rate: u32, out_rate: u32, channels: usize) -> Self {
+        Resampler {
+            step: in_rate as f64 / out_rate as f64,
+            pos: 1.0,
+            prev: vec![0.0; channels],
+            channels,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pos = 1.0;
+        self.prev.fill(0.0);
+    }
+
+    // input frame k is at virtual index k + 1, with the last frame of the previous packet at 0
+    fn process(&mut self, input: Vec<f32>) -> Vec<f32> {
+        if self.step == 1.0 || input.is_empty() {
+            return input;
+        }
+
+        let ch = self.channels;
+        let n = input.len() / ch;
+        let frame = |k: usize| -> &[f32] {
+            if k == 0 {
+                &self.prev
+            } else {
+                &input[(k - 1) * ch..k * ch]
+            }
+        };
+
+        let mut out = Vec::with_capacity((n as f64 / self.step) as usize * ch + ch);
+        let mut pos = self.pos;
+        while pos < n as f64 {
+            let i = pos.floor() as usize;
+            let t = (pos - i as f64) as f32;
+            let (a, b) = (frame(i), frame(i + 1));
+            for c in 0..ch {
+                out.push(a[c] * (1.0 - t) + b[c] * t);
+            }
+            pos += self.step;
+        }
+
+        self.pos = pos - n as f64;
+        self.prev = input[(n - 1) * ch..].to_vec();
+        out
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::{Player, PlayerEvent};
+
+// Everything the null player has been told to do, shared with whoever holds a handle to it
+#[derive(Debug, Default)]
+pub struct NullPlayerState {
+    pub loaded: Option<PathBuf>,
+    pub upcoming: Vec<PathBuf>,
+    pub paused: bool,
+    pub position: f64,
+    pub duration: f64,
+    pub volume: f64,
+    pub pending_events: Vec<PlayerEvent>,
+}
+
+impl NullPlayerState {
+    // pretends the loaded track played to the end, moving on like a gapless backend would
+    pub fn finish_track(&mut self) {
+        self.position = 0.0;
+        if self.upcoming.is_empty() {
+            self.pending_events.push(PlayerEvent::EndOfTrack);
+        } else {
+            self.loaded = Some(self.upcoming.remove(0));
+            self.pending_events.push(PlayerEvent::AdvancedToNext);
+        }
+    }
+}
+
+// A backend that plays nothing, for running without audio output and for driving playback
+// logic from tests through the handle
+pub struct NullPlayer {
+    state: Arc<Mutex<NullPlayerState>>,
+}
+
+impl NullPlayer {
+    pub fn new() -> Self {
+        NullPlayer {
+            state: Arc::new(Mutex::new(NullPlayerState {
+                paused: true,
+                volume: 100.0,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn handle(&self) -> Arc<Mutex<NullPlayerState>> {
+        Arc::clone(&self.state)
+    }
+}
+
+impl Default for NullPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for NullPlayer {
+    fn load(&mut self, path: &Path) {
+        let mut s = self.state.lock().unwrap();
+        s.loaded = Some(path.to_path_buf());
+        s.paused = false;
+        s.position = 0.0;
+    }
+
+    fn set_upcoming(&mut self, paths: &[PathBuf]) {
+        self.state.lock().unwrap().upcoming = paths.to_vec();
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let mut s = self.state.lock().unwrap();
+        if s.paused != paused {
+            s.paused = paused;
+            s.pending_events.push(PlayerEvent::Paused(paused));
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    fn seek(&mut self, secs: f64) {
+        self.state.lock().unwrap().position = secs;
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        self.state.lock().unwrap().volume = volume;
+    }
+
+    fn position(&self) -> f64 {
+        self.state.lock().unwrap().position
+    }
+
+    fn duration(&self) -> f64 {
+        self.state.lock().unwrap().duration
+    }
+
+    fn poll_events(&mut self) -> Vec<PlayerEvent> {
+        std::mem::take(&mut self.state.lock().unwrap().pending_events)
+    }
+
+    fn shutdown(&mut self) {
+        let mut s = self.state.lock().unwrap();
+        s.loaded = None;
+        s.upcoming.clear();
+    }
+}
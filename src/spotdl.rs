@@ -1,46 +1,115 @@
-use std::{path::PathBuf, process::{Child, Command, Stdio}};
-use log::debug;
+use std::{path::PathBuf, process::Command};
+use log::{debug, warn};
 use rspotify::{
-    model::{AlbumId, SearchResult as rsptSearchResult, SearchType},
+    model::{FullTrack, SearchResult as rsptSearchResult, SearchType},
     prelude::*,
-    ClientCredsSpotify, Credentials,
+    ClientCredsSpotify, ClientResult, Credentials,
 };
-use tokio::runtime::Runtime;
+use serde::{Deserialize, Serialize};
 
 use std::fs::File;
 
-use crate::utils::{get_music_dir, get_newtracks_dir};
+use crate::config::Config;
+use crate::db::TrackInfo;
+use crate::spotify_link::SpotifyLink;
+use crate::utils::get_music_dir;
+use crate::NUM_SEARCH_RESULTS;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub name: String,
     pub artists: Vec<String>,
     pub album: String,
     pub id: String,
     pub duration: u64,
+    pub isrc: Option<String>,
+    pub release_date: Option<String>,
+    pub track_number: u32,
+    pub disc_number: u32,
+    #[serde(default)]
+    pub album_artist: Option<String>,
+    // the link for tracks that aren't on spotify, ie: youtube or soundcloud
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 impl SearchResult {
     pub fn get_url(&self) -> String {
-        format!("http://open.spotify.com/track/{}", self.id)
+        match &self.url {
+            Some(url) => url.clone(),
+            None => format!("http://open.spotify.com/track/{}", self.id),
+        }
+    }
+
+    // the link itself for tracks that aren't on spotify
+    pub fn get_source_id(&self) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => format!("spotify:track:{}", self.id),
+        }
+    }
+
+    // a track only known by its link, the downloaded file's tags fill in the rest
+    pub fn from_url(url: &str) -> Self {
+        SearchResult {
+            name: url.to_string(),
+            artists: Vec::new(),
+            album: String::new(),
+            id: String::new(),
+            duration: 0,
+            isrc: None,
+            release_date: None,
+            track_number: 0,
+            disc_number: 0,
+            album_artist: None,
+            url: Some(url.to_string()),
+        }
+    }
+
+    // None for local tracks, which have no id to download them by
+    pub fn from_full_track(t: &FullTrack) -> Option<Self> {
+        Some(SearchResult {
+            name: t.name.clone(),
+            artists: t.artists.iter().map(|a| a.name.clone()).collect(),
+            album: t.album.name.clone(),
+            id: t.id.as_ref()?.id().to_string(),
+            duration: t.duration.num_seconds() as u64,
+            isrc: t.external_ids.get("isrc").cloned(),
+            release_date: t.album.release_date.clone(),
+            track_number: t.track_number,
+            disc_number: t.disc_number as u32,
+            album_artist: t.album.artists.first().map(|a| a.name.clone()),
+            url: None,
+        })
+    }
+
+    pub fn to_track_info(&self) -> TrackInfo {
+        TrackInfo {
+            name: self.name.clone(),
+            artists: Some(self.artists.clone()),
+            // tracks only known by their link have no album, their tags have it
+            album: Some(self.album.clone()).filter(|a| !a.is_empty()),
+            duration: self.duration as u32,
+            source_id: Some(self.get_source_id()),
+            isrc: self.isrc.clone(),
+            release_date: self.release_date.clone(),
+            album_artist: self.album_artist.clone(),
+            track_number: Some(self.track_number).filter(|&n| n > 0),
+            disc_number: Some(self.disc_number).filter(|&n| n > 0),
+            ..Default::default()
+        }
     }
 }
 
-fn get_creds() -> (String, String) {
-    let output = String::from_utf8(
-        Command::new("pip")
-            .arg("show")
-            .arg("spotdl")
-            .output()
-            .unwrap()
-            .stdout,
-    )
-    .unwrap();
+// the client credentials spotdl ships with, None if spotdl isn't installed
+fn get_spotdl_creds() -> Option<(String, String)> {
+    let output = Command::new("pip").arg("show").arg("spotdl").output().ok()?;
+    let output = String::from_utf8(output.stdout).ok()?;
 
     let mut location = PathBuf::new();
     for l in output.lines() {
-        if l.contains("Location") {
-            location.push(l.split(" ").collect::<Vec<&str>>()[1]);
+        if let Some(l) = l.strip_prefix("Location: ") {
+            location.push(l);
         }
     }
 
@@ -51,21 +120,31 @@ fn get_creds() -> (String, String) {
     let mut client_id = String::new();
     let mut client_secret = String::new();
 
-    let file_contents = std::fs::read_to_string(location).unwrap();
+    let file_contents = std::fs::read_to_string(location).ok()?;
     for l in file_contents.lines() {
-        if l.contains("client_id"){
-            client_id.push_str(l.split("\"").collect::<Vec<&str>>()[3]);
+        let value = l.split("\"").nth(3);
+        if l.contains("client_id") {
+            client_id.push_str(value?);
         }
         if l.contains("client_secret") {
-            client_secret.push_str(l.split("\"").collect::<Vec<&str>>()[3]);
+            client_secret.push_str(value?);
         }
     }
 
-    (client_id, client_secret)
+    Some((client_id, client_secret))
 }
 
-pub fn init_spotify_client() -> ClientCredsSpotify {
-    let (id, secret) = get_creds();
+// Uses the credentials from the config, or failing that spotdl's. Without either, searches and
+// links fail
+pub fn init_spotify_client(config: &Config) -> ClientCredsSpotify {
+    let configured = config
+        .spotify_client_id
+        .clone()
+        .zip(config.spotify_client_secret.clone());
+    let (id, secret) = configured.or_else(get_spotdl_creds).unwrap_or_else(|| {
+        warn!("No spotify credentials in the config and spotdl isn't installed");
+        Default::default()
+    });
     let creds = Credentials {
         id,
         secret: Some(secret),
@@ -73,67 +152,182 @@ pub fn init_spotify_client() -> ClientCredsSpotify {
     ClientCredsSpotify::new(creds)
 }
 
-pub fn search_tracks(search_string: String, n_results: u32, spotify: &mut ClientCredsSpotify) -> Vec<SearchResult> {
-    debug!("Searching for query \'{}\'", &search_string);
-    let rt = Runtime::new().unwrap();
-    let results = rt.block_on(async {
-        spotify.request_token().await.unwrap();
-        spotify
-            .search(
-                &search_string[..],
-                SearchType::Track,
-                None,
-                None,
-                Some(n_results),
-                None,
-            )
-            .await
-            .unwrap()
-    });
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchKind {
+    Tracks,
+    Albums,
+    Artists,
+    Playlists,
+}
+
+impl SearchKind {
+    pub const ALL: [SearchKind; 4] = [
+        SearchKind::Tracks,
+        SearchKind::Albums,
+        SearchKind::Artists,
+        SearchKind::Playlists,
+    ];
 
-    let mut parsed_results = Vec::new();
-    if let rsptSearchResult::Tracks(tracks) = results {
-        for t in tracks.items.iter() {
-            let mut artists = Vec::new();
-            for v in &t.artists {
-                artists.push(v.name.clone());
-            }
-
-            let id = t.id.clone()
-                .unwrap()
-                .to_string()
-                .clone();
-
-            let track_id = id.clone()
-                .split(":")
-                .collect::<Vec<&str>>()[2]
-                .to_string();
-
-            parsed_results.push(
-                SearchResult {
-                    name: t.name.clone(),
-                    album: t.album.name.clone(),
-                    artists,
-                    id: track_id,
-                    duration: t.duration.num_seconds() as u64
-                }
-            );
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchKind::Tracks => "Tracks",
+            SearchKind::Albums => "Albums",
+            SearchKind::Artists => "Artists",
+            SearchKind::Playlists => "Playlists",
         }
     }
-    parsed_results
+
+    fn search_type(&self) -> SearchType {
+        match self {
+            SearchKind::Tracks => SearchType::Track,
+            SearchKind::Albums => SearchType::Album,
+            SearchKind::Artists => SearchType::Artist,
+            SearchKind::Playlists => SearchType::Playlist,
+        }
+    }
+}
+
+// An album, artist or playlist a search found, its tracks are fetched through the link
+#[derive(Debug, Clone)]
+pub struct CollectionResult {
+    pub name: String,
+    // the artists and year of an album, genres of an artist, owner and size of a playlist
+    pub detail: String,
+    pub link: SpotifyLink,
+}
+
+// Tracks for track searches, collections for the rest
+#[derive(Debug, Default)]
+pub struct SearchPage {
+    pub tracks: Vec<SearchResult>,
+    pub collections: Vec<CollectionResult>,
+    // whether there's another page after this one
+    pub has_more: bool,
+}
+
+// Requests a token the first time, the client refreshes it itself once it expires. Clones of the
+// client share it
+pub async fn ensure_token(spotify: &ClientCredsSpotify) -> ClientResult<()> {
+    let has_token = spotify.get_token().lock().await.unwrap().is_some();
+    if !has_token {
+        spotify.request_token().await?;
+    }
+    Ok(())
+}
+
+// a page of NUM_SEARCH_RESULTS results, starting at offset
+pub async fn search(
+    spotify: &ClientCredsSpotify,
+    query: &str,
+    kind: SearchKind,
+    offset: u32,
+) -> anyhow::Result<SearchPage> {
+    debug!("Searching {} for query '{}' from {}", kind.as_str(), query, offset);
+    ensure_token(spotify).await?;
+    let results = spotify
+        .search(
+            query,
+            kind.search_type(),
+            None,
+            None,
+            Some(NUM_SEARCH_RESULTS),
+            Some(offset),
+        )
+        .await?;
+
+    let page = match results {
+        rsptSearchResult::Tracks(p) => SearchPage {
+            tracks: p.items.iter().filter_map(SearchResult::from_full_track).collect(),
+            has_more: p.next.is_some(),
+            ..Default::default()
+        },
+        rsptSearchResult::Albums(p) => SearchPage {
+            collections: p
+                .items
+                .into_iter()
+                .filter_map(|a| {
+                    let artists = a.artists.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+                    let date = a.release_date.unwrap_or_default();
+                    let year = date.chars().take(4).collect::<String>();
+                    Some(CollectionResult {
+                        name: a.name,
+                        detail: format!("{} ({})", artists.join(", "), year),
+                        link: SpotifyLink::Album(a.id?.id().to_string()),
+                    })
+                })
+                .collect(),
+            has_more: p.next.is_some(),
+            ..Default::default()
+        },
+        rsptSearchResult::Artists(p) => SearchPage {
+            collections: p
+                .items
+                .into_iter()
+                .map(|a| CollectionResult {
+                    detail: a.genres.join(", "),
+                    link: SpotifyLink::Artist(a.id.id().to_string()),
+                    name: a.name,
+                })
+                .collect(),
+            has_more: p.next.is_some(),
+            ..Default::default()
+        },
+        rsptSearchResult::Playlists(p) => SearchPage {
+            collections: p
+                .items
+                .into_iter()
+                .map(|pl| CollectionResult {
+                    detail: format!(
+                        "by {}, {} tracks",
+                        pl.owner.display_name.unwrap_or(pl.owner.id.id().to_string()),
+                        pl.tracks.total
+                    ),
+                    link: SpotifyLink::Playlist(pl.id.id().to_string()),
+                    name: pl.name,
+                })
+                .collect(),
+            has_more: p.next.is_some(),
+            ..Default::default()
+        },
+        _ => SearchPage::default(),
+    };
+    Ok(page)
 }
 
-pub fn download_track(url: &String) -> Child {
-    debug!("Downloading track from url: {}", url);
-
-    Command::new("spotdl")
-        .arg(format!("{}", url))
-        .arg("--format")
-        .arg("mp3")
-        .arg("--output")
-        .arg(get_newtracks_dir().as_os_str().to_str().unwrap())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spotify_link::tests::{album, client, page, serve, token};
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn searches_a_page_at_a_time() {
+        let mut found = album("1111111111111111111111", "Record");
+        found["artists"] = json!([{"external_urls": {}, "href": null, "id": null, "name": "Band"}]);
+        let base = serve(vec![
+            token(),
+            ("type=album", json!({"albums": page(vec![found], Some("http://next"))})),
+        ]);
+        let spotify = client(&base);
+        let rt = Runtime::new().unwrap();
+
+        let res = rt.block_on(search(&spotify, "record", SearchKind::Albums, 0)).unwrap();
+        assert!(res.tracks.is_empty());
+        assert!(res.has_more);
+        assert_eq!(res.collections[0].name, "Record");
+        assert_eq!(res.collections[0].detail, "Band (2020)");
+        assert_eq!(
+            res.collections[0].link,
+            SpotifyLink::Album("1111111111111111111111".to_string())
+        );
+
+        // the token is kept for the next searches, which don't ask for another
+        let other = client(&serve(vec![("type=album", json!({"albums": page(vec![], None)}))]));
+        let token = rt.block_on(spotify.get_token().lock()).unwrap().clone();
+        *rt.block_on(other.get_token().lock()).unwrap() = token;
+        let res = rt.block_on(search(&other, "record", SearchKind::Albums, 20)).unwrap();
+        assert!(res.collections.is_empty());
+        assert!(!res.has_more);
+    }
 }
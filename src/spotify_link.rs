@@ -0,0 +1,596 @@
+use std::{collections::HashSet, future::Future};
+
+use anyhow::Context;
+use log::debug;
+use rspotify::{
+    model::{AlbumId, AlbumType, ArtistId, Page, PlayableItem, PlaylistId, TrackId},
+    prelude::*,
+    ClientCredsSpotify, ClientResult,
+};
+
+use crate::spotdl::{ensure_token, SearchResult};
+
+// the most items the api hands out per page, and track ids it takes per request
+const PAGE_SIZE: u32 = 50;
+
+// A spotify link whose tracks can all be pulled in at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpotifyLink {
+    Playlist(String),
+    Album(String),
+    Artist(String),
+}
+
+// everything a link points to, name being that of the playlist, album or artist
+#[derive(Debug, Clone)]
+pub struct LinkTracks {
+    pub name: String,
+    pub tracks: Vec<SearchResult>,
+}
+
+impl SpotifyLink {
+    // Takes open.spotify.com urls, with or without a locale and query, and spotify: uris
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let parts = match s.strip_prefix("spotify:") {
+            Some(uri) => uri.split(':').collect::<Vec<&str>>(),
+            None => {
+                let path = s.split_once("open.spotify.com/")?.1;
+                let path = path.split(['?', '#']).next().unwrap_or_default();
+                path.split('/')
+                    .filter(|p| !p.is_empty() && !p.starts_with("intl-"))
+                    .collect()
+            }
+        };
+
+        let (kind, id) = match parts.as_slice() {
+            [kind, id] if !id.is_empty() => (*kind, id.to_string()),
+            _ => return None,
+        };
+        match kind {
+            "playlist" => Some(SpotifyLink::Playlist(id)),
+            "album" => Some(SpotifyLink::Album(id)),
+            "artist" => Some(SpotifyLink::Artist(id)),
+            _ => None,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        let (kind, id) = match self {
+            SpotifyLink::Playlist(id) => ("playlist", id),
+            SpotifyLink::Album(id) => ("album", id),
+            SpotifyLink::Artist(id) => ("artist", id),
+        };
+        format!("https://open.spotify.com/{}/{}", kind, id)
+    }
+}
+
+// Collects the items of every page, starting from first. fetch gets the offset of the next page
+async fn all_pages<T, F, Fut>(first: Page<T>, fetch: F) -> ClientResult<Vec<T>>
+where
+    F: Fn(u32) -> Fut,
+    Fut: Future<Output = ClientResult<Page<T>>>,
+{
+    let mut next = first.next;
+    let mut items = first.items;
+    while next.is_some() {
+        let page = fetch(items.len() as u32).await?;
+        if page.items.is_empty() {
+            break;
+        }
+        next = page.next;
+        items.extend(page.items);
+    }
+    Ok(items)
+}
+
+// Albums list tracks without their isrc, so those are fetched again in full
+async fn full_tracks(
+    spotify: &ClientCredsSpotify,
+    ids: Vec<TrackId<'static>>,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let mut results = Vec::new();
+    for chunk in ids.chunks(PAGE_SIZE as usize) {
+        let tracks = spotify.tracks(chunk.iter().cloned(), None).await?;
+        results.extend(tracks.iter().filter_map(SearchResult::from_full_track));
+    }
+    Ok(results)
+}
+
+async fn album_track_ids(
+    spotify: &ClientCredsSpotify,
+    id: AlbumId<'_>,
+    first: Page<rspotify::model::SimplifiedTrack>,
+) -> ClientResult<Vec<TrackId<'static>>> {
+    let tracks = all_pages(first, |offset| {
+        spotify.album_track_manual(id.as_ref(), None, Some(PAGE_SIZE), Some(offset))
+    })
+    .await?;
+    Ok(tracks.into_iter().filter_map(|t| t.id).collect())
+}
+
+async fn playlist_tracks(spotify: &ClientCredsSpotify, id: &str) -> anyhow::Result<LinkTracks> {
+    let id = PlaylistId::from_id(id).context("Invalid playlist id")?;
+    let playlist = spotify.playlist(id.as_ref(), None, None).await?;
+    let items = all_pages(playlist.tracks, |offset| {
+        spotify.playlist_items_manual(id.as_ref(), None, None, Some(PAGE_SIZE), Some(offset))
+    })
+    .await?;
+
+    // episodes and local files can't be downloaded
+    let tracks = items
+        .iter()
+        .filter_map(|i| match &i.track {
+            Some(PlayableItem::Track(t)) => SearchResult::from_full_track(t),
+            _ => None,
+        })
+        .collect();
+    Ok(LinkTracks {
+        name: playlist.name,
+        tracks,
+    })
+}
+
+async fn album_tracks(spotify: &ClientCredsSpotify, id: &str) -> anyhow::Result<LinkTracks> {
+    let id = AlbumId::from_id(id).context("Invalid album id")?;
+    let album = spotify.album(id.as_ref(), None).await?;
+    let ids = album_track_ids(spotify, id, album.tracks).await?;
+    Ok(LinkTracks {
+        name: album.name,
+        tracks: full_tracks(spotify, ids).await?,
+    })
+}
+
+// An album or single of an artist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub name: String,
+    // album or single
+    pub album_type: String,
+    pub release_date: String,
+    // of the album
+    pub id: String,
+}
+
+impl Release {
+    pub fn link(&self) -> SpotifyLink {
+        SpotifyLink::Album(self.id.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtistReleases {
+    pub name: String,
+    pub releases: Vec<Release>,
+}
+
+// The name without what tells re-releases apart, so "Record (Deluxe Edition)" and
+// "Record - Remastered 2011" are both "record"
+fn release_key(name: &str) -> String {
+    const MARKERS: [&str; 7] = [
+        "remaster",
+        "deluxe",
+        "edition",
+        "anniversary",
+        "expanded",
+        "bonus",
+        "version",
+    ];
+    let mut key = name.to_lowercase();
+    loop {
+        let suffix = [key.rfind('('), key.rfind('['), key.rfind(" - ")]
+            .into_iter()
+            .flatten()
+            .max();
+        match suffix {
+            Some(i) if MARKERS.iter().any(|m| key[i..].contains(m)) => {
+                key.truncate(i);
+                key = key.trim_end().to_string();
+            }
+            _ => return key.trim().to_string(),
+        }
+    }
+}
+
+// keeps the first release of each album or single, oldest first
+fn dedupe_releases(mut releases: Vec<Release>) -> Vec<Release> {
+    releases.sort_by(|a, b| a.release_date.cmp(&b.release_date));
+    let mut seen = HashSet::new();
+    releases.retain(|r| seen.insert((r.album_type.clone(), release_key(&r.name))));
+    releases
+}
+
+// the artist's albums and singles, without their re-releases
+pub async fn artist_releases(
+    spotify: &ClientCredsSpotify,
+    id: &str,
+) -> anyhow::Result<ArtistReleases> {
+    let id = ArtistId::from_id(id).context("Invalid artist id")?;
+    ensure_token(spotify).await?;
+    let artist = spotify.artist(id.as_ref()).await?;
+    let groups = [AlbumType::Album, AlbumType::Single];
+    let fetch_albums = |offset| {
+        spotify.artist_albums_manual(id.as_ref(), groups, None, Some(PAGE_SIZE), Some(offset))
+    };
+    let albums = all_pages(fetch_albums(0).await?, fetch_albums).await?;
+
+    let releases = albums
+        .into_iter()
+        .filter_map(|a| {
+            Some(Release {
+                id: a.id?.id().to_string(),
+                name: a.name,
+                album_type: a.album_type.unwrap_or_default(),
+                release_date: a.release_date.unwrap_or_default(),
+            })
+        })
+        .collect();
+    Ok(ArtistReleases {
+        name: artist.name,
+        releases: dedupe_releases(releases),
+    })
+}
+
+// Every track of the releases, in full. Singles repeat album tracks, which share the isrc, so
+// albums go first and keep their copy, with its album and track number
+pub async fn releases_tracks(
+    spotify: &ClientCredsSpotify,
+    releases: &[Release],
+) -> anyhow::Result<Vec<SearchResult>> {
+    ensure_token(spotify).await?;
+    let mut releases = releases.iter().collect::<Vec<&Release>>();
+    releases.sort_by_key(|r| r.album_type != "album");
+    let mut ids = Vec::new();
+    for release in releases {
+        let album_id = AlbumId::from_id(release.id.as_str()).context("Invalid album id")?;
+        let first = spotify
+            .album_track_manual(album_id.as_ref(), None, Some(PAGE_SIZE), Some(0))
+            .await?;
+        ids.extend(album_track_ids(spotify, album_id, first).await?);
+    }
+
+    let mut seen = HashSet::new();
+    Ok(full_tracks(spotify, ids)
+        .await?
+        .into_iter()
+        .filter(|t| seen.insert(t.isrc.clone().unwrap_or(t.id.clone())))
+        .collect())
+}
+
+// every track on the artist's albums and singles
+async fn artist_tracks(spotify: &ClientCredsSpotify, id: &str) -> anyhow::Result<LinkTracks> {
+    let artist = artist_releases(spotify, id).await?;
+    Ok(LinkTracks {
+        tracks: releases_tracks(spotify, &artist.releases).await?,
+        name: artist.name,
+    })
+}
+
+pub async fn link_tracks(
+    link: &SpotifyLink,
+    spotify: &ClientCredsSpotify,
+) -> anyhow::Result<LinkTracks> {
+    debug!("Fetching the tracks of {:?}", link);
+    ensure_token(spotify).await?;
+    match link {
+        SpotifyLink::Playlist(id) => playlist_tracks(spotify, id).await,
+        SpotifyLink::Album(id) => album_tracks(spotify, id).await,
+        SpotifyLink::Artist(id) => artist_tracks(spotify, id).await,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rspotify::{Config, Credentials};
+    use serde_json::{json, Value};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+    use tokio::runtime::Runtime;
+
+    // Stands in for the spotify api, answering each request with the body of the first route
+    // whose pattern is in the request line
+    pub fn serve(routes: Vec<(&'static str, Value)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let routes = routes.clone();
+                thread::spawn(move || handle(stream.unwrap(), &routes));
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn handle(stream: TcpStream, routes: &[(&'static str, Value)]) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((k, v)) = header.split_once(':') {
+                    if k.eq_ignore_ascii_case("content-length") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let (status, body) = match routes.iter().find(|(p, _)| request_line.contains(p)) {
+                Some((_, v)) => ("200 OK", v.to_string()),
+                None => ("404 Not Found", "{}".to_string()),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    }
+
+    // link_tracks, outside of a runtime
+    fn fetch_link_tracks(
+        link: &SpotifyLink,
+        spotify: &ClientCredsSpotify,
+    ) -> anyhow::Result<LinkTracks> {
+        Runtime::new().unwrap().block_on(link_tracks(link, spotify))
+    }
+
+    pub fn client(base: &str) -> ClientCredsSpotify {
+        let config = Config {
+            api_base_url: format!("{}/v1/", base),
+            auth_base_url: format!("{}/", base),
+            ..Default::default()
+        };
+        ClientCredsSpotify::with_config(Credentials::new("id", "secret"), config)
+    }
+
+    pub fn token() -> (&'static str, Value) {
+        let token = json!({"access_token": "t", "token_type": "Bearer", "expires_in": 3600});
+        ("POST /api/token", token)
+    }
+
+    pub fn page(items: Vec<Value>, next: Option<&str>) -> Value {
+        json!({
+            "href": "", "items": items, "limit": 50, "next": next, "offset": 0,
+            "previous": null, "total": 0
+        })
+    }
+
+    pub fn album(id: &str, name: &str) -> Value {
+        json!({
+            "album_type": "album", "artists": [], "external_urls": {}, "href": null, "id": id,
+            "images": [], "name": name, "release_date": "2020-01-01"
+        })
+    }
+
+    fn track(id: &str, isrc: &str) -> Value {
+        track_on(id, isrc, album("1111111111111111111111", "Record"), 1)
+    }
+
+    fn track_on(id: &str, isrc: &str, album: Value, number: u32) -> Value {
+        json!({
+            "album": album,
+            "artists": [{"external_urls": {}, "href": null, "id": null, "name": "Band"}],
+            "disc_number": 1, "duration_ms": 61000, "explicit": false,
+            "external_ids": {"isrc": isrc}, "external_urls": {}, "href": null, "id": id,
+            "is_local": false, "name": format!("Song {}", id), "popularity": 0,
+            "preview_url": null, "track_number": number
+        })
+    }
+
+    fn simplified_track(id: &str) -> Value {
+        json!({
+            "artists": [], "available_markets": null, "disc_number": 1, "duration_ms": 1000,
+            "explicit": false, "external_urls": {}, "id": id, "is_local": false,
+            "is_playable": null, "linked_from": null, "restrictions": null, "name": "",
+            "preview_url": null, "track_number": 1
+        })
+    }
+
+    fn playlist_item(track: Value) -> Value {
+        json!({"added_at": null, "added_by": null, "is_local": false, "track": track})
+    }
+
+    #[test]
+    fn parses_links() {
+        let id = "37i9dQZF1DXcBWIGoYBM5M";
+        let url = format!("https://open.spotify.com/intl-de/playlist/{}?si=abc", id);
+        assert_eq!(SpotifyLink::parse(&url), Some(SpotifyLink::Playlist(id.to_string())));
+        assert_eq!(
+            SpotifyLink::parse(&format!("spotify:album:{}", id)),
+            Some(SpotifyLink::Album(id.to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse(&format!("open.spotify.com/artist/{}", id)),
+            Some(SpotifyLink::Artist(id.to_string()))
+        );
+        assert_eq!(SpotifyLink::parse("https://open.spotify.com/track/abc"), None);
+        assert_eq!(SpotifyLink::parse("some song"), None);
+    }
+
+    #[test]
+    fn pages_through_playlists() {
+        let pid = "37i9dQZF1DXcBWIGoYBM5M";
+        let next = "http://next";
+        let first = page(
+            vec![
+                playlist_item(track("2222222222222222222222", "A")),
+                // local files have no id
+                playlist_item(json!(null)),
+            ],
+            Some(next),
+        );
+        let playlist = json!({
+            "collaborative": false, "description": null, "external_urls": {},
+            "followers": {"total": 0}, "href": "", "id": pid, "images": [], "name": "Mix",
+            "owner": {"display_name": null, "external_urls": {}, "href": "", "id": "me"},
+            "public": true, "snapshot_id": "", "tracks": first
+        });
+        let second = page(vec![playlist_item(track("3333333333333333333333", "B"))], None);
+        let base = serve(vec![
+            token(),
+            ("offset=2", second),
+            ("/v1/playlists/", playlist),
+        ]);
+
+        let link = SpotifyLink::Playlist(pid.to_string());
+        let res = fetch_link_tracks(&link, &client(&base)).unwrap();
+        assert_eq!(res.name, "Mix");
+        let ids = res.tracks.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["2222222222222222222222", "3333333333333333333333"]);
+        assert_eq!(res.tracks[0].isrc.as_deref(), Some("A"));
+        assert_eq!(res.tracks[0].album, "Record");
+        assert_eq!(res.tracks[0].duration, 61);
+    }
+
+    #[test]
+    fn fetches_album_tracks_in_full() {
+        let aid = "1111111111111111111111";
+        let mut full_album = album(aid, "Record");
+        full_album["copyrights"] = json!([]);
+        full_album["external_ids"] = json!({});
+        full_album["genres"] = json!([]);
+        full_album["href"] = json!("");
+        full_album["popularity"] = json!(0);
+        full_album["release_date_precision"] = json!("day");
+        full_album["tracks"] = page(
+            vec![simplified_track("2222222222222222222222")],
+            Some("http://next"),
+        );
+        let base = serve(vec![
+            token(),
+            (
+                "/v1/albums/1111111111111111111111/tracks?",
+                page(vec![simplified_track("3333333333333333333333")], None),
+            ),
+            (
+                "/v1/tracks/?ids=2222222222222222222222,3333333333333333333333",
+                json!({"tracks": [
+                    track("2222222222222222222222", "A"),
+                    track("3333333333333333333333", "B")
+                ]}),
+            ),
+            ("/v1/albums/", full_album),
+        ]);
+
+        let link = SpotifyLink::Album(aid.to_string());
+        let res = fetch_link_tracks(&link, &client(&base)).unwrap();
+        assert_eq!(res.name, "Record");
+        let isrcs = res.tracks.iter().map(|t| t.isrc.clone().unwrap()).collect::<Vec<String>>();
+        assert_eq!(isrcs, vec!["A", "B"]);
+
+        let missing = SpotifyLink::Playlist("0000000000000000000000".to_string());
+        assert!(fetch_link_tracks(&missing, &client(&base)).is_err());
+    }
+
+    #[test]
+    fn dedupes_re_releases() {
+        let release = |name: &str, album_type: &str, date: &str| Release {
+            name: name.to_string(),
+            album_type: album_type.to_string(),
+            release_date: date.to_string(),
+            id: date.to_string(),
+        };
+        assert_eq!(release_key("Record (Deluxe Edition) - Remastered 2011"), "record");
+        assert_eq!(release_key("Live [Expanded]"), "live");
+        // parts that don't mark a re-release stay
+        assert_eq!(release_key("Songs (For You)"), "songs (for you)");
+
+        let releases = dedupe_releases(vec![
+            release("Record - 2011 Remaster", "album", "2011-05-01"),
+            release("Record", "album", "1979-01-01"),
+            release("Record", "single", "1978-10-01"),
+            release("Other Record", "album", "1981-01-01"),
+        ]);
+        let dates = releases.iter().map(|r| r.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(dates, vec!["1978-10-01", "1979-01-01", "1981-01-01"]);
+    }
+
+    #[test]
+    fn prefers_album_tracks_over_their_singles() {
+        let artist_id = "7777777777777777777777";
+        let (album_id, single_id) = ("1111111111111111111111", "4444444444444444444444");
+        let release = |id: &str, name: &str, album_type: &str, date: &str| {
+            let mut a = album(id, name);
+            a["album_type"] = json!(album_type);
+            a["release_date"] = json!(date);
+            a
+        };
+        let record = release(album_id, "Record", "album", "2020-02-01");
+        let single = release(single_id, "Song", "single", "2020-01-01");
+        let deluxe = release(
+            "6666666666666666666666",
+            "Record (Deluxe Edition)",
+            "album",
+            "2021-01-01",
+        );
+        let artist = json!({
+            "external_urls": {}, "followers": {"href": null, "total": 0}, "genres": [],
+            "href": "", "id": artist_id, "images": [], "name": "Band", "popularity": 0
+        });
+        let base = serve(vec![
+            token(),
+            (
+                "/v1/artists/7777777777777777777777/albums",
+                page(vec![deluxe, record.clone(), single.clone()], None),
+            ),
+            ("/v1/artists/", artist),
+            (
+                "/v1/albums/1111111111111111111111/tracks?",
+                page(
+                    vec![
+                        simplified_track("2222222222222222222222"),
+                        simplified_track("3333333333333333333333"),
+                    ],
+                    None,
+                ),
+            ),
+            (
+                "/v1/albums/4444444444444444444444/tracks?",
+                page(vec![simplified_track("5555555555555555555555")], None),
+            ),
+            (
+                concat!(
+                    "/v1/tracks/?ids=2222222222222222222222,3333333333333333333333,",
+                    "5555555555555555555555"
+                ),
+                json!({"tracks": [
+                    track_on("2222222222222222222222", "X", record.clone(), 1),
+                    track_on("3333333333333333333333", "Y", record, 2),
+                    track_on("5555555555555555555555", "X", single, 1)
+                ]}),
+            ),
+        ]);
+
+        let spotify = client(&base);
+        let rt = Runtime::new().unwrap();
+        let artist = rt.block_on(artist_releases(&spotify, artist_id)).unwrap();
+        assert_eq!(artist.name, "Band");
+        // the deluxe edition is a re-release of the album
+        let ids = artist.releases.iter().map(|r| r.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, vec![single_id, album_id]);
+
+        let tracks = rt.block_on(releases_tracks(&spotify, &artist.releases)).unwrap();
+        let ids = tracks.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["2222222222222222222222", "3333333333333333333333"]);
+        assert_eq!(tracks[0].album, "Record");
+        assert_eq!(tracks[1].track_number, 2);
+    }
+}
@@ -1,9 +1,45 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::db::{sync::PlaylistSource, TrackDB};
+
 #[derive(PartialEq, Eq, Ord, PartialOrd, Debug, Serialize, Deserialize, Clone)]
 pub enum F1State {
     All,
     Playlists,
     Artists,
     Albums,
+    Genres,
+    Years,
+}
+
+// The f2 panel's values for a category, kept until the category or the library changes
+#[derive(Default)]
+pub struct FilterCache {
+    f1_state: Option<F1State>,
+    version: (i64, i64),
+    pub values: Vec<String>,
+    // the smart ones among the values, when they're playlists
+    pub smart_playlists: Vec<String>,
+    // of the synced playlists among them
+    pub sources: HashMap<String, PlaylistSource>,
+}
+
+impl FilterCache {
+    // returns whether the library changed since the last refresh
+    pub fn refresh(&mut self, trackdb: &TrackDB, f1_state: &F1State) -> bool {
+        let version = trackdb.library_version();
+        let library_changed = version != self.version;
+        if library_changed || self.f1_state.as_ref() != Some(f1_state) {
+            self.values = trackdb.filter_values(f1_state);
+            (self.smart_playlists, self.sources) = match f1_state {
+                F1State::Playlists => (trackdb.smart_playlist_names(), trackdb.playlist_sources()),
+                _ => Default::default(),
+            };
+            self.f1_state = Some(f1_state.clone());
+            self.version = version;
+        }
+        library_changed
+    }
 }
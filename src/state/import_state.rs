@@ -0,0 +1,26 @@
+use std::thread::JoinHandle;
+
+use crate::{db::TrackFileMode, import::ImportReport};
+
+// the import folder popup, with the import itself running on its own thread
+pub struct ImportState {
+    pub show_popup: bool,
+    pub folder: String,
+    pub mode: TrackFileMode,
+    pub playlist: Option<String>,
+    pub job: Option<JoinHandle<anyhow::Result<ImportReport>>>,
+    pub report: Option<Result<ImportReport, String>>,
+}
+
+impl Default for ImportState {
+    fn default() -> Self {
+        ImportState {
+            show_popup: false,
+            folder: String::new(),
+            mode: TrackFileMode::Copy,
+            playlist: None,
+            job: None,
+            report: None,
+        }
+    }
+}
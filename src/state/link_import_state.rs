@@ -0,0 +1,11 @@
+// Set while the search popup lists the tracks of a spotify playlist, album or artist link
+pub struct LinkImport {
+    // of what the link points to
+    pub name: String,
+    // mprs playlist the tracks go into, created if there's none by that name
+    pub playlist: String,
+    // tracks not in the library when the link was fetched
+    pub n_new: usize,
+    // a playlist created from it is synced with this
+    pub url: String,
+}
@@ -2,3 +2,10 @@ pub mod state;
 pub mod notification_state;
 pub mod tracklist_state;
 pub mod filter_state;
+pub mod import_state;
+pub mod session;
+pub mod tag_editor_state;
+pub mod smart_playlist_state;
+pub mod playlist_file_state;
+pub mod link_import_state;
+pub mod search_state;
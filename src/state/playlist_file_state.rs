@@ -0,0 +1,36 @@
+use crate::db::playlist_file::PlaylistImportReport;
+
+// what the playlist file popup was opened for
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaylistFileAction {
+    // the playlist called this, or the queue if None
+    Export(Option<String>),
+    Import,
+}
+
+// The popup for exporting playlists to and importing them from m3u8 and xspf files, open while
+// action is set
+#[derive(Default)]
+pub struct PlaylistFileState {
+    pub action: Option<PlaylistFileAction>,
+    pub path: String,
+    // m3u8 paths relative to the file's dir
+    pub relative: bool,
+    // playlist to import into, named after the file if empty
+    pub playlist: String,
+    pub report: Option<Result<PlaylistImportReport, String>>,
+}
+
+impl PlaylistFileState {
+    pub fn open(&mut self, action: PlaylistFileAction) {
+        *self = PlaylistFileState {
+            action: Some(action),
+            relative: self.relative,
+            ..Default::default()
+        };
+    }
+
+    pub fn close(&mut self) {
+        self.action = None;
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use tokio::sync::oneshot::Receiver;
+
+use crate::{
+    spotdl::{CollectionResult, SearchKind, SearchPage, SearchResult},
+    spotify_link::{ArtistReleases, LinkTracks, Release},
+};
+
+// what a search job comes back with
+pub enum SearchOutcome {
+    // results of the kind, added to those shown unless they're the first page
+    Page {
+        kind: SearchKind,
+        offset: u32,
+        page: SearchPage,
+    },
+    // the tracks of a link, or of an album, artist or playlist from the results
+    Link {
+        url: String,
+        tracks: LinkTracks,
+    },
+    // the albums and singles of an artist from the results or a link
+    Artist { releases: ArtistReleases },
+    // every track of the releases picked in the artist view, to go into playlist
+    Releases {
+        playlist: String,
+        tracks: Vec<SearchResult>,
+    },
+}
+
+// An artist's albums and singles, opened from the results. Their tracks are downloaded into
+// playlist, or only those of the selected releases if any are
+pub struct ArtistView {
+    pub name: String,
+    pub releases: Vec<Release>,
+    // indices into releases
+    pub selected: HashSet<usize>,
+    pub playlist: String,
+}
+
+// The search popup's searches, run on the tokio runtime so the ui isn't held up meanwhile. The
+// tracks found are in AppState.search_results
+pub struct SearchState {
+    // picked for the next search
+    pub kind: SearchKind,
+    // what the results shown were searched with, for loading more of them
+    pub query: String,
+    pub results_kind: SearchKind,
+    pub collections: Vec<CollectionResult>,
+    pub has_more: bool,
+    // shown instead of the results while set, albums opened from it go back to it
+    pub artist: Option<ArtistView>,
+    pub job: Option<Receiver<anyhow::Result<SearchOutcome>>>,
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        SearchState {
+            kind: SearchKind::Tracks,
+            query: String::new(),
+            results_kind: SearchKind::Tracks,
+            collections: Vec::new(),
+            has_more: false,
+            artist: None,
+            job: None,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+};
+
+use crate::{
+    playback::{resume_track_at, update_volume},
+    track_queue::TrackQueue,
+    utils::get_session_file_path,
+};
+
+use super::{filter_state::F1State, state::AppState};
+
+// Playback state saved on exit, so the next launch picks up where this one left off
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub trackqueue: TrackQueue,
+    pub position: f64,
+    pub shuffle: bool,
+    pub volume: f64,
+    pub muted: bool,
+    pub f1_state: F1State,
+    pub f2_state: String,
+}
+
+impl Session {
+    pub fn from_app_state(app_state: &AppState) -> Self {
+        let position = match app_state.trackqueue.get_curr_track() {
+            Some(_) => app_state.player.position(),
+            None => 0.0,
+        };
+
+        Session {
+            trackqueue: app_state.trackqueue.clone(),
+            position,
+            shuffle: app_state.shuffle,
+            volume: app_state.volume,
+            muted: app_state.muted,
+            f1_state: app_state.f1_state.clone(),
+            f2_state: app_state.f2_state.clone(),
+        }
+    }
+
+    pub fn save_to_file(&self) {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(get_session_file_path())
+            .unwrap();
+
+        let encoded: Vec<u8> = bincode::serialize(self).unwrap();
+        file.write_all(&encoded).unwrap();
+    }
+
+    // returns None if there is no saved session or it can't be read, ie: after a format change
+    pub fn load_from_file() -> Option<Self> {
+        let mut file = File::open(get_session_file_path()).ok()?;
+        let mut encoded = Vec::new();
+        file.read_to_end(&mut encoded).ok()?;
+
+        match bincode::deserialize(&encoded) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!("Could not read saved session : {}", e);
+                None
+            }
+        }
+    }
+
+    // applies the session to app_state, with the current track loaded paused at the saved position
+    pub fn restore(self, app_state: &mut AppState) {
+        debug!("Restoring session : {:?}", self);
+
+        let mut trackqueue = self.trackqueue;
+        let trackdb = &app_state.trackdb;
+        trackqueue.retain_tracks(|t| trackdb.contains_track(t));
+        app_state.trackqueue = trackqueue;
+
+        // prev_state is updated too so the ui doesn't treat these as fresh user changes
+        app_state.shuffle = self.shuffle;
+        app_state.prev_state.shuffle = self.shuffle;
+
+        let f2_exists = app_state
+            .trackdb
+            .filter_values(&self.f1_state)
+            .contains(&self.f2_state);
+        if f2_exists {
+            app_state.prev_state.f1_state = self.f1_state.clone();
+            app_state.f1_state = self.f1_state;
+            app_state.f2_state = self.f2_state;
+        }
+
+        app_state.volume = self.volume;
+        app_state.muted = self.muted;
+        update_volume(app_state);
+
+        resume_track_at(app_state, self.position);
+    }
+}
+
+pub fn save_session(app_state: &AppState) {
+    Session::from_app_state(app_state).save_to_file();
+}
+
+pub fn restore_session(app_state: &mut AppState) {
+    if let Some(session) = Session::load_from_file() {
+        session.restore(app_state);
+    }
+}
@@ -0,0 +1,24 @@
+use crate::db::smart::SmartPlaylist;
+
+// The smart playlist dialog, open while editing is set
+#[derive(Default)]
+pub struct SmartPlaylistState {
+    pub editing: Option<SmartPlaylist>,
+    // name of the smart playlist being edited, None when creating one
+    pub prev_name: Option<String>,
+    pub error: Option<String>,
+}
+
+impl SmartPlaylistState {
+    pub fn open(&mut self, sp: SmartPlaylist, prev_name: Option<String>) {
+        *self = SmartPlaylistState {
+            editing: Some(sp),
+            prev_name,
+            error: None,
+        };
+    }
+
+    pub fn close(&mut self) {
+        *self = SmartPlaylistState::default();
+    }
+}
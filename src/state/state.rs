@@ -1,22 +1,34 @@
 use rspotify::ClientCredsSpotify;
-use stopwatch::Stopwatch;
 
 use crate::{
+    config::Config,
     db::{TrackDB, TrackInfo},
+    download_manager::DownloadManager,
+    downloader::downloaders_from_config,
+    player::{null::NullPlayer, Player},
     spotdl::{init_spotify_client, SearchResult},
+    spotify_link::LinkTracks,
     track_queue::TrackQueue,
 };
 use std::{
     collections::HashMap,
     path::PathBuf,
-    process::Child,
     sync::{Arc, Mutex},
 };
+use tokio::sync::oneshot::Receiver;
 
 use super::{
-    filter_state::F1State, notification_state::NotificationState, tracklist_state::TracklistState,
+    filter_state::{F1State, FilterCache},
+    import_state::ImportState, link_import_state::LinkImport,
+    notification_state::NotificationState, playlist_file_state::PlaylistFileState,
+    search_state::SearchState,
+    smart_playlist_state::SmartPlaylistState, tag_editor_state::TagEditorState,
+    tracklist_state::TracklistState,
 };
 
+// the remote tracks of each synced playlist, or why they couldn't be fetched
+pub type SyncResults = Vec<(String, anyhow::Result<LinkTracks>)>;
+
 pub struct AppStateWrapper {
     pub app_state: Arc<Mutex<AppState>>,
 }
@@ -30,18 +42,25 @@ impl Default for AppStateWrapper {
 }
 
 pub struct AppState {
-    pub paused: bool,
-    pub mpv_child: Option<Child>,
+    pub config: Config,
+    pub player: Box<dyn Player>,
+    pub preloaded_tracks: Vec<u32>,
+    // position the seek bar is being dragged to, not sent to the player until released
+    pub seek_scrub: Option<f64>,
 
     pub shuffle: bool,
+    // in percent, applied through playback::update_volume
+    pub volume: f64,
+    pub muted: bool,
+    pub show_queue: bool,
     pub notification: NotificationState,
     pub tracklist_state: TracklistState,
+    pub filter_cache: FilterCache,
     pub f1_state: F1State,
     pub f2_state: String,
 
     pub trackdb: TrackDB,
     pub trackqueue: TrackQueue,
-    pub track_clock: Stopwatch,
     pub prev_state: PrevState,
 
     pub curr_trackinfo: Option<TrackInfo>,
@@ -51,8 +70,17 @@ pub struct AppState {
     pub new_track_search_term: String,
     pub spt_creds: ClientCredsSpotify,
     pub search_results: Option<Vec<SearchResult>>,
-    pub selected_result_urls: HashMap<usize, String>,
-    pub pending_download_childs: (String, Vec<Child>),
+    pub selected_results: HashMap<usize, SearchResult>,
+    pub link_import: Option<LinkImport>,
+    pub search: SearchState,
+    // fetching the remote tracks of synced playlists
+    pub sync_job: Option<Receiver<SyncResults>>,
+    pub downloads: DownloadManager,
+    pub show_downloads: bool,
+    pub import_state: ImportState,
+    pub tag_editor: TagEditorState,
+    pub smart_playlist_editor: SmartPlaylistState,
+    pub playlist_file: PlaylistFileState,
 }
 
 pub struct PrevState {
@@ -64,15 +92,20 @@ pub struct PrevState {
 
 impl Default for AppState {
     fn default() -> Self {
-        let tdb = TrackDB::init();
-        let default_playlist = *tdb
-            .track_filter_cache
-            .get(&F1State::Playlists)
-            .unwrap()
-            .keys()
-            .collect::<Vec<&String>>()
-            .get(0)
-            .unwrap();
+        AppState::new(Config::load(), TrackDB::init())
+    }
+}
+
+impl AppState {
+    // the state around tdb, playing through the null player until one is set
+    pub fn new(config: Config, mut tdb: TrackDB) -> Self {
+        let downloads = DownloadManager::load(&mut tdb, downloaders_from_config(&config));
+        let spt_creds = init_spotify_client(&config);
+        let default_playlist = tdb
+            .playlist_names()
+            .first()
+            .cloned()
+            .unwrap_or("Liked".to_string());
 
         let prev_state = PrevState {
             f1_state: F1State::Playlists,
@@ -82,60 +115,60 @@ impl Default for AppState {
         };
 
         Self {
-            paused: true,
-            mpv_child: None,
+            config,
+            player: Box::new(NullPlayer::new()),
+            preloaded_tracks: Vec::new(),
+            seek_scrub: None,
             shuffle: false,
+            volume: 100.0,
+            muted: false,
+            show_queue: false,
             notification: NotificationState::default(),
             tracklist_state: TracklistState::default(),
+            filter_cache: FilterCache::default(),
             f1_state: F1State::Playlists,
-            f2_state: default_playlist.clone(),
+            f2_state: default_playlist,
 
+            downloads,
             trackdb: tdb,
             trackqueue: TrackQueue::new(),
-            track_clock: Stopwatch::new(),
             prev_state,
             curr_trackinfo: None,
             curr_albumcover: None,
             ctx: None,
             new_playlist_name: String::new(),
             new_track_search_term: String::new(),
-            spt_creds: init_spotify_client(),
+            spt_creds,
             search_results: None,
-            selected_result_urls: HashMap::new(),
-            pending_download_childs: (String::new(), Vec::new()),
+            selected_results: HashMap::new(),
+            link_import: None,
+            search: SearchState::default(),
+            sync_job: None,
+            show_downloads: false,
+            import_state: ImportState::default(),
+            tag_editor: TagEditorState::default(),
+            smart_playlist_editor: SmartPlaylistState::default(),
+            playlist_file: PlaylistFileState::default(),
         }
     }
-}
 
-impl AppState {
     pub fn get_curr_displayed_tracklist(&self) -> Vec<u32> {
-        self.trackdb
-            .track_filter_cache
-            .get(&self.f1_state)
-            .unwrap()
-            .get(&self.f2_state)
-            .unwrap()
-            .clone()
+        self.trackdb.filter_tracks(&self.f1_state, &self.f2_state)
     }
 
     pub fn get_curr_track_path(&self) -> Option<PathBuf> {
         let curr_trackid = self.trackqueue.get_curr_track();
         if let Some(id) = curr_trackid {
-            if let Some(tinfo) = self.trackdb.trackmap.get(&id) {
-                Some(tinfo.get_file_path())
-            } else {
-                None
-            }
+            self.trackdb.get_track(id).map(|tinfo| tinfo.get_file_path())
         } else {
             None
         }
     }
 
-    pub fn get_curr_track_info(&self) -> Option<&TrackInfo> {
+    pub fn get_curr_track_info(&self) -> Option<TrackInfo> {
         let curr_trackid = self.trackqueue.get_curr_track();
         if let Some(id) = curr_trackid {
-            let tinfo = self.trackdb.trackmap.get(&id).unwrap();
-            Some(tinfo)
+            self.trackdb.get_track(id)
         } else {
             None
         }
@@ -0,0 +1,107 @@
+use std::{fs::read, sync::Arc};
+
+use crate::utils::TagEdit;
+
+// The tag editor dialog, open while track_id is set. Fields are kept as typed until saved
+#[derive(Default)]
+pub struct TagEditorState {
+    pub track_id: Option<u32>,
+    pub title: String,
+    // separated by ;
+    pub artists: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: String,
+    pub year: String,
+    pub genre: String,
+    pub cover: Option<Arc<[u8]>>,
+    // image file to replace the cover with
+    pub new_cover_path: String,
+    pub remove_cover: bool,
+    pub error: Option<String>,
+}
+
+fn parse_number(field: &str, s: &str) -> Result<Option<u32>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    s.parse()
+        .map(Some)
+        .map_err(|_| format!("{} must be a number", field))
+}
+
+impl TagEditorState {
+    pub fn open(&mut self, track_id: u32, tags: TagEdit, cover: Vec<u8>) {
+        *self = TagEditorState {
+            track_id: Some(track_id),
+            title: tags.title,
+            artists: tags.artists.join("; "),
+            album: tags.album,
+            album_artist: tags.album_artist,
+            track_number: tags.track_number.map(|n| n.to_string()).unwrap_or_default(),
+            year: tags.year.map(|y| y.to_string()).unwrap_or_default(),
+            genre: tags.genre,
+            cover: Some(cover).filter(|c| !c.is_empty()).map(Arc::from),
+            ..Default::default()
+        };
+    }
+
+    pub fn close(&mut self) {
+        *self = TagEditorState::default();
+    }
+
+    pub fn to_tag_edit(&self) -> Result<TagEdit, String> {
+        if self.title.trim().is_empty() {
+            return Err("Title can't be empty".to_string());
+        }
+        let cover = if self.remove_cover {
+            Some(Vec::new())
+        } else if !self.new_cover_path.trim().is_empty() {
+            let p = self.new_cover_path.trim();
+            Some(read(p).map_err(|e| format!("Could not read {} : {}", p, e))?)
+        } else {
+            None
+        };
+
+        Ok(TagEdit {
+            title: self.title.trim().to_string(),
+            artists: self
+                .artists
+                .split(';')
+                .map(|a| a.trim().to_string())
+                .filter(|a| !a.is_empty())
+                .collect(),
+            album: self.album.trim().to_string(),
+            album_artist: self.album_artist.trim().to_string(),
+            track_number: parse_number("Track number", &self.track_number)?,
+            year: parse_number("Year", &self.year)?,
+            genre: self.genre.trim().to_string(),
+            cover,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_typed_fields() {
+        let mut state = TagEditorState::default();
+        state.open(1, TagEdit::default(), Vec::new());
+        state.title = " t ".to_string();
+        state.artists = "a; b ;;".to_string();
+        state.year = "1999".to_string();
+
+        let tags = state.to_tag_edit().unwrap();
+        assert_eq!(tags.title, "t");
+        assert_eq!(tags.artists, vec!["a", "b"]);
+        assert_eq!(tags.year, Some(1999));
+        assert_eq!(tags.track_number, None);
+        assert_eq!(tags.cover, None);
+
+        state.track_number = "4th".to_string();
+        assert!(state.to_tag_edit().is_err());
+    }
+}
@@ -1,7 +1,12 @@
-use crate::utils::duration_to_str;
+use std::collections::HashSet;
 
+use crate::{config::TrackColumn, db::TrackInfo, utils::duration_to_str};
+
+#[derive(Default)]
 pub struct TracklistState {
     pub items: Vec<TracklistItem>,
+    // entries a synced playlist's remote has dropped, found when the tracklist is filled
+    pub stale: HashSet<u32>,
 }
 
 #[derive(Clone)]
@@ -11,32 +16,67 @@ pub struct TracklistItem {
     pub artist: String,
     pub album: String,
     pub duration: String,
+    pub album_artist: String,
+    pub track_number: String,
+    pub year: String,
+    pub genre: String,
+    pub date_added: String,
 }
 
-impl Default for TracklistState {
-    fn default() -> Self {
-        TracklistState { items: Vec::new() }
+impl TracklistItem {
+    fn new(t_info: TrackInfo) -> Self {
+        let track_number = match (t_info.disc_number, t_info.track_number) {
+            (Some(d), Some(t)) if d > 1 => format!("{}-{}", d, t),
+            (_, Some(t)) => t.to_string(),
+            (_, None) => String::new(),
+        };
+        TracklistItem {
+            id: t_info.id,
+            name: t_info.name,
+            artist: t_info.artists.unwrap_or_default().join(", "),
+            album: t_info.album.unwrap_or_default(),
+            duration: duration_to_str(t_info.duration),
+            album_artist: t_info.album_artist.unwrap_or_default(),
+            track_number,
+            year: t_info
+                .release_date
+                .and_then(|d| d.get(..4).map(|y| y.to_string()))
+                .unwrap_or_default(),
+            genre: t_info.genre.unwrap_or_default(),
+            // just the day
+            date_added: t_info
+                .date_added
+                .and_then(|d| d.get(..10).map(|y| y.to_string()))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn column_value(&self, column: TrackColumn) -> &str {
+        match column {
+            TrackColumn::AlbumArtist => &self.album_artist,
+            TrackColumn::TrackNumber => &self.track_number,
+            TrackColumn::Year => &self.year,
+            TrackColumn::Genre => &self.genre,
+            TrackColumn::DateAdded => &self.date_added,
+        }
     }
 }
 
 impl TracklistState {
     pub fn empty(&mut self) {
         self.items.clear();
+        self.stale.clear();
+    }
+
+    pub fn add_item(&mut self, t_info: TrackInfo) {
+        self.items.push(TracklistItem::new(t_info));
     }
 
-    pub fn add_item(
-        &mut self,
-        id: u32,
-        name: String,
-        artists: Vec<String>,
-        album: String,
-        duration: u32,
-    ) {
-        self.items.push(TracklistItem {
-            id, name, album,
-            artist: artists.join(", "),
-            duration: duration_to_str(duration)
-        });
+    // refreshes the row of t_info's track, if it's listed
+    pub fn update_item(&mut self, t_info: TrackInfo) {
+        if let Some(item) = self.items.iter_mut().find(|x| x.id == t_info.id) {
+            *item = TracklistItem::new(t_info);
+        }
     }
 
     pub fn remove_with_id(&mut self, remove_id: u32) {
@@ -1,22 +1,38 @@
-use std::path::PathBuf;
 use rand::thread_rng;
 use rand::seq::SliceRandom;
 
 use log::debug;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TrackType {
     RegQueueTrack(u32),
     ExQueueTrack(u32),
     None
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RepeatMode {
+    Off,
+    One,
+    All,
+}
+
+// the lists of tracks shown in the queue panel, history is ordered oldest first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueueSection {
+    Explicit,
+    Regular,
+    History,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrackQueue {
     pub reg_queue: Vec<u32>,
     pub exp_queue: Vec<u32>, 
     pub curr_track: TrackType,
-    pub played_tracks: Vec<u32>
+    pub played_tracks: Vec<u32>,
+    pub repeat_mode: RepeatMode,
 }
 
 impl TrackQueue {
@@ -26,6 +42,7 @@ impl TrackQueue {
             exp_queue: Vec::new(),
             curr_track: TrackType::None,
             played_tracks: Vec::new(),
+            repeat_mode: RepeatMode::All,
         }
     }
 
@@ -60,63 +77,406 @@ impl TrackQueue {
         self.reg_queue.insert(0, p);
     }
 
+    pub fn set_repeat_mode(&mut self, mode: RepeatMode) {
+        self.repeat_mode = mode;
+    }
+
     pub fn get_curr_track(&self) -> Option<u32> {
-        match self.curr_track.clone() {
+        match self.curr_track {
             TrackType::RegQueueTrack(t) => Some(t),
             TrackType::ExQueueTrack(t) => Some(t),
             TrackType::None => None
         }
     }
 
-    pub fn next_track(&mut self) {
+    // the current track followed by everything queued after it
+    pub fn queued_tracks(&self) -> Vec<u32> {
+        self.get_curr_track()
+            .into_iter()
+            .chain(self.exp_queue.iter().copied())
+            .chain(self.reg_queue.iter().copied())
+            .collect()
+    }
+
+    // tracks that repeated calls to auto_advance will move to, in order, up to n of them
+    pub fn upcoming_tracks(&self, n: usize) -> Vec<u32> {
+        if self.repeat_mode == RepeatMode::One {
+            return self.get_curr_track().into_iter().take(n).collect();
+        }
+
+        let mut upcoming = self
+            .exp_queue
+            .iter()
+            .chain(self.reg_queue.iter())
+            .copied()
+            .collect::<Vec<u32>>();
+
+        // once both queues run out, everything that went through played_tracks comes around again
+        if self.repeat_mode == RepeatMode::All {
+            let curr = match self.curr_track {
+                TrackType::RegQueueTrack(t) => Some(t),
+                _ => None,
+            };
+            upcoming.extend(
+                self.played_tracks
+                    .iter()
+                    .copied()
+                    .chain(curr)
+                    .chain(self.reg_queue.iter().copied()),
+            );
+        }
+        upcoming.truncate(n);
+        upcoming
+    }
+
+    // moves on after the current track finished playing by itself. Unlike next_track, this
+    // stays on the current track when repeating a single one
+    pub fn auto_advance(&mut self) -> TrackType {
+        if self.repeat_mode == RepeatMode::One && self.get_curr_track().is_some() {
+            return self.curr_track;
+        }
+        self.next_track()
+    }
+
+    // skips to the next track. The explicit queue is played before the regular queue, and once
+    // both are empty the played tracks are cycled back into the regular queue, unless repeat is
+    // off in which case playback stops with no current track
+    pub fn next_track(&mut self) -> TrackType {
         // move curr track to played tracks vec
-        if let TrackType::RegQueueTrack(t) = self.curr_track.clone() {
+        if let TrackType::RegQueueTrack(t) = self.curr_track {
             self.played_tracks.push(t);
         }
         self.curr_track = TrackType::None;
 
-        // get next track from explicit queue first, if empty look at regular queue
-        // if both empty, reset regular queue to played tracks and call next track again
-        if self.exp_queue.len() > 0 {
+        if self.exp_queue.is_empty()
+            && self.reg_queue.is_empty()
+            && self.repeat_mode != RepeatMode::Off
+        {
+            self.reg_queue = std::mem::take(&mut self.played_tracks);
+        }
+
+        if !self.exp_queue.is_empty() {
             self.curr_track = TrackType::ExQueueTrack(self.exp_queue.remove(0));
-        } else if self.reg_queue.len() > 0 {
+        } else if !self.reg_queue.is_empty() {
             self.curr_track = TrackType::RegQueueTrack(self.reg_queue.remove(0));
-        } else {
-            self.reg_queue = self.played_tracks.clone();
-            self.played_tracks = Vec::new();
-            self.next_track();
         }
         debug!("{:?}", self);
+        self.curr_track
     }
 
-    pub fn prev_track(&mut self) {
-        if self.played_tracks.len() > 0 {
-            match self.curr_track.clone() {
-                TrackType::RegQueueTrack(t) => { self.reg_queue.insert(0, t) },
-                TrackType::ExQueueTrack(t) => { self.exp_queue.insert(0, t) },
+    // goes back to the last played track, does nothing if there is none
+    pub fn prev_track(&mut self) -> TrackType {
+        if let Some(prev) = self.played_tracks.pop() {
+            match self.curr_track {
+                TrackType::RegQueueTrack(t) => self.reg_queue.insert(0, t),
+                TrackType::ExQueueTrack(t) => self.exp_queue.insert(0, t),
                 TrackType::None => {}
             }
-            self.curr_track = TrackType::RegQueueTrack(self.played_tracks.pop().unwrap());
+            self.curr_track = TrackType::RegQueueTrack(prev);
         }
         debug!("{:?}", self);
+        self.curr_track
+    }
+
+    fn section_mut(&mut self, section: QueueSection) -> &mut Vec<u32> {
+        match section {
+            QueueSection::Explicit => &mut self.exp_queue,
+            QueueSection::Regular => &mut self.reg_queue,
+            QueueSection::History => &mut self.played_tracks,
+        }
+    }
+
+    // moves a queued track to another position, possibly in the other queue. The history can't
+    // be reordered. Returns false if either position is invalid
+    pub fn move_track(&mut self, from: (QueueSection, usize), to: (QueueSection, usize)) -> bool {
+        if from.0 == QueueSection::History || to.0 == QueueSection::History {
+            return false;
+        }
+        if from.1 >= self.section_mut(from.0).len() {
+            return false;
+        }
+
+        let t = self.section_mut(from.0).remove(from.1);
+        let dest = self.section_mut(to.0);
+        let idx = to.1.min(dest.len());
+        dest.insert(idx, t);
+        true
+    }
+
+    pub fn remove_from_queue(&mut self, section: QueueSection, index: usize) -> Option<u32> {
+        let v = self.section_mut(section);
+        if index < v.len() {
+            Some(v.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn clear_queue(&mut self, section: QueueSection) {
+        self.section_mut(section).clear();
+    }
+
+    // makes the track at index in section the current track. Jumping ahead skips explicit
+    // tracks before it and moves regular ones before it to the history, jumping back into the
+    // history returns everything after it to the front of the queues like repeated prev_track
+    pub fn jump_to(&mut self, section: QueueSection, index: usize) -> TrackType {
+        match section {
+            QueueSection::Explicit if index < self.exp_queue.len() => {
+                if let TrackType::RegQueueTrack(t) = self.curr_track {
+                    self.played_tracks.push(t);
+                }
+                self.exp_queue.drain(..index);
+                self.curr_track = TrackType::ExQueueTrack(self.exp_queue.remove(0));
+            }
+            QueueSection::Regular if index < self.reg_queue.len() => {
+                if let TrackType::RegQueueTrack(t) = self.curr_track {
+                    self.played_tracks.push(t);
+                }
+                let skipped = self.reg_queue.drain(..index).collect::<Vec<u32>>();
+                self.played_tracks.extend(skipped);
+                self.curr_track = TrackType::RegQueueTrack(self.reg_queue.remove(0));
+            }
+            QueueSection::History if index < self.played_tracks.len() => {
+                for _ in index..self.played_tracks.len() {
+                    self.prev_track();
+                }
+            }
+            _ => {}
+        }
+        debug!("{:?}", self);
+        self.curr_track
+    }
+
+    // drops every track for which keep returns false, ie: tracks deleted from the db
+    pub fn retain_tracks(&mut self, keep: impl Fn(u32) -> bool) {
+        self.reg_queue.retain(|&t| keep(t));
+        self.exp_queue.retain(|&t| keep(t));
+        self.played_tracks.retain(|&t| keep(t));
+        if let Some(t) = self.get_curr_track() {
+            if !keep(t) {
+                self.curr_track = TrackType::None;
+            }
+        }
     }
 
-    pub fn add_ordered_tracklist_to_reg_queue(&mut self, mut tids: Vec<u32>) {
+    // queues tids in order, starting after the current track and wrapping around to the tracks
+    // before it. If the current track is not in tids, all of them are queued
+    pub fn add_ordered_tracklist_to_reg_queue(&mut self, tids: Vec<u32>) {
         if let Some(tid) = self.get_curr_track() {
             self.empty_reg_queue();
-            let mut before_tracks = Vec::new();
-            loop {
-                let removed_tid = tids.remove(0);
-                if removed_tid == tid {
-                    break
+            let split = match tids.iter().position(|&x| x == tid) {
+                Some(i) => i + 1,
+                None => 0,
+            };
+
+            for &t in tids[split..].iter().chain(tids[..split].iter()) {
+                if t != tid {
+                    self.add_to_reg_queue(t);
                 }
-                before_tracks.push(removed_tid);
             }
+        }
+    }
+}
 
-            tids.append(&mut before_tracks);
-            for tid in tids.into_iter() {
-                self.add_to_reg_queue(tid);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_with(reg: &[u32], exp: &[u32], mode: RepeatMode) -> TrackQueue {
+        let mut q = TrackQueue::new();
+        q.reg_queue = reg.to_vec();
+        q.exp_queue = exp.to_vec();
+        q.set_repeat_mode(mode);
+        q
+    }
+
+    #[test]
+    fn empty_library_never_yields_a_track() {
+        for mode in [RepeatMode::Off, RepeatMode::One, RepeatMode::All] {
+            let mut q = queue_with(&[], &[], mode);
+            assert_eq!(q.next_track(), TrackType::None);
+            assert_eq!(q.auto_advance(), TrackType::None);
+            assert_eq!(q.prev_track(), TrackType::None);
+            assert!(q.upcoming_tracks(5).is_empty());
         }
     }
+
+    #[test]
+    fn none_to_reg_and_reg_to_reg() {
+        let mut q = queue_with(&[1, 2], &[], RepeatMode::Off);
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(2));
+        assert_eq!(q.played_tracks, vec![1]);
+    }
+
+    #[test]
+    fn explicit_queue_plays_first() {
+        let mut q = queue_with(&[1], &[10, 11], RepeatMode::Off);
+        assert_eq!(q.next_track(), TrackType::ExQueueTrack(10));
+        assert_eq!(q.next_track(), TrackType::ExQueueTrack(11));
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(1));
+        // explicitly queued tracks are not kept in the history
+        assert!(q.played_tracks.is_empty());
+    }
+
+    #[test]
+    fn reg_to_ex_and_back() {
+        let mut q = queue_with(&[1, 2], &[], RepeatMode::Off);
+        q.next_track();
+        q.add_to_queue(10);
+        assert_eq!(q.next_track(), TrackType::ExQueueTrack(10));
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(2));
+        assert_eq!(q.played_tracks, vec![1]);
+    }
+
+    #[test]
+    fn end_of_queue_stops_when_repeat_off() {
+        let mut q = queue_with(&[1], &[], RepeatMode::Off);
+        q.next_track();
+        assert_eq!(q.auto_advance(), TrackType::None);
+        assert_eq!(q.played_tracks, vec![1]);
+        assert!(q.reg_queue.is_empty());
+
+        let mut q = queue_with(&[], &[10], RepeatMode::Off);
+        q.next_track();
+        assert_eq!(q.next_track(), TrackType::None);
+    }
+
+    #[test]
+    fn end_of_queue_wraps_when_repeat_all() {
+        let mut q = queue_with(&[1, 2], &[], RepeatMode::All);
+        q.next_track();
+        q.next_track();
+        assert_eq!(q.auto_advance(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.reg_queue, vec![2]);
+        assert!(q.played_tracks.is_empty());
+    }
+
+    #[test]
+    fn repeat_all_with_only_explicit_tracks_stops() {
+        let mut q = queue_with(&[], &[10], RepeatMode::All);
+        assert_eq!(q.next_track(), TrackType::ExQueueTrack(10));
+        assert_eq!(q.next_track(), TrackType::None);
+    }
+
+    #[test]
+    fn repeat_one_stays_unless_skipped() {
+        let mut q = queue_with(&[1, 2], &[], RepeatMode::One);
+        q.next_track();
+        assert_eq!(q.auto_advance(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.upcoming_tracks(3), vec![1]);
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(2));
+        // skipping past the end wraps around
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(1));
+    }
+
+    #[test]
+    fn prev_track_transitions() {
+        let mut q = queue_with(&[1, 2], &[10], RepeatMode::Off);
+        assert_eq!(q.prev_track(), TrackType::None);
+
+        q.next_track();
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.next_track(), TrackType::RegQueueTrack(2));
+        assert_eq!(q.prev_track(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.reg_queue, vec![2]);
+
+        // ex -> reg puts the explicit track back at the front of its queue
+        q.add_to_queue(11);
+        q.next_track();
+        assert_eq!(q.curr_track, TrackType::ExQueueTrack(11));
+        assert_eq!(q.prev_track(), TrackType::RegQueueTrack(1));
+        assert_eq!(q.exp_queue, vec![11]);
+
+        // none -> reg after the queue ran out
+        q.exp_queue.clear();
+        q.next_track();
+        q.next_track();
+        assert_eq!(q.next_track(), TrackType::None);
+        assert_eq!(q.prev_track(), TrackType::RegQueueTrack(2));
+    }
+
+    #[test]
+    fn upcoming_matches_auto_advance() {
+        for mode in [RepeatMode::Off, RepeatMode::One, RepeatMode::All] {
+            let mut q = queue_with(&[1, 2, 3], &[10], mode);
+            q.next_track();
+            q.next_track();
+            let upcoming = q.upcoming_tracks(6);
+            let advanced = (0..upcoming.len())
+                .filter_map(|_| {
+                    q.auto_advance();
+                    q.get_curr_track()
+                })
+                .collect::<Vec<u32>>();
+            assert_eq!(upcoming, advanced, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn move_track_within_and_across_queues() {
+        let mut q = queue_with(&[1, 2, 3], &[10, 11], RepeatMode::Off);
+        assert!(q.move_track((QueueSection::Regular, 2), (QueueSection::Regular, 0)));
+        assert_eq!(q.reg_queue, vec![3, 1, 2]);
+        assert!(q.move_track((QueueSection::Regular, 0), (QueueSection::Explicit, 1)));
+        assert_eq!(q.exp_queue, vec![10, 3, 11]);
+        assert_eq!(q.reg_queue, vec![1, 2]);
+        assert!(q.move_track((QueueSection::Explicit, 0), (QueueSection::Explicit, 99)));
+        assert_eq!(q.exp_queue, vec![3, 11, 10]);
+
+        assert!(!q.move_track((QueueSection::Regular, 5), (QueueSection::Regular, 0)));
+        assert!(!q.move_track((QueueSection::History, 0), (QueueSection::Regular, 0)));
+    }
+
+    #[test]
+    fn remove_and_clear_queue() {
+        let mut q = queue_with(&[1, 2], &[10], RepeatMode::Off);
+        assert_eq!(q.remove_from_queue(QueueSection::Regular, 0), Some(1));
+        assert_eq!(q.remove_from_queue(QueueSection::Regular, 3), None);
+        q.clear_queue(QueueSection::Explicit);
+        assert!(q.exp_queue.is_empty());
+        assert_eq!(q.reg_queue, vec![2]);
+    }
+
+    #[test]
+    fn jump_to_queued_tracks() {
+        let mut q = queue_with(&[1, 2, 3, 4], &[10, 11], RepeatMode::Off);
+        q.next_track();
+        assert_eq!(q.jump_to(QueueSection::Explicit, 0), TrackType::ExQueueTrack(11));
+        assert!(q.exp_queue.is_empty());
+
+        assert_eq!(q.jump_to(QueueSection::Regular, 2), TrackType::RegQueueTrack(3));
+        assert_eq!(q.played_tracks, vec![1, 2]);
+        assert_eq!(q.reg_queue, vec![4]);
+
+        assert_eq!(q.jump_to(QueueSection::History, 0), TrackType::RegQueueTrack(1));
+        assert!(q.played_tracks.is_empty());
+        assert_eq!(q.reg_queue, vec![2, 3, 4]);
+
+        assert_eq!(q.jump_to(QueueSection::Regular, 9), TrackType::RegQueueTrack(1));
+    }
+
+    #[test]
+    fn ordered_tracklist_starts_after_current() {
+        let mut q = queue_with(&[], &[], RepeatMode::All);
+        q.curr_track = TrackType::RegQueueTrack(3);
+        q.add_ordered_tracklist_to_reg_queue(vec![1, 2, 3, 4]);
+        assert_eq!(q.reg_queue, vec![4, 1, 2]);
+
+        q.curr_track = TrackType::RegQueueTrack(9);
+        q.add_ordered_tracklist_to_reg_queue(vec![1, 2]);
+        assert_eq!(q.reg_queue, vec![1, 2]);
+    }
+
+    #[test]
+    fn retain_tracks_drops_missing_tracks() {
+        let mut q = queue_with(&[1, 2, 3], &[4], RepeatMode::All);
+        q.played_tracks = vec![5, 6];
+        q.curr_track = TrackType::RegQueueTrack(2);
+        q.retain_tracks(|t| t % 2 == 1);
+        assert_eq!(q.reg_queue, vec![1, 3]);
+        assert!(q.exp_queue.is_empty());
+        assert_eq!(q.played_tracks, vec![5]);
+        assert_eq!(q.curr_track, TrackType::None);
+    }
 }
@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
 use crate::ui::toggle_button::toggle;
+use crate::playback::{curr_track_duration, seek_to};
 use crate::utils::duration_to_str;
 use crate::{state::state::AppState, utils::get_album_cover};
 use eframe::egui::load::Bytes;
-use eframe::egui::{self, ImageSource, ProgressBar, Ui};
+use eframe::egui::{self, ImageSource, Ui};
 
-pub fn draw_currtrack_panel(app_state: &AppState, ui: &mut Ui) {
+pub fn draw_currtrack_panel(app_state: &mut AppState, ui: &mut Ui) {
     let p = app_state.get_curr_track_path();
     if p.is_none() {
         egui::TopBottomPanel::bottom("bottom_left_panel")
@@ -29,7 +30,7 @@ pub fn draw_currtrack_panel(app_state: &AppState, ui: &mut Ui) {
             ui.add(
                 egui::Image::new(ImageSource::Bytes {
                     uri: std::borrow::Cow::Owned(uri_str),
-                    bytes: Bytes::Shared(Arc::clone(&app_state.curr_albumcover.as_ref().unwrap())),
+                    bytes: Bytes::Shared(Arc::clone(app_state.curr_albumcover.as_ref().unwrap())),
                 })
                 .rounding(5.0)
                 .max_size(egui::Vec2 { x: 300.0, y: 300.0 }),
@@ -39,15 +40,7 @@ pub fn draw_currtrack_panel(app_state: &AppState, ui: &mut Ui) {
 
             let s = app_state.curr_trackinfo.clone().unwrap();
 
-            let elapsed_time = app_state.track_clock.elapsed().as_secs_f32();
-            let total_time = s.duration as f32;
-            let display_text = format!(
-                "{}/{}",
-                duration_to_str(elapsed_time as u32),
-                duration_to_str(total_time as u32)
-            );
-
-            ui.add(ProgressBar::new(elapsed_time / total_time).text(display_text));
+            draw_seek_bar(app_state, ui);
 
             ui.add_space(5.0);
             ui.add(egui::Label::new(egui::RichText::new(&s.name).strong()).truncate(true));
@@ -62,3 +55,31 @@ pub fn draw_currtrack_panel(app_state: &AppState, ui: &mut Ui) {
             }
         });
 }
+
+// slider showing the player's position, dragging it only seeks once the drag is released
+fn draw_seek_bar(app_state: &mut AppState, ui: &mut Ui) {
+    let total_time = curr_track_duration(app_state);
+    let mut pos = app_state
+        .seek_scrub
+        .unwrap_or(app_state.player.position())
+        .min(total_time);
+
+    ui.spacing_mut().slider_width = ui.available_width();
+    let response = ui.add(
+        egui::Slider::new(&mut pos, 0.0..=total_time.max(1.0))
+            .show_value(false)
+            .trailing_fill(true),
+    );
+    ui.label(format!(
+        "{}/{}",
+        duration_to_str(pos as u32),
+        duration_to_str(total_time as u32)
+    ));
+
+    if response.dragged() {
+        app_state.seek_scrub = Some(pos);
+    } else if response.drag_stopped() || response.changed() {
+        app_state.seek_scrub = None;
+        seek_to(app_state, pos);
+    }
+}
@@ -0,0 +1,133 @@
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+
+use crate::{db::downloads::DownloadStatus, state::state::AppState};
+
+enum DownloadAction {
+    Retry(u32),
+    Cancel(u32),
+    Remove(u32),
+}
+
+// every download job, with its progress and what went wrong for the failed ones
+pub fn display_downloads_window(app_state: &mut AppState, ctx: &egui::Context) {
+    if !app_state.show_downloads {
+        return;
+    }
+
+    let mut open = true;
+    let mut actions = Vec::new();
+    egui::Window::new("Downloads")
+        .open(&mut open)
+        .collapsible(false)
+        .default_width(700.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("At once: ");
+                let mut n = app_state.config.max_concurrent_downloads;
+                ui.add(egui::DragValue::new(&mut n).clamp_range(1..=8));
+                if n != app_state.config.max_concurrent_downloads {
+                    app_state.config.max_concurrent_downloads = n;
+                    app_state.config.save();
+                }
+
+                ui.separator();
+                if ui.button(" Retry Failed ").clicked() {
+                    for j in app_state.downloads.jobs.iter() {
+                        if j.status == DownloadStatus::Failed {
+                            actions.push(DownloadAction::Retry(j.id));
+                        }
+                    }
+                }
+                if ui.button(" Clear Done ").clicked() {
+                    app_state.downloads.clear_done(&mut app_state.trackdb);
+                }
+            });
+            ui.separator();
+
+            if app_state.downloads.jobs.is_empty() {
+                ui.label("No downloads");
+                return;
+            }
+            TableBuilder::new(ui)
+                .striped(true)
+                .max_scroll_height(400.0)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::initial(280.0).clip(true))
+                .column(Column::initial(120.0).clip(true))
+                .column(Column::exact(150.0))
+                .column(Column::remainder())
+                .header(20.0, |mut header| {
+                    for title in ["Track", "Playlist", "Status", ""] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    let jobs = &app_state.downloads.jobs;
+                    body.rows(20.0, jobs.len(), |mut row| {
+                        let job = &jobs[row.index()];
+                        row.col(|ui| {
+                            ui.label(format!(
+                                "{} - {}",
+                                job.result.name,
+                                job.result.artists.join(", ")
+                            ));
+                        });
+                        row.col(|ui| {
+                            ui.label(&job.playlist);
+                        });
+                        row.col(|ui| match job.status {
+                            DownloadStatus::Downloading | DownloadStatus::Tagging => {
+                                let bar = egui::ProgressBar::new(job.progress / 100.0)
+                                    .text(job.status.as_str());
+                                ui.add(bar);
+                            }
+                            DownloadStatus::Failed => {
+                                let label = egui::RichText::new("failed").color(Color32::RED);
+                                let error = job.error.clone().unwrap_or_default();
+                                ui.label(label).on_hover_text(error);
+                            }
+                            _ => {
+                                let mut status = job.status.as_str().to_string();
+                                // the error of the last attempt shows while it waits for a retry
+                                if job.status == DownloadStatus::Queued && job.attempts > 0 {
+                                    status = format!("queued, retry {}", job.attempts);
+                                }
+                                let label = ui.label(status);
+                                if let Some(e) = &job.error {
+                                    label.on_hover_text(e);
+                                }
+                            }
+                        });
+                        row.col(|ui| {
+                            if !job.status.is_finished() {
+                                if ui.small_button("Cancel").clicked() {
+                                    actions.push(DownloadAction::Cancel(job.id));
+                                }
+                                return;
+                            }
+                            if job.status != DownloadStatus::Done
+                                && ui.small_button("Retry").clicked()
+                            {
+                                actions.push(DownloadAction::Retry(job.id));
+                            }
+                            if ui.small_button("Remove").clicked() {
+                                actions.push(DownloadAction::Remove(job.id));
+                            }
+                        });
+                    });
+                });
+        });
+
+    let downloads = &mut app_state.downloads;
+    for a in actions {
+        match a {
+            DownloadAction::Retry(id) => downloads.retry(&mut app_state.trackdb, id),
+            DownloadAction::Cancel(id) => downloads.cancel(&mut app_state.trackdb, id),
+            DownloadAction::Remove(id) => downloads.remove(&mut app_state.trackdb, id),
+        }
+    }
+    app_state.show_downloads = open;
+}
@@ -1,4 +1,7 @@
 use crate::state::filter_state::F1State;
+use crate::state::playlist_file_state::PlaylistFileAction;
+use crate::ui::playlist_sync::start_sync;
+use crate::ui::smart_playlist_editor::open_smart_playlist_editor;
 use crate::ui::toggle_button::toggle;
 use crate::F2_PANEL_ROW_HEIGHT;
 use crate::{state::state::AppState, utils::f1_state_enum_to_str};
@@ -10,16 +13,10 @@ use egui_extras::{Column, TableBuilder};
 pub fn draw_f1_panel(app_state: &mut AppState, ui: &mut Ui) {
     if app_state.f1_state != app_state.prev_state.f1_state {
         app_state.prev_state.f1_state = app_state.f1_state.clone();
-        app_state.f2_state = (*app_state
+        app_state.f2_state = app_state
             .trackdb
-            .track_filter_cache
-            .get(&app_state.f1_state)
-            .unwrap()
-            .keys()
-            .collect::<Vec<&String>>()
-            .get(0)
-            .unwrap())
-        .clone();
+            .filter_values(&app_state.f1_state)
+            .swap_remove(0);
     }
 
     egui::TopBottomPanel::top("top_left_panel")
@@ -28,84 +25,65 @@ pub fn draw_f1_panel(app_state: &mut AppState, ui: &mut Ui) {
         .show_inside(ui, |ui| {
             let available_height = 150.0;
             let available_width = ui.available_width();
+            let categories = [
+                ("Playlists", F1State::Playlists),
+                ("Artists", F1State::Artists),
+                ("Albums", F1State::Albums),
+                ("Genres", F1State::Genres),
+                ("Years", F1State::Years),
+                ("All", F1State::All),
+            ];
+            let button_height = available_height / categories.len() as f32;
             ui.vertical_centered(|ui| {
-                if ui
-                    .add(egui::Button::new("Playlists").min_size(egui::Vec2 {
-                        x: available_width,
-                        y: available_height / 4.0,
-                    }))
-                    .clicked()
-                {
-                    app_state.f1_state = F1State::Playlists;
-                };
-                if ui
-                    .add(egui::Button::new("Artists").min_size(egui::Vec2 {
-                        x: available_width,
-                        y: available_height / 4.0,
-                    }))
-                    .clicked()
-                {
-                    app_state.f1_state = F1State::Artists;
-                };
-                if ui
-                    .add(egui::Button::new("Albums").min_size(egui::Vec2 {
-                        x: available_width,
-                        y: available_height / 4.0,
-                    }))
-                    .clicked()
-                {
-                    app_state.f1_state = F1State::Albums;
-                };
-                if ui
-                    .add(egui::Button::new("All").min_size(egui::Vec2 {
-                        x: available_width,
-                        y: available_height / 4.0,
-                    }))
-                    .clicked()
-                {
-                    app_state.f1_state = F1State::All;
-                };
+                for (label, f1_state) in categories {
+                    if ui
+                        .add(egui::Button::new(label).min_size(egui::Vec2 {
+                            x: available_width,
+                            y: button_height,
+                        }))
+                        .clicked()
+                    {
+                        app_state.f1_state = f1_state;
+                    };
+                }
             })
         });
 }
 
 pub fn draw_f2_panel(app_state: &mut AppState, ui: &mut Ui) {
-    let f2_values = app_state
-        .trackdb
-        .track_filter_cache
-        .get(&app_state.f1_state)
-        .unwrap()
-        .keys()
-        .map(|x| x.clone())
-        .collect::<Vec<String>>();
+    let library_changed = app_state
+        .filter_cache
+        .refresh(&app_state.trackdb, &app_state.f1_state);
+    let f2_values = app_state.filter_cache.values.clone();
+    let smart_playlists = app_state.filter_cache.smart_playlists.clone();
 
-    if app_state.f1_state != app_state.prev_state.f2_state.0
-        || app_state.f2_state != app_state.prev_state.f2_state.1
-    {
-        app_state.prev_state.f2_state.0 = app_state.f1_state.clone();
-        app_state.prev_state.f2_state.1 = app_state.f2_state.clone();
+    let selection_changed = app_state.f1_state != app_state.prev_state.f2_state.0
+        || app_state.f2_state != app_state.prev_state.f2_state.1;
+    if selection_changed {
+        app_state.trackdb.reshuffle_smart_playlists();
+    }
+    // smart playlists change as the library does, so they're checked again when it does
+    let smart_selected = smart_playlists.contains(&app_state.f2_state);
 
-        app_state.tracklist_state.empty();
-        let tid_iter = match app_state
+    if selection_changed || (smart_selected && library_changed) {
+        let tids = app_state
             .trackdb
-            .track_filter_cache
-            .get(&app_state.f1_state)
-            .unwrap()
-            .get(&app_state.f2_state)
-        {
-            Some(x) => x.iter(),
-            None => return,
-        };
+            .filter_tracks(&app_state.f1_state, &app_state.f2_state);
+        let shown = app_state.tracklist_state.items.iter().map(|x| &x.id);
+        if selection_changed || !tids.iter().eq(shown) {
+            app_state.prev_state.f2_state.0 = app_state.f1_state.clone();
+            app_state.prev_state.f2_state.1 = app_state.f2_state.clone();
 
-        for tid in tid_iter {
-            let tinfo = app_state.trackdb.trackmap.get(tid).unwrap();
-            app_state.tracklist_state.add_item(
-                tid.clone(),
-                tinfo.name.clone(),
-                tinfo.artists.clone().unwrap_or(Vec::new()),
-                tinfo.album.clone().unwrap_or(String::new()),
-                tinfo.duration,
-            )
+            app_state.tracklist_state.empty();
+            for tid in tids.iter() {
+                let tinfo = app_state.trackdb.get_track(*tid).unwrap();
+                app_state.tracklist_state.add_item(tinfo);
+            }
+            let synced = app_state.filter_cache.sources.contains_key(&app_state.f2_state);
+            if app_state.f1_state == F1State::Playlists && synced {
+                let stale = app_state.trackdb.stale_tracks(&app_state.f2_state);
+                app_state.tracklist_state.stale = stale.into_iter().collect();
+            }
         }
     }
 
@@ -126,11 +104,25 @@ pub fn draw_f2_panel(app_state: &mut AppState, ui: &mut Ui) {
                     if app_state.f2_state == **curr_row {
                         row.set_selected(true);
                     }
+                    let is_smart = smart_playlists.contains(curr_row);
+                    let source = app_state.filter_cache.sources.get(curr_row).cloned();
                     row.col(|ui| {
-                        ui.add(egui::Label::new(curr_row).selectable(false));
+                        let label = match (is_smart, &source) {
+                            (true, _) => format!("⚡ {}", curr_row),
+                            (false, Some(_)) => format!("🔄 {}", curr_row),
+                            (false, None) => curr_row.to_string(),
+                        };
+                        ui.add(egui::Label::new(label).selectable(false));
                     });
 
-                    let response = row.response();
+                    let mut response = row.response();
+                    if let Some(s) = &source {
+                        let synced = match (&s.last_synced, &s.summary) {
+                            (Some(t), Some(summary)) => format!("Last synced {} UTC\n{}", t, summary),
+                            _ => "Not synced yet".to_string(),
+                        };
+                        response = response.on_hover_text(format!("From {}\n{}", s.url, synced));
+                    }
                     if response.clicked() {
                         app_state.f2_state = (*curr_row).clone();
                     }
@@ -148,6 +140,30 @@ pub fn draw_f2_panel(app_state: &mut AppState, ui: &mut Ui) {
                                     app_state.new_playlist_name = String::new();
                                 }
                             });
+                            if ui.button(" Create Smart Playlist ").clicked() {
+                                open_smart_playlist_editor(app_state, None);
+                                ui.close_menu();
+                            }
+                            if is_smart && ui.button(" Edit Smart Playlist ").clicked() {
+                                open_smart_playlist_editor(app_state, Some(curr_row));
+                                ui.close_menu();
+                            }
+                            if source.is_some() {
+                                if ui.button(" Sync ").clicked() {
+                                    start_sync(app_state, vec![curr_row.clone()]);
+                                    ui.close_menu();
+                                }
+                                if ui.button(" Stop Syncing ").clicked() {
+                                    app_state.trackdb.set_playlist_source(curr_row, None);
+                                    ui.close_menu();
+                                }
+                            }
+                            if ui.button(" Export Playlist ").clicked() {
+                                app_state
+                                    .playlist_file
+                                    .open(PlaylistFileAction::Export(Some(curr_row.clone())));
+                                ui.close_menu();
+                            }
                             ui.menu_button(" Delete Playlist ", |ui| {
                                 if ui.button("Confirm").clicked() {
                                     app_state.f2_state = String::from("Liked");
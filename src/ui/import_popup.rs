@@ -0,0 +1,117 @@
+use std::{path::PathBuf, thread};
+
+use crate::{
+    db::{TrackDB, TrackFileMode},
+    import::import_folder,
+    state::{filter_state::F1State, state::AppState},
+    utils::get_db_file_path,
+};
+use eframe::egui;
+
+use super::ui::sync_tracklist_with_db;
+
+fn start_import(app_state: &mut AppState) {
+    let folder = PathBuf::from(app_state.import_state.folder.trim());
+    let mode = app_state.import_state.mode;
+    let playlist = app_state.import_state.playlist.clone();
+
+    // the import gets its own connection so the ui isn't locked out of the db meanwhile
+    app_state.import_state.report = None;
+    app_state.import_state.job = Some(thread::spawn(move || {
+        let mut tdb = TrackDB::open(&get_db_file_path());
+        import_folder(&mut tdb, &folder, mode, playlist.as_deref())
+    }));
+}
+
+// picks up the result of a finished import
+pub fn check_import_progress(app_state: &mut AppState) {
+    let finished = match &app_state.import_state.job {
+        Some(job) => job.is_finished(),
+        None => false,
+    };
+    if !finished {
+        return;
+    }
+
+    let job = app_state.import_state.job.take().unwrap();
+    let report = job.join().unwrap().map_err(|e| format!("{:#}", e));
+    if let Ok(r) = &report {
+        app_state
+            .notification
+            .set_message(format!("Imported {} tracks", r.imported), Some(3));
+        sync_tracklist_with_db(app_state);
+    }
+    app_state.import_state.report = Some(report);
+}
+
+pub fn display_import_popup(app_state: &mut AppState, ctx: &egui::Context) {
+    if !app_state.import_state.show_popup {
+        return;
+    }
+
+    egui::Window::new("Import folder")
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let running = app_state.import_state.job.is_some();
+
+            ui.horizontal(|ui| {
+                ui.label("Folder: ");
+                ui.text_edit_singleline(&mut app_state.import_state.folder);
+            });
+
+            ui.horizontal(|ui| {
+                let mode = &mut app_state.import_state.mode;
+                ui.radio_value(mode, TrackFileMode::Copy, "Copy into music dir");
+                ui.radio_value(mode, TrackFileMode::Reference, "Reference in place");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Add to playlist: ");
+                let selected = &mut app_state.import_state.playlist;
+                egui::ComboBox::from_id_source("import_playlist")
+                    .selected_text(selected.clone().unwrap_or("None".to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(selected, None, "None");
+                        for p in app_state.trackdb.playlist_names() {
+                            ui.selectable_value(selected, Some(p.clone()), p);
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                let can_import = !running && !app_state.import_state.folder.trim().is_empty();
+                if ui.add_enabled(can_import, egui::Button::new("Import")).clicked() {
+                    start_import(app_state);
+                }
+                if ui.button("Close").clicked() {
+                    app_state.import_state.show_popup = false;
+                }
+                if running {
+                    ui.spinner();
+                    ui.label("Importing...");
+                }
+            });
+
+            match &app_state.import_state.report {
+                Some(Ok(report)) => {
+                    ui.separator();
+                    ui.label(format!("Imported {} tracks", report.imported));
+                    if !report.skipped.is_empty() {
+                        ui.collapsing(format!("Skipped {} files", report.skipped.len()), |ui| {
+                            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                                for (p, reason) in report.skipped.iter() {
+                                    ui.label(format!("{} : {}", p.display(), reason));
+                                }
+                            });
+                        });
+                    }
+                }
+                Some(Err(e)) => {
+                    ui.separator();
+                    ui.label(format!("Import failed: {}", e));
+                }
+                None => {}
+            }
+        });
+}
@@ -4,3 +4,11 @@ pub mod filter_panel;
 pub mod currtrack_panel;
 pub mod visualizer_panel;
 pub mod toggle_button;
+pub mod transport_panel;
+pub mod queue_panel;
+pub mod import_popup;
+pub mod tag_editor;
+pub mod smart_playlist_editor;
+pub mod playlist_file_popup;
+pub mod playlist_sync;
+pub mod downloads_panel;
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use crate::{
+    state::{playlist_file_state::PlaylistFileAction, state::AppState},
+    NOTIFICATION_TIMEOUT_S,
+};
+use eframe::egui;
+
+fn export(app_state: &mut AppState, playlist: Option<&str>) {
+    let state = &app_state.playlist_file;
+    let dest = PathBuf::from(state.path.trim());
+    let res = match playlist {
+        Some(p) => app_state.trackdb.export_playlist(p, &dest, state.relative),
+        None => app_state.trackdb.export_tracks(
+            &app_state.trackqueue.queued_tracks(),
+            "Queue",
+            &dest,
+            state.relative,
+        ),
+    };
+    match res {
+        Ok(()) => {
+            app_state.playlist_file.close();
+            app_state.notification.set_message(
+                format!("Exported to {}", dest.display()),
+                Some(NOTIFICATION_TIMEOUT_S),
+            );
+        }
+        Err(e) => app_state.playlist_file.report = Some(Err(format!("{:#}", e))),
+    }
+}
+
+fn import(app_state: &mut AppState) {
+    let state = &app_state.playlist_file;
+    let src = PathBuf::from(state.path.trim());
+    let name = Some(state.playlist.trim()).filter(|n| !n.is_empty());
+    let report = app_state
+        .trackdb
+        .import_playlist_file(&src, name)
+        .map_err(|e| format!("{:#}", e));
+    if let Ok(r) = &report {
+        app_state.notification.set_message(
+            format!("Added {} tracks to {}", r.added, r.playlist),
+            Some(NOTIFICATION_TIMEOUT_S),
+        );
+        // the tracklist is rebuilt if it shows the playlist
+        app_state.prev_state.f2_state.1 = String::new();
+    }
+    app_state.playlist_file.report = Some(report);
+}
+
+pub fn display_playlist_file_popup(app_state: &mut AppState, ctx: &egui::Context) {
+    let action = match &app_state.playlist_file.action {
+        Some(a) => a.clone(),
+        None => return,
+    };
+    let title = match &action {
+        PlaylistFileAction::Export(Some(p)) => format!("Export {}", p),
+        PlaylistFileAction::Export(None) => "Export queue".to_string(),
+        PlaylistFileAction::Import => "Import playlist file".to_string(),
+    };
+
+    let mut run = false;
+    egui::Window::new(title)
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let state = &mut app_state.playlist_file;
+            ui.horizontal(|ui| {
+                ui.label("File (.m3u8 or .xspf): ");
+                ui.text_edit_singleline(&mut state.path);
+            });
+            match action {
+                PlaylistFileAction::Export(_) => {
+                    ui.checkbox(&mut state.relative, "Relative paths (m3u8 only)");
+                }
+                PlaylistFileAction::Import => {
+                    ui.horizontal(|ui| {
+                        ui.label("Into playlist: ");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut state.playlist)
+                                .hint_text("named after the file"),
+                        );
+                    });
+                }
+            }
+
+            ui.horizontal(|ui| {
+                let label = match action {
+                    PlaylistFileAction::Export(_) => "Export",
+                    PlaylistFileAction::Import => "Import",
+                };
+                let can_run = !state.path.trim().is_empty();
+                if ui.add_enabled(can_run, egui::Button::new(label)).clicked() {
+                    run = true;
+                }
+                if ui.button("Close").clicked() {
+                    state.close();
+                }
+            });
+
+            match &state.report {
+                Some(Ok(report)) => {
+                    ui.separator();
+                    ui.label(format!("Added {} tracks to {}", report.added, report.playlist));
+                    if !report.unresolved.is_empty() {
+                        let title = format!("{} entries not found", report.unresolved.len());
+                        ui.collapsing(title, |ui| {
+                            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                                for e in report.unresolved.iter() {
+                                    ui.label(e);
+                                }
+                            });
+                        });
+                    }
+                }
+                Some(Err(e)) => {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                None => {}
+            }
+        });
+
+    if run {
+        match action {
+            PlaylistFileAction::Export(p) => export(app_state, p.as_deref()),
+            PlaylistFileAction::Import => import(app_state),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use log::warn;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+
+use crate::{
+    db::TrackInfo,
+    spotdl::SearchResult,
+    spotify_link::{link_tracks, SpotifyLink},
+    state::state::AppState,
+    NOTIFICATION_TIMEOUT_S,
+};
+
+use super::ui::download_results;
+
+// Fetches the remote tracks of the playlists on the tokio runtime, they're synced once it's done
+pub fn start_sync(app_state: &mut AppState, playlists: Vec<String>) {
+    if app_state.sync_job.is_some() || playlists.is_empty() {
+        return;
+    }
+    let sources = playlists
+        .into_iter()
+        .filter_map(|p| app_state.trackdb.playlist_source(&p).map(|s| (p, s.url)))
+        .collect::<Vec<(String, String)>>();
+    app_state
+        .notification
+        .set_message(format!("Syncing {} playlists...", sources.len()), None);
+
+    let spotify = app_state.spt_creds.clone();
+    let ctx = app_state.ctx.clone();
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let mut results = Vec::new();
+        for (p, url) in sources {
+            let res = match SpotifyLink::parse(&url) {
+                Some(link) => link_tracks(&link, &spotify).await,
+                None => Err(anyhow!("{} is not a spotify playlist, album or artist", url)),
+            };
+            results.push((p, res));
+        }
+        let _ = tx.send(results);
+        if let Some(ctx) = ctx {
+            ctx.request_repaint();
+        }
+    });
+    app_state.sync_job = Some(rx);
+}
+
+// syncs the playlists that weren't synced within the configured interval
+pub fn sync_due_playlists(app_state: &mut AppState) {
+    if let Some(hours) = app_state.config.sync_interval_hours {
+        let due = app_state.trackdb.synced_playlists(Some(hours));
+        start_sync(app_state, due);
+    }
+}
+
+// Syncs playlist with its remote tracks, downloading the ones missing from the library
+pub fn apply_sync(app_state: &mut AppState, playlist: &str, remote: Vec<SearchResult>) {
+    let infos = remote
+        .iter()
+        .map(|r| r.to_track_info())
+        .collect::<Vec<TrackInfo>>();
+    let report =
+        app_state
+            .trackdb
+            .sync_playlist(playlist, &infos, app_state.config.sync_remove_missing);
+    // the tracklist is rebuilt if it shows the playlist
+    app_state.prev_state.f2_state.1 = String::new();
+
+    let new = report
+        .to_download
+        .iter()
+        .map(|i| remote[*i].clone())
+        .collect::<Vec<SearchResult>>();
+    if new.is_empty() {
+        app_state.notification.set_message(
+            format!("Synced {} : {}", playlist, report.summary()),
+            Some(NOTIFICATION_TIMEOUT_S),
+        );
+    } else {
+        download_results(app_state, new, playlist.to_string());
+    }
+}
+
+// picks up the remote tracks once they're all fetched
+pub fn check_sync_progress(app_state: &mut AppState) {
+    let results = match app_state.sync_job.as_mut().map(|rx| rx.try_recv()) {
+        Some(Ok(results)) => results,
+        Some(Err(TryRecvError::Closed)) => {
+            warn!("The sync was interrupted");
+            Vec::new()
+        }
+        Some(Err(TryRecvError::Empty)) | None => return,
+    };
+    app_state.sync_job = None;
+    for (playlist, res) in results {
+        match res {
+            Ok(remote) => apply_sync(app_state, &playlist, remote.tracks),
+            Err(e) => {
+                warn!("Could not sync {} : {:#}", playlist, e);
+                app_state.notification.set_message(
+                    format!("Could not sync {} : {}", playlist, e),
+                    Some(NOTIFICATION_TIMEOUT_S),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::playback::play_track;
+use crate::state::playlist_file_state::PlaylistFileAction;
+use crate::state::state::AppState;
+use crate::track_queue::QueueSection;
+use eframe::egui::{self, Ui};
+
+// payload carried while dragging a queued track around
+#[derive(Clone, Copy)]
+struct QueueDragItem(QueueSection, usize);
+
+enum QueueAction {
+    Jump(QueueSection, usize),
+    Remove(QueueSection, usize),
+    Move((QueueSection, usize), (QueueSection, usize)),
+    Clear(QueueSection),
+    Export,
+}
+
+fn track_label(app_state: &AppState, tid: u32) -> String {
+    match app_state.trackdb.get_track(tid) {
+        Some(t) => match &t.artists {
+            Some(ar) => format!("{} - {}", t.name, ar.join(", ")),
+            None => t.name,
+        },
+        None => format!("Unknown track {}", tid),
+    }
+}
+
+// accepts a dragged track dropped onto response, inserting it at index in section
+fn handle_drop(
+    response: &egui::Response,
+    ui: &Ui,
+    section: QueueSection,
+    index: usize,
+    action: &mut Option<QueueAction>,
+) {
+    if response.dnd_hover_payload::<QueueDragItem>().is_some() {
+        let rect = response.rect;
+        ui.painter()
+            .hline(rect.x_range(), rect.top(), ui.visuals().selection.stroke);
+    }
+
+    if let Some(item) = response.dnd_release_payload::<QueueDragItem>() {
+        let QueueDragItem(from_section, from_index) = *item;
+        // removing the dragged track first shifts everything after it up by one
+        let to_index = if from_section == section && from_index < index {
+            index - 1
+        } else {
+            index
+        };
+        *action = Some(QueueAction::Move(
+            (from_section, from_index),
+            (section, to_index),
+        ));
+    }
+}
+
+fn draw_section(
+    app_state: &AppState,
+    ui: &mut Ui,
+    title: &str,
+    section: QueueSection,
+    tids: &[u32],
+    action: &mut Option<QueueAction>,
+) {
+    let header = ui.horizontal(|ui| {
+        ui.strong(title);
+        if section != QueueSection::History && !tids.is_empty() {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("Clear").clicked() {
+                    *action = Some(QueueAction::Clear(section));
+                }
+            });
+        }
+    });
+    if section != QueueSection::History {
+        handle_drop(&header.response, ui, section, 0, action);
+    }
+
+    if tids.is_empty() {
+        ui.weak("Nothing here");
+    }
+
+    // history is listed most recent first
+    let indices: Vec<usize> = match section {
+        QueueSection::History => (0..tids.len()).rev().collect(),
+        _ => (0..tids.len()).collect(),
+    };
+
+    for i in indices {
+        let row = ui.horizontal(|ui| {
+            if ui.small_button("▶").on_hover_text("Play now").clicked() {
+                *action = Some(QueueAction::Jump(section, i));
+            }
+            if section != QueueSection::History
+                && ui.small_button("✕").on_hover_text("Remove").clicked()
+            {
+                *action = Some(QueueAction::Remove(section, i));
+            }
+
+            let label = egui::Label::new(track_label(app_state, tids[i]))
+                .truncate(true)
+                .selectable(false);
+            if section == QueueSection::History {
+                ui.add(label);
+            } else {
+                let id = egui::Id::new(("queue_item", title, i));
+                ui.dnd_drag_source(id, QueueDragItem(section, i), |ui| {
+                    ui.add(label);
+                });
+            }
+        });
+        if section != QueueSection::History {
+            handle_drop(&row.response, ui, section, i, action);
+        }
+    }
+}
+
+pub fn draw_queue_panel(app_state: &mut AppState, ui: &mut Ui) {
+    let mut action = None;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        let q = &app_state.trackqueue;
+        let (exp, reg, played) = (
+            q.exp_queue.clone(),
+            q.reg_queue.clone(),
+            q.played_tracks.clone(),
+        );
+
+        if let Some(tid) = q.get_curr_track() {
+            ui.horizontal(|ui| {
+                ui.strong("Now Playing");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let export = ui.small_button("Export");
+                    if export.on_hover_text("Save as a playlist file").clicked() {
+                        action = Some(QueueAction::Export);
+                    }
+                });
+            });
+            ui.add(egui::Label::new(track_label(app_state, tid)).truncate(true));
+            ui.separator();
+        }
+
+        draw_section(
+            app_state,
+            ui,
+            "Up Next",
+            QueueSection::Explicit,
+            &exp,
+            &mut action,
+        );
+        ui.separator();
+        draw_section(
+            app_state,
+            ui,
+            "Next From Tracklist",
+            QueueSection::Regular,
+            &reg,
+            &mut action,
+        );
+        ui.separator();
+        draw_section(
+            app_state,
+            ui,
+            "History",
+            QueueSection::History,
+            &played,
+            &mut action,
+        );
+    });
+
+    let q = &mut app_state.trackqueue;
+    match action {
+        Some(QueueAction::Jump(section, i)) => {
+            q.jump_to(section, i);
+            play_track(app_state);
+        }
+        Some(QueueAction::Remove(section, i)) => {
+            q.remove_from_queue(section, i);
+        }
+        Some(QueueAction::Move(from, to)) => {
+            q.move_track(from, to);
+        }
+        Some(QueueAction::Clear(section)) => q.clear_queue(section),
+        Some(QueueAction::Export) => app_state.playlist_file.open(PlaylistFileAction::Export(None)),
+        None => {}
+    }
+}
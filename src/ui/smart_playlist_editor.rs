@@ -0,0 +1,151 @@
+use crate::{
+    db::smart::{Rule, RuleField, SmartOrder, SmartPlaylist},
+    state::{filter_state::F1State, state::AppState},
+    NOTIFICATION_TIMEOUT_S,
+};
+use eframe::egui;
+
+pub fn open_smart_playlist_editor(app_state: &mut AppState, name: Option<&str>) {
+    match name.and_then(|n| app_state.trackdb.get_smart_playlist(n)) {
+        Some(sp) => app_state
+            .smart_playlist_editor
+            .open(sp, name.map(|n| n.to_string())),
+        None => app_state
+            .smart_playlist_editor
+            .open(SmartPlaylist::new(String::new()), None),
+    }
+}
+
+fn save_smart_playlist(app_state: &mut AppState) {
+    let state = &mut app_state.smart_playlist_editor;
+    let sp = state.editing.clone().unwrap();
+    if let Err(e) = app_state
+        .trackdb
+        .save_smart_playlist(&sp, state.prev_name.as_deref())
+    {
+        state.error = Some(e);
+        return;
+    }
+    state.close();
+
+    // show it, and have the tracklist rebuilt if it was already shown
+    app_state.f1_state = F1State::Playlists;
+    app_state.f2_state = sp.name.clone();
+    app_state.prev_state.f2_state.1 = String::new();
+    app_state
+        .notification
+        .set_message(format!("Saved {}", sp.name), Some(NOTIFICATION_TIMEOUT_S));
+}
+
+fn draw_rule(ui: &mut egui::Ui, idx: usize, rule: &mut Rule) -> bool {
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        let prev_field = rule.field;
+        egui::ComboBox::from_id_source(("smart_rule_field", idx))
+            .selected_text(rule.field.name())
+            .show_ui(ui, |ui| {
+                for f in RuleField::ALL {
+                    ui.selectable_value(&mut rule.field, f, f.name());
+                }
+            });
+        // ops differ between fields
+        if rule.field != prev_field && !rule.field.ops().contains(&rule.op) {
+            rule.op = rule.field.ops()[0];
+        }
+
+        egui::ComboBox::from_id_source(("smart_rule_op", idx))
+            .selected_text(rule.op.name())
+            .show_ui(ui, |ui| {
+                for op in rule.field.ops() {
+                    ui.selectable_value(&mut rule.op, *op, op.name());
+                }
+            });
+        ui.add(egui::TextEdit::singleline(&mut rule.value).desired_width(150.0));
+        if ui.button("✖").clicked() {
+            remove = true;
+        }
+    });
+    remove
+}
+
+pub fn display_smart_playlist_editor(app_state: &mut AppState, ctx: &egui::Context) {
+    if app_state.smart_playlist_editor.editing.is_none() {
+        return;
+    }
+
+    let mut save = false;
+    egui::Window::new("Smart playlist")
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let state = &mut app_state.smart_playlist_editor;
+            let sp = state.editing.as_mut().unwrap();
+
+            ui.horizontal(|ui| {
+                ui.label("Name: ");
+                ui.text_edit_singleline(&mut sp.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Match");
+                ui.radio_value(&mut sp.match_all, true, "all");
+                ui.radio_value(&mut sp.match_all, false, "any");
+                ui.label("of the rules");
+            });
+
+            ui.separator();
+            let mut remove_idx = None;
+            for (i, rule) in sp.rules.iter_mut().enumerate() {
+                if draw_rule(ui, i, rule) {
+                    remove_idx = Some(i);
+                }
+            }
+            if let Some(i) = remove_idx {
+                sp.rules.remove(i);
+            }
+            if ui.button("Add rule").clicked() {
+                sp.rules.push(Rule {
+                    field: RuleField::Title,
+                    op: RuleField::Title.ops()[0],
+                    value: String::new(),
+                });
+            }
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Order by: ");
+                egui::ComboBox::from_id_source("smart_order")
+                    .selected_text(sp.order.name())
+                    .show_ui(ui, |ui| {
+                        for o in SmartOrder::ALL {
+                            ui.selectable_value(&mut sp.order, o, o.name());
+                        }
+                    });
+                ui.checkbox(&mut sp.descending, "Descending");
+            });
+            ui.horizontal(|ui| {
+                let mut limited = sp.limit.is_some();
+                ui.checkbox(&mut limited, "Limit to");
+                let mut limit = sp.limit.unwrap_or(25);
+                ui.add_enabled(limited, egui::DragValue::new(&mut limit).clamp_range(1..=10000));
+                ui.label("tracks");
+                sp.limit = limited.then_some(limit);
+            });
+
+            if let Some(e) = &state.error {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    state.close();
+                }
+            });
+        });
+
+    if save {
+        save_smart_playlist(app_state);
+    }
+}
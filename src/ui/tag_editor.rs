@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use crate::{
+    playback::sync_upcoming,
+    state::state::AppState,
+    utils::{get_album_cover, read_tags},
+    NOTIFICATION_TIMEOUT_S,
+};
+use eframe::egui::{self, load::Bytes, ImageSource};
+
+pub fn open_tag_editor(app_state: &mut AppState, track_id: u32) {
+    let p = match app_state.trackdb.get_track(track_id) {
+        Some(t) => t.get_file_path(),
+        None => return,
+    };
+    match read_tags(&p) {
+        Ok(tags) => app_state.tag_editor.open(track_id, tags, get_album_cover(&p)),
+        Err(e) => app_state
+            .notification
+            .set_message(format!("Could not read tags : {}", e), Some(NOTIFICATION_TIMEOUT_S)),
+    }
+}
+
+// writes the dialog's tags into the file and db, then refreshes everything showing the track
+fn save_tags(app_state: &mut AppState, ctx: &egui::Context) {
+    let track_id = app_state.tag_editor.track_id.unwrap();
+    let res = app_state
+        .tag_editor
+        .to_tag_edit()
+        .and_then(|tags| {
+            app_state
+                .trackdb
+                .edit_track_tags(track_id, &tags)
+                .map_err(|e| format!("{:#}", e))
+        });
+    if let Err(e) = res {
+        app_state.tag_editor.error = Some(e);
+        return;
+    }
+    app_state.tag_editor.close();
+
+    let t_info = app_state.trackdb.get_track(track_id).unwrap();
+    if app_state.get_curr_displayed_tracklist().contains(&track_id) {
+        app_state.tracklist_state.update_item(t_info);
+    } else {
+        app_state.tracklist_state.remove_with_id(track_id);
+    }
+    if app_state.trackqueue.get_curr_track() == Some(track_id) {
+        app_state.curr_trackinfo = app_state.get_curr_track_info();
+        let cover = get_album_cover(&app_state.get_curr_track_path().unwrap());
+        app_state.curr_albumcover = Some(Arc::from(cover));
+    }
+    // images are cached by uri, which stays the same when a cover changes
+    ctx.forget_all_images();
+    // the file may have moved along with its tags
+    sync_upcoming(app_state, true);
+    app_state
+        .notification
+        .set_message("Saved tags".to_string(), Some(NOTIFICATION_TIMEOUT_S));
+}
+
+pub fn display_tag_editor(app_state: &mut AppState, ctx: &egui::Context) {
+    let track_id = match app_state.tag_editor.track_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let mut save = false;
+    egui::Window::new("Edit tags")
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let state = &mut app_state.tag_editor;
+            egui::Grid::new("tag_editor_fields")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    let fields = [
+                        ("Title", &mut state.title),
+                        ("Artists (separated by ;)", &mut state.artists),
+                        ("Album", &mut state.album),
+                        ("Album artist", &mut state.album_artist),
+                        ("Track number", &mut state.track_number),
+                        ("Year", &mut state.year),
+                        ("Genre", &mut state.genre),
+                    ];
+                    for (label, value) in fields {
+                        ui.label(label);
+                        ui.text_edit_singleline(value);
+                        ui.end_row();
+                    }
+
+                    ui.label("Cover");
+                    ui.vertical(|ui| {
+                        if let Some(cover) = &state.cover {
+                            ui.add(
+                                egui::Image::new(ImageSource::Bytes {
+                                    uri: format!("tag_editor_cover_{}.jpg", track_id).into(),
+                                    bytes: Bytes::Shared(Arc::clone(cover)),
+                                })
+                                .rounding(5.0)
+                                .max_size(egui::Vec2 { x: 120.0, y: 120.0 }),
+                            );
+                        }
+                        ui.add_enabled_ui(!state.remove_cover, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Replace with image file: ");
+                                ui.text_edit_singleline(&mut state.new_cover_path);
+                            });
+                        });
+                        if state.cover.is_some() {
+                            ui.checkbox(&mut state.remove_cover, "Remove cover");
+                        }
+                    });
+                    ui.end_row();
+                });
+
+            if let Some(e) = &state.error {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    state.close();
+                }
+            });
+        });
+
+    if save {
+        save_tags(app_state, ctx);
+    }
+}
@@ -1,9 +1,12 @@
 use std::sync::Arc;
 
+use crate::config::TrackColumn;
+use crate::state::filter_state::F1State;
 use crate::track_queue::TrackType;
+use crate::ui::tag_editor::open_tag_editor;
 use crate::ui::toggle_button::toggle;
 use crate::TRACKLIST_ROW_HEIGHT;
-use crate::{mpv::play_track, state::state::AppState};
+use crate::{playback::play_track, state::state::AppState};
 use eframe::egui::{self, Ui};
 use egui_extras::{Column, TableBuilder};
 
@@ -20,15 +23,24 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
     }
 
     let available_height = ui.available_height();
+    let extra_columns = app_state.config.tracklist_columns.clone();
+    // entries a synced playlist's remote has dropped get marked
+    let stale = app_state.tracklist_state.stale.clone();
+    // the name column gives up the room the optional columns take
+    let name_width = (600.0 - 100.0 * extra_columns.len() as f32).max(250.0);
     // TODO: Maybe dont hardcode size values in table (use percentage of screen size)
     let mut table = TableBuilder::new(ui)
         .striped(true)
         .resizable(false)
         .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
         .column(Column::auto().at_least(20.0))
-        .column(Column::remainder().at_least(600.0).at_most(600.0).clip(true))
-        .column(Column::remainder().at_least(200.0).at_most(200.0).resizable(false).clip(true))
+        .column(Column::remainder().at_least(name_width).at_most(name_width).clip(true))
         .column(Column::remainder().at_least(200.0).at_most(200.0).resizable(false).clip(true))
+        .column(Column::remainder().at_least(200.0).at_most(200.0).resizable(false).clip(true));
+    for _ in extra_columns.iter() {
+        table = table.column(Column::remainder().at_least(90.0).at_most(90.0).clip(true));
+    }
+    table = table
         .column(Column::remainder())
         .min_scrolled_height(0.0)
         .max_scroll_height(available_height);
@@ -37,7 +49,7 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
     table
         .header(20.0, |mut header| {
             header.col(|ui| {
-                ui.strong("");
+                draw_columns_menu(app_state, ui);
             });
             header.col(|ui| {
                 ui.strong("Name");
@@ -48,6 +60,11 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
             header.col(|ui| {
                 ui.strong("Album");
             });
+            for c in extra_columns.iter() {
+                header.col(|ui| {
+                    ui.strong(c.title());
+                });
+            }
             header.col(|ui| {
                 ui.strong("Duration");
             });
@@ -83,7 +100,13 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
                     }
 
                     row.col(|ui| {
-                        ui.add(egui::Label::new(&curr_row.name).selectable(false));
+                        if stale.contains(&curr_row.id) {
+                            let label = egui::Label::new(format!("⚠ {}", curr_row.name));
+                            ui.add(label.selectable(false))
+                                .on_hover_text("No longer in the remote playlist");
+                        } else {
+                            ui.add(egui::Label::new(&curr_row.name).selectable(false));
+                        }
                     });
                     row.col(|ui| {
                         ui.add(egui::Label::new(&curr_row.artist).selectable(false));
@@ -91,6 +114,11 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
                     row.col(|ui| {
                         ui.add(egui::Label::new(&curr_row.album).selectable(false));
                     });
+                    for c in extra_columns.iter() {
+                        row.col(|ui| {
+                            ui.add(egui::Label::new(curr_row.column_value(*c)).selectable(false));
+                        });
+                    }
                     row.col(|ui| {
                         ui.add(egui::Label::new(&curr_row.duration).selectable(false));
                     });
@@ -118,11 +146,40 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
                             ui.close_menu();
                         }
 
+                        ui.menu_button("Add to playlist", |ui| {
+                            let in_playlists = app_state.trackdb.get_track_playlists(curr_row.id);
+                            for p in app_state.trackdb.playlist_names() {
+                                if in_playlists.contains(&p) {
+                                    continue;
+                                }
+                                if ui.button(&p).clicked() {
+                                    app_state.trackdb.add_to_playlist(curr_row.id, &p);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+
+                        if ui.button("Edit tags").clicked() {
+                            ui.close_menu();
+                            open_tag_editor(app_state, curr_row.id);
+                        }
+
+                        // smart playlists only hold what their rules match
+                        let removable = app_state.f1_state == F1State::Playlists
+                            && !app_state.trackdb.is_smart_playlist(&app_state.f2_state);
+                        if removable && ui.button("Remove from playlist").clicked() {
+                            ui.close_menu();
+                            app_state
+                                .trackdb
+                                .remove_from_playlist(curr_row.id, &app_state.f2_state);
+                            app_state.tracklist_state.remove_with_id(curr_row.id);
+                        }
+
                         if let Some(tid) = app_state.trackqueue.get_curr_track() {
                             if curr_row.id != tid {
-                                if ui.button("Delete track").clicked() {
+                                if ui.button("Delete from library").clicked() {
                                     ui.close_menu();
-                                    app_state.trackdb.remove_track(curr_row.id, Some(true));
+                                    app_state.trackdb.remove_track(curr_row.id);
                                     app_state.tracklist_state.remove_with_id(curr_row.id);
 
                                     app_state.trackqueue.reg_queue.retain(|x| *x != curr_row.id);
@@ -136,3 +193,21 @@ fn table_ui(app_state: &mut AppState, ui: &mut egui::Ui) {
             }
         });
 }
+
+// picks which optional columns the tracklist shows, saved in the config. Columns turned on go
+// after the ones already shown, so the config's order holds
+fn draw_columns_menu(app_state: &mut AppState, ui: &mut Ui) {
+    ui.menu_button("⚙", |ui| {
+        for c in TrackColumn::ALL {
+            let mut shown = app_state.config.tracklist_columns.contains(&c);
+            if ui.checkbox(&mut shown, c.title()).changed() {
+                let columns = &mut app_state.config.tracklist_columns;
+                columns.retain(|x| *x != c);
+                if shown {
+                    columns.push(c);
+                }
+                app_state.config.save();
+            }
+        }
+    });
+}
@@ -0,0 +1,69 @@
+use crate::playback::{next_track, restart_or_prev_track, toggle_pause, update_volume};
+use crate::state::state::AppState;
+use crate::track_queue::RepeatMode;
+use crate::TRANSPORT_BUTTON_SIZE;
+use eframe::egui::{self, Ui};
+
+pub fn repeat_mode_to_str(mode: RepeatMode) -> &'static str {
+    match mode {
+        RepeatMode::Off => "Repeat: Off",
+        RepeatMode::One => "Repeat: One",
+        RepeatMode::All => "Repeat: All",
+    }
+}
+
+fn transport_button(ui: &mut Ui, text: &str) -> egui::Response {
+    ui.add(egui::Button::new(egui::RichText::new(text).size(16.0)).min_size(egui::Vec2 {
+        x: TRANSPORT_BUTTON_SIZE,
+        y: TRANSPORT_BUTTON_SIZE,
+    }))
+}
+
+// prev/play/next buttons on the left, repeat mode and volume on the right
+pub fn draw_transport_panel(app_state: &mut AppState, ui: &mut Ui) {
+    ui.horizontal_centered(|ui| {
+        if transport_button(ui, "⏮").on_hover_text("Previous").clicked() {
+            restart_or_prev_track(app_state);
+        }
+
+        let play_pause = if app_state.player.paused() { "▶" } else { "⏸" };
+        if transport_button(ui, play_pause).on_hover_text("Play/Pause (Space)").clicked() {
+            toggle_pause(app_state);
+        }
+
+        if transport_button(ui, "⏭").on_hover_text("Next").clicked() {
+            next_track(app_state);
+        }
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            let prev_volume = app_state.volume;
+            ui.add(
+                egui::Slider::new(&mut app_state.volume, 0.0..=100.0)
+                    .show_value(false)
+                    .trailing_fill(true),
+            );
+            if app_state.volume != prev_volume {
+                app_state.muted = false;
+                update_volume(app_state);
+            }
+
+            let mute_icon = if app_state.muted || app_state.volume == 0.0 { "🔇" } else { "🔊" };
+            if ui.button(mute_icon).on_hover_text("Mute").clicked() {
+                app_state.muted = !app_state.muted;
+                update_volume(app_state);
+            }
+
+            ui.separator();
+
+            let mut repeat_mode = app_state.trackqueue.repeat_mode;
+            egui::ComboBox::from_id_source("repeat_mode")
+                .selected_text(repeat_mode_to_str(repeat_mode))
+                .show_ui(ui, |ui| {
+                    for mode in [RepeatMode::Off, RepeatMode::One, RepeatMode::All] {
+                        ui.selectable_value(&mut repeat_mode, mode, repeat_mode_to_str(mode));
+                    }
+                });
+            app_state.trackqueue.set_repeat_mode(repeat_mode);
+        });
+    });
+}
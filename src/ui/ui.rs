@@ -1,14 +1,29 @@
+use std::collections::HashSet;
 use std::process::exit;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::mpv::kill_track;
-use crate::spotdl::{download_track, search_tracks, SearchResult};
+use crate::config::DownloadFormat;
+use crate::playback::{seek_relative, toggle_pause};
+use crate::db::downloads::DownloadStatus;
+use crate::spotdl::{search as search_spotify, SearchKind, SearchResult};
+use crate::spotify_link::{artist_releases, link_tracks, releases_tracks, Release, SpotifyLink};
+use crate::state::link_import_state::LinkImport;
 use crate::state::filter_state::F1State;
+use crate::state::playlist_file_state::PlaylistFileAction;
+use crate::state::search_state::{ArtistView, SearchOutcome};
+use crate::state::session::save_session;
 use crate::state::state::{AppState, AppStateWrapper};
 use crate::ui::toggle_button::toggle;
 use crate::utils::duration_to_str;
-use crate::{NUM_SEARCH_RESULTS, UI_SLEEP_DURATION_MS};
+use crate::{
+    NOTIFICATION_TIMEOUT_S, SEEK_LONG_S, SEEK_SHORT_S, UI_SLEEP_DURATION_MS,
+};
+use anyhow::anyhow;
+use log::warn;
+use std::future::Future;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use url::Url;
 use eframe::egui::{
     self, Align, Color32, FontData, FontDefinitions, Layout, Ui, Vec2, ViewportInfo, Visuals, Window
 };
@@ -16,8 +31,16 @@ use egui_extras::{install_image_loaders, Column, TableBuilder};
 
 use super::{
     currtrack_panel::draw_currtrack_panel,
+    downloads_panel::display_downloads_window,
     filter_panel::{draw_f1_panel, draw_f2_panel},
+    import_popup::{check_import_progress, display_import_popup},
+    playlist_file_popup::display_playlist_file_popup,
+    playlist_sync::{apply_sync, check_sync_progress, sync_due_playlists},
+    queue_panel::draw_queue_panel,
+    smart_playlist_editor::display_smart_playlist_editor,
+    tag_editor::display_tag_editor,
     tracklist_panel::draw_tracklist,
+    transport_panel::draw_transport_panel,
     visualizer_panel::draw_visualizer,
 };
 
@@ -36,8 +59,29 @@ fn draw_top_panel(app_state: &mut AppState, ctx: &egui::Context) {
                     ui.separator();
                     ui.label("Shuffle");
                     ui.add(toggle(&mut app_state.shuffle));
+                    ui.separator();
+                    ui.label("Queue");
+                    ui.add(toggle(&mut app_state.show_queue));
+
+                    ui.separator();
+                    let downloads = match app_state.downloads.n_active() {
+                        0 => " Downloads ".to_string(),
+                        n => format!(" Downloads ({}) ", n),
+                    };
+                    if ui.button(downloads).clicked() {
+                        app_state.show_downloads = !app_state.show_downloads;
+                    }
+                    if ui.button(" Import Folder ").clicked() {
+                        app_state.import_state.show_popup = true;
+                    }
+                    if ui.button(" Import Playlist ").clicked() {
+                        app_state.playlist_file.open(PlaylistFileAction::Import);
+                    }
 
-                    if let F1State::Playlists = app_state.f1_state {
+                    // downloads go into the selected playlist, which can't be a smart one
+                    let addable = app_state.f1_state == F1State::Playlists
+                        && !app_state.trackdb.is_smart_playlist(&app_state.f2_state);
+                    if addable {
                         ui.separator();
                         if ui.button(" Add Track ").clicked() {
                             app_state.search_results = Some(Vec::new());
@@ -62,6 +106,16 @@ fn draw_bottom_panel(app_state: &AppState, ctx: &egui::Context) {
         });
 }
 
+// transport panel contains playback controls, sits right above the notification bar
+fn draw_transport_bar(app_state: &mut AppState, ctx: &egui::Context) {
+    egui::TopBottomPanel::bottom("transport_panel")
+        .resizable(false)
+        .min_height(40.0)
+        .show(ctx, |ui| {
+            draw_transport_panel(app_state, ui);
+        });
+}
+
 fn draw_left_panel(app_state: &mut AppState, ctx: &egui::Context) {
     egui::SidePanel::left("left_panel")
         .resizable(false)
@@ -74,6 +128,19 @@ fn draw_left_panel(app_state: &mut AppState, ctx: &egui::Context) {
         });
 }
 
+fn draw_right_panel(app_state: &mut AppState, ctx: &egui::Context) {
+    if !app_state.show_queue {
+        return;
+    }
+    egui::SidePanel::right("queue_panel")
+        .resizable(false)
+        .min_width(300.0)
+        .max_width(300.0)
+        .show(ctx, |ui| {
+            draw_queue_panel(app_state, ui);
+        });
+}
+
 fn draw_main_panel(app_state: &mut AppState, ctx: &egui::Context) {
     egui::CentralPanel::default().show(ctx, |ui| {
         // draw_visualizer(app_state, ui);
@@ -92,41 +159,495 @@ fn display_search_results_popup(app_state: &mut AppState, ctx: &egui::Context) {
         .min_height(200.0)
         .show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.text_edit_singleline(&mut app_state.new_track_search_term);
-                if ui.button("Search").clicked() {
-                    let results = search_tracks(
-                        app_state.new_track_search_term.clone(),
-                        NUM_SEARCH_RESULTS,
-                        &mut app_state.spt_creds,
-                    );
-                    app_state.search_results = Some(results);
+                let kind = &mut app_state.search.kind;
+                egui::ComboBox::from_id_source("search_kind")
+                    .selected_text(kind.as_str())
+                    .show_ui(ui, |ui| {
+                        for k in SearchKind::ALL {
+                            ui.selectable_value(kind, k, k.as_str());
+                        }
+                    });
+                let response = ui.text_edit_singleline(&mut app_state.new_track_search_term);
+                let entered =
+                    response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Search").clicked() || entered {
+                    search(app_state);
+                }
+                let loading = app_state.search.job.is_some();
+                if app_state.search.has_more
+                    && app_state.link_import.is_none()
+                    && app_state.search.artist.is_none()
+                    && !loading
+                    && ui.button("Load more").clicked()
+                {
+                    load_more_results(app_state);
+                }
+                if loading {
+                    ui.spinner();
                 }
 
                 if ui.button("Download").clicked() {
-                    app_state.search_results = None;
-                    app_state.pending_download_childs.0 = app_state.f2_state.clone();
-                    for v in app_state.selected_result_urls.values() {
-                        let child = download_track(v);
-                        app_state.pending_download_childs.1.push(child);
-                    }
-                    app_state.selected_result_urls.clear();
-                    app_state.notification.set_message(
-                        format!(
-                            "Downloading tracks, {} remaining...",
-                            app_state.pending_download_childs.1.len()
-                        ),
-                        None,
-                    );
+                    let results = std::mem::take(&mut app_state.selected_results).into_values();
+                    let playlist = app_state.f2_state.clone();
+                    close_search(app_state);
+                    download_results(app_state, results.collect(), playlist);
                 }
                 if ui.button("Close").clicked() {
-                    app_state.search_results = None;
+                    close_search(app_state);
+                }
+
+                let mut format = app_state.config.download_format;
+                egui::ComboBox::from_id_source("download_format")
+                    .selected_text(format.as_str())
+                    .show_ui(ui, |ui| {
+                        for f in DownloadFormat::ALL {
+                            ui.selectable_value(&mut format, f, f.as_str());
+                        }
+                    });
+                if format != app_state.config.download_format {
+                    app_state.config.download_format = format;
+                    app_state.config.save();
                 }
             });
 
+            if app_state.link_import.is_some() {
+                // back to the artist, or the albums, artists or playlists it was opened from
+                let opened_from_results = app_state.search.artist.is_some()
+                    || !app_state.search.collections.is_empty();
+                if opened_from_results && ui.button("⬅ Back").clicked() {
+                    app_state.link_import = None;
+                    app_state.search_results = Some(Vec::new());
+                    app_state.selected_results.clear();
+                    return;
+                }
+                draw_link_import_bar(ui, app_state);
+            } else if app_state.search.artist.is_some() {
+                draw_artist_view(ui, app_state);
+                return;
+            } else if !app_state.search.collections.is_empty() {
+                draw_collection_table(ui, app_state);
+                return;
+            }
             draw_search_result_table(ui, app_state);
         });
 }
 
+fn close_search(app_state: &mut AppState) {
+    app_state.search_results = None;
+    app_state.link_import = None;
+    app_state.search.artist = None;
+    // whatever it was still waiting on is dropped
+    app_state.search.job = None;
+}
+
+// runs the job on the tokio runtime, check_search_progress picks up what it comes back with
+fn start_search_job<F>(app_state: &mut AppState, job: F)
+where
+    F: Future<Output = anyhow::Result<SearchOutcome>> + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let ctx = app_state.ctx.clone();
+    tokio::spawn(async move {
+        let _ = tx.send(job.await);
+        if let Some(ctx) = ctx {
+            ctx.request_repaint();
+        }
+    });
+    app_state.search.job = Some(rx);
+}
+
+fn start_search(app_state: &mut AppState, query: String, kind: SearchKind, offset: u32) {
+    let spotify = app_state.spt_creds.clone();
+    start_search_job(app_state, async move {
+        let page = search_spotify(&spotify, &query, kind, offset).await?;
+        Ok(SearchOutcome::Page { kind, offset, page })
+    });
+}
+
+// artists open into their albums and singles, the rest into their tracks
+fn start_link_fetch(app_state: &mut AppState, link: SpotifyLink) {
+    let spotify = app_state.spt_creds.clone();
+    if let SpotifyLink::Artist(id) = link {
+        start_search_job(app_state, async move {
+            let releases = artist_releases(&spotify, &id).await?;
+            Ok(SearchOutcome::Artist { releases })
+        });
+        return;
+    }
+    start_search_job(app_state, async move {
+        let tracks = link_tracks(&link, &spotify).await?;
+        Ok(SearchOutcome::Link {
+            url: link.url(),
+            tracks,
+        })
+    });
+}
+
+// Searches for the term, or lists everything if it's a link to a playlist, album or artist.
+// Other links, like youtube or soundcloud ones, are left for the downloaders
+fn search(app_state: &mut AppState) {
+    app_state.selected_results.clear();
+    let term = app_state.new_track_search_term.trim().to_string();
+    if term.is_empty() {
+        return;
+    }
+    let is_link = Url::parse(&term).is_ok_and(|u| u.scheme().starts_with("http"));
+    match SpotifyLink::parse(&term) {
+        Some(link) => start_link_fetch(app_state, link),
+        None if is_link => {
+            app_state.search_results = Some(vec![SearchResult::from_url(&term)]);
+            app_state.search.collections.clear();
+            app_state.search.has_more = false;
+            app_state.search.artist = None;
+            app_state.link_import = None;
+        }
+        None => {
+            app_state.search.query = term.clone();
+            start_search(app_state, term, app_state.search.kind, 0);
+        }
+    }
+}
+
+fn load_more_results(app_state: &mut AppState) {
+    let search = &app_state.search;
+    let offset = match search.results_kind {
+        SearchKind::Tracks => app_state.search_results.as_ref().map_or(0, |r| r.len()),
+        _ => search.collections.len(),
+    };
+    start_search(
+        app_state,
+        search.query.clone(),
+        search.results_kind,
+        offset as u32,
+    );
+}
+
+// picks up what a search job came back with, unless the popup was closed meanwhile
+pub fn check_search_progress(app_state: &mut AppState) {
+    let res = match app_state.search.job.as_mut().map(|rx| rx.try_recv()) {
+        Some(Ok(res)) => res,
+        Some(Err(TryRecvError::Closed)) => Err(anyhow!("the search was interrupted")),
+        Some(Err(TryRecvError::Empty)) | None => return,
+    };
+    app_state.search.job = None;
+    if app_state.search_results.is_none() {
+        return;
+    }
+
+    match res {
+        Ok(SearchOutcome::Page { kind, offset, page }) => {
+            let search = &mut app_state.search;
+            search.results_kind = kind;
+            search.has_more = page.has_more;
+            if offset == 0 {
+                app_state.selected_results.clear();
+                app_state.search_results = Some(page.tracks);
+                search.collections = page.collections;
+            } else {
+                app_state.search_results.as_mut().unwrap().extend(page.tracks);
+                search.collections.extend(page.collections);
+            }
+            search.artist = None;
+            app_state.link_import = None;
+        }
+        Ok(SearchOutcome::Link { url, tracks }) => {
+            let n_new = tracks
+                .tracks
+                .iter()
+                .filter(|r| {
+                    app_state
+                        .trackdb
+                        .find_track(Some(&r.get_source_id()), r.isrc.as_deref())
+                        .is_none()
+                })
+                .count();
+            app_state.selected_results.clear();
+            app_state.search_results = Some(tracks.tracks);
+            app_state.link_import = Some(LinkImport {
+                playlist: tracks.name.clone(),
+                name: tracks.name,
+                n_new,
+                url,
+            });
+        }
+        Ok(SearchOutcome::Artist { releases }) => {
+            app_state.selected_results.clear();
+            app_state.search_results = Some(Vec::new());
+            app_state.link_import = None;
+            app_state.search.artist = Some(ArtistView {
+                playlist: releases.name.clone(),
+                name: releases.name,
+                releases: releases.releases,
+                selected: HashSet::new(),
+            });
+        }
+        Ok(SearchOutcome::Releases { playlist, tracks }) => {
+            close_search(app_state);
+            app_state.trackdb.create_playlist(playlist.clone());
+            download_results(app_state, tracks, playlist);
+        }
+        Err(e) => {
+            warn!("Search failed : {:#}", e);
+            app_state.notification.set_message(
+                format!("Search failed : {}", e),
+                Some(NOTIFICATION_TIMEOUT_S),
+            );
+        }
+    }
+}
+
+// the albums, artists or playlists found, opening one lists its tracks
+fn draw_collection_table(ui: &mut Ui, app_state: &mut AppState) {
+    let mut opened = None;
+    let available_height = ui.available_height();
+    TableBuilder::new(ui)
+        .striped(true)
+        .resizable(false)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+        .column(Column::exact(400.0).clip(true))
+        .column(Column::exact(500.0).clip(true))
+        .column(Column::remainder())
+        .min_scrolled_height(0.0)
+        .max_scroll_height(available_height)
+        .header(20.0, |mut header| {
+            header.col(|ui| {
+                ui.strong("Name");
+            });
+            header.col(|ui| {
+                ui.strong("");
+            });
+            header.col(|ui| {
+                ui.strong("");
+            });
+        })
+        .body(|body| {
+            let collections = &app_state.search.collections;
+            body.rows(30.0, collections.len(), |mut row| {
+                let c = &collections[row.index()];
+                row.col(|ui| {
+                    ui.label(&c.name);
+                });
+                row.col(|ui| {
+                    ui.label(&c.detail);
+                });
+                row.col(|ui| {
+                    if ui.button("Open").clicked() {
+                        opened = Some(c.link.clone());
+                    }
+                });
+            });
+        });
+
+    if let Some(link) = opened {
+        start_link_fetch(app_state, link);
+    }
+}
+
+// An artist's albums and singles. Opening one lists its tracks, and the tracks of the selected
+// ones, or of all of them, can be added to a playlist at once
+fn draw_artist_view(ui: &mut Ui, app_state: &mut AppState) {
+    let playlists = app_state.trackdb.playlist_names();
+    let loading = app_state.search.job.is_some();
+    let mut back = false;
+    let mut add_all = false;
+    let mut opened = None;
+
+    let artist = app_state.search.artist.as_mut().unwrap();
+    ui.horizontal(|ui| {
+        if !app_state.search.collections.is_empty() && ui.button("⬅ Back").clicked() {
+            back = true;
+        }
+        let picked = match artist.selected.len() {
+            0 => format!("all {}", artist.releases.len()),
+            n => format!("{} of {}", n, artist.releases.len()),
+        };
+        ui.label(format!(
+            "{} : {} releases. Into playlist: ",
+            artist.name, picked
+        ));
+        ui.text_edit_singleline(&mut artist.playlist);
+        egui::ComboBox::from_id_source("artist_playlist")
+            .selected_text("Existing")
+            .show_ui(ui, |ui| {
+                for p in playlists.iter() {
+                    ui.selectable_value(&mut artist.playlist, p.clone(), p);
+                }
+            });
+        if ui
+            .add_enabled(!loading, egui::Button::new("Download missing"))
+            .clicked()
+        {
+            add_all = true;
+        }
+    });
+
+    let available_height = ui.available_height();
+    TableBuilder::new(ui)
+        .striped(true)
+        .resizable(false)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+        .column(Column::exact(30.0))
+        .column(Column::exact(500.0).clip(true))
+        .column(Column::exact(100.0))
+        .column(Column::exact(60.0))
+        .column(Column::remainder())
+        .min_scrolled_height(0.0)
+        .max_scroll_height(available_height)
+        .header(20.0, |mut header| {
+            for title in ["", "Name", "Type", "Year", ""] {
+                header.col(|ui| {
+                    ui.strong(title);
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(30.0, artist.releases.len(), |mut row| {
+                let i = row.index();
+                let r = &artist.releases[i];
+                row.col(|ui| {
+                    let mut selected = artist.selected.contains(&i);
+                    if ui.checkbox(&mut selected, "").changed() {
+                        match selected {
+                            true => artist.selected.insert(i),
+                            false => artist.selected.remove(&i),
+                        };
+                    }
+                });
+                row.col(|ui| {
+                    ui.label(&r.name);
+                });
+                row.col(|ui| {
+                    ui.label(&r.album_type);
+                });
+                row.col(|ui| {
+                    ui.label(r.release_date.get(..4).unwrap_or_default());
+                });
+                row.col(|ui| {
+                    if ui.button("Open").clicked() {
+                        opened = Some(r.link());
+                    }
+                });
+            });
+        });
+
+    if back {
+        app_state.search.artist = None;
+    } else if let Some(link) = opened {
+        start_link_fetch(app_state, link);
+    } else if add_all {
+        let playlist = artist.playlist.trim().to_string();
+        if playlist.is_empty() || app_state.trackdb.is_smart_playlist(&playlist) {
+            app_state.notification.set_message(
+                "Pick a playlist that isn't a smart playlist".to_string(),
+                Some(NOTIFICATION_TIMEOUT_S),
+            );
+            return;
+        }
+        let releases = artist
+            .releases
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| artist.selected.is_empty() || artist.selected.contains(i))
+            .map(|(_, r)| r.clone())
+            .collect::<Vec<Release>>();
+        let spotify = app_state.spt_creds.clone();
+        start_search_job(app_state, async move {
+            let tracks = releases_tracks(&spotify, &releases).await?;
+            Ok(SearchOutcome::Releases { playlist, tracks })
+        });
+    }
+}
+
+// Adds results to playlist, downloading the ones that aren't in the library yet
+pub fn download_results(app_state: &mut AppState, results: Vec<SearchResult>, playlist: String) {
+    let mut n_linked = 0;
+    for r in results {
+        // tracks already in the library are only added to the playlist
+        let existing = app_state
+            .trackdb
+            .find_track(Some(&r.get_source_id()), r.isrc.as_deref());
+        if let Some(tid) = existing {
+            app_state.trackdb.add_to_playlist(tid, &playlist);
+            n_linked += 1;
+            continue;
+        }
+
+        app_state.downloads.enqueue(&mut app_state.trackdb, r, &playlist);
+    }
+
+    if app_state.downloads.n_active() == 0 {
+        app_state.notification.set_message(
+            format!("Added {} tracks from the library", n_linked),
+            Some(NOTIFICATION_TIMEOUT_S),
+        );
+        sync_tracklist_with_db(app_state);
+    } else {
+        app_state.notification.set_message(
+            format!(
+                "Downloading tracks, {} remaining...",
+                app_state.downloads.n_active()
+            ),
+            None,
+        );
+    }
+}
+
+// picks the playlist everything from a link goes into, and adds it all there
+fn draw_link_import_bar(ui: &mut Ui, app_state: &mut AppState) {
+    let results = app_state.search_results.clone().unwrap_or_default();
+    let playlists = app_state.trackdb.playlist_names();
+
+    let mut add_all = false;
+    ui.horizontal(|ui| {
+        let link_import = app_state.link_import.as_mut().unwrap();
+        ui.label(format!(
+            "{} : {} tracks, {} to download. Into playlist: ",
+            link_import.name,
+            results.len(),
+            link_import.n_new
+        ));
+        ui.text_edit_singleline(&mut link_import.playlist);
+        egui::ComboBox::from_id_source("link_import_playlist")
+            .selected_text("Existing")
+            .show_ui(ui, |ui| {
+                for p in playlists.iter() {
+                    ui.selectable_value(&mut link_import.playlist, p.clone(), p);
+                }
+            });
+        let label = match playlists.contains(&link_import.playlist) {
+            true => "Add all",
+            false => "Create and add all",
+        };
+        if ui.button(label).clicked() {
+            add_all = true;
+        }
+    });
+
+    if add_all {
+        let link_import = app_state.link_import.as_ref().unwrap();
+        let playlist = link_import.playlist.trim().to_string();
+        let url = link_import.url.clone();
+        if playlist.is_empty() || app_state.trackdb.is_smart_playlist(&playlist) {
+            app_state.notification.set_message(
+                "Pick a playlist that isn't a smart playlist".to_string(),
+                Some(NOTIFICATION_TIMEOUT_S),
+            );
+            return;
+        }
+        app_state.search_results = None;
+        app_state.link_import = None;
+        if playlists.contains(&playlist) {
+            download_results(app_state, results, playlist);
+            return;
+        }
+
+        // playlists made from a link can be synced with it later
+        app_state.trackdb.create_playlist(playlist.clone());
+        app_state.trackdb.set_playlist_source(&playlist, Some(&url));
+        apply_sync(app_state, &playlist, results);
+    }
+}
+
 fn draw_search_result_table(ui: &mut Ui, app_state: &mut AppState) {
     if app_state.search_results.is_none() {
         return;
@@ -188,105 +709,141 @@ fn draw_search_result_table(ui: &mut Ui, app_state: &mut AppState) {
         .body(|mut body| {
             // TODO: Put this in a constant?
             let row_height = 30.0;
-            for row_index in 0..(result_vec.len()) {
-                let curr_row = result_vec.get(row_index);
-
-                if let None = curr_row {
-                    continue;
+            // only the rows in view are drawn, links can list thousands of tracks
+            body.rows(row_height, result_vec.len(), |mut row| {
+                let row_index = row.index();
+                let curr_row = &result_vec[row_index];
+                let in_library = app_state
+                    .trackdb
+                    .find_track(Some(&curr_row.get_source_id()), curr_row.isrc.as_deref())
+                    .is_some();
+
+                if app_state.selected_results.contains_key(&row_index) {
+                    row.set_selected(true);
                 }
-                let curr_row = curr_row.unwrap();
 
-                body.row(row_height, |mut row| {
-                    if app_state.selected_result_urls.contains_key(&row_index) {
-                        row.set_selected(true);
-                    }
-
-                    row.col(|ui| {
+                row.col(|ui| {
+                    if in_library {
+                        ui.label("✔").on_hover_text("Already in the library");
+                    } else {
                         ui.label((row_index + 1).to_string());
-                    });
-                    row.col(|ui| {
-                        ui.label(curr_row.name.clone());
-                    });
-                    row.col(|ui| {
-                        ui.label(curr_row.artists.join(", "));
-                    });
-                    row.col(|ui| {
-                        ui.label(&curr_row.album);
-                    });
-                    row.col(|ui| {
-                        ui.label(duration_to_str(curr_row.duration as u32));
-                    });
-
-                    let response = row.response();
-                    if response.clicked() {
-                        if app_state.selected_result_urls.contains_key(&row_index) {
-                            app_state.selected_result_urls.remove_entry(&row_index);
-                        } else {
-                            app_state
-                                .selected_result_urls
-                                .insert(row_index, curr_row.get_url());
-                        }
                     }
                 });
-            }
+                row.col(|ui| {
+                    ui.label(curr_row.name.clone());
+                });
+                row.col(|ui| {
+                    ui.label(curr_row.artists.join(", "));
+                });
+                row.col(|ui| {
+                    ui.label(&curr_row.album);
+                });
+                row.col(|ui| {
+                    ui.label(duration_to_str(curr_row.duration as u32));
+                });
+
+                let response = row.response();
+                if response.clicked() {
+                    if app_state.selected_results.contains_key(&row_index) {
+                        app_state.selected_results.remove_entry(&row_index);
+                    } else {
+                        app_state
+                            .selected_results
+                            .insert(row_index, curr_row.clone());
+                    }
+                }
+            });
         });
 }
 
-pub fn check_download_progress(app_state: &mut AppState) {
-    let mut remove_idxs = Vec::new();
-    for (i, c) in app_state.pending_download_childs.1.iter_mut().enumerate() {
-        if c.try_wait().unwrap().is_some() {
-            remove_idxs.push(i);
+// adds tracks of the displayed tracklist that are missing from the table, ie: new downloads
+pub fn sync_tracklist_with_db(app_state: &mut AppState) {
+    let ids = app_state.get_curr_displayed_tracklist();
+
+    let present_ids = app_state
+        .tracklist_state
+        .items
+        .iter()
+        .map(|x| x.id)
+        .collect::<Vec<u32>>();
+    if ids.len() != present_ids.len() {
+        for id in ids.iter() {
+            if !present_ids.contains(id) {
+                let tinfo = app_state.trackdb.get_track(*id).unwrap();
+                app_state.tracklist_state.add_item(tinfo);
+            }
         }
     }
+}
+
+// runs the download jobs, and puts the finished tracks in place
+pub fn check_download_progress(app_state: &mut AppState) {
+    let finished = app_state
+        .downloads
+        .update(&mut app_state.trackdb, &app_state.config);
+    if finished.is_empty() {
+        return;
+    }
 
-    for idx in remove_idxs.into_iter().rev() {
-        app_state.pending_download_childs.1.remove(idx);
+    for job in finished.iter().filter(|j| j.status == DownloadStatus::Done) {
+        // synced playlists keep the remote's order
+        app_state.trackdb.apply_remote_order(&job.playlist);
     }
 
-    if app_state.pending_download_childs.1.len() > 0 {
+    let n_active = app_state.downloads.n_active();
+    if n_active > 0 {
         app_state.notification.set_message(
-            format!(
-                "Downloading tracks, {} remaining...",
-                app_state.pending_download_childs.1.len()
-            ),
+            format!("Downloading tracks, {} remaining...", n_active),
             None,
         );
+        sync_tracklist_with_db(app_state);
     } else {
-        if app_state.pending_download_childs.0.len() > 0 {
-            app_state
-                .notification
-                .set_message("All tracks downloaded".to_string(), Some(3));
-            app_state
-                .trackdb
-                .add_all_tracks(Some(app_state.pending_download_childs.0.clone()));
-            app_state.pending_download_childs = (String::new(), Vec::new());
+        let message = match app_state.downloads.n_failed() {
+            0 => "All tracks downloaded".to_string(),
+            n => format!("Downloads finished, {} failed", n),
+        };
+        app_state
+            .notification
+            .set_message(message, Some(NOTIFICATION_TIMEOUT_S));
+        // the order of synced playlists may have changed along with the tracks
+        app_state.prev_state.f2_state.1 = String::new();
+    }
 
-            let ids = app_state.get_curr_displayed_tracklist();
+    app_state.ctx.clone().unwrap().request_repaint();
+}
 
-            let present_ids = app_state
-                .tracklist_state
-                .items
-                .iter()
-                .map(|x| x.id)
-                .collect::<Vec<u32>>();
-            if ids.len() != present_ids.len() {
-                for id in ids.iter() {
-                    if !present_ids.contains(id) {
-                        let tinfo = app_state.trackdb.trackmap.get(id).unwrap();
-                        app_state.tracklist_state.add_item(
-                            id.clone(),
-                            tinfo.name.clone(),
-                            tinfo.artists.clone().unwrap_or(Vec::new()),
-                            tinfo.album.clone().unwrap_or(String::new()),
-                            tinfo.duration,
-                        );
-                    }
-                }
-            }
+// global keybinds, ignored while a text field has focus
+fn handle_shortcuts(app_state: &mut AppState, ctx: &egui::Context) {
+    if ctx.wants_keyboard_input() {
+        return;
+    }
 
-            app_state.ctx.clone().unwrap().request_repaint();
-        }
+    let (left, right, shift, space, s, q) = ctx.input(|i| {
+        (
+            i.key_pressed(egui::Key::ArrowLeft),
+            i.key_pressed(egui::Key::ArrowRight),
+            i.modifiers.shift,
+            i.key_pressed(egui::Key::Space),
+            i.key_pressed(egui::Key::S),
+            i.key_pressed(egui::Key::Q),
+        )
+    });
+    if q {
+        app_state.show_queue = !app_state.show_queue;
+    }
+    if space {
+        toggle_pause(app_state);
+    }
+    if s {
+        app_state.shuffle = !app_state.shuffle;
+    }
+
+    let step = if shift { SEEK_LONG_S } else { SEEK_SHORT_S };
+    if left {
+        seek_relative(app_state, -step);
+    }
+    if right {
+        seek_relative(app_state, step);
     }
 }
 
@@ -294,6 +851,7 @@ pub fn setup_fn(app_state: &mut AppState, ctx: &egui::Context) {
     if let None = app_state.ctx {
         install_image_loaders(ctx);
         app_state.ctx = Some(ctx.clone());
+        sync_due_playlists(app_state);
     }
 }
 
@@ -322,23 +880,31 @@ impl eframe::App for AppStateWrapper {
         draw_top_panel(&mut app_state_g, ctx);
         draw_left_panel(&mut app_state_g, ctx);
         draw_bottom_panel(&mut app_state_g, ctx);
+        draw_transport_bar(&mut app_state_g, ctx);
+        draw_right_panel(&mut app_state_g, ctx);
         draw_main_panel(&mut app_state_g, ctx);
         display_search_results_popup(&mut app_state_g, ctx);
+        display_import_popup(&mut app_state_g, ctx);
+        display_tag_editor(&mut app_state_g, ctx);
+        display_smart_playlist_editor(&mut app_state_g, ctx);
+        display_playlist_file_popup(&mut app_state_g, ctx);
+        display_downloads_window(&mut app_state_g, ctx);
+        handle_shortcuts(&mut app_state_g, ctx);
 
         update_shuffle(&mut app_state_g);
         check_download_progress(&mut app_state_g);
+        check_import_progress(&mut app_state_g);
+        check_sync_progress(&mut app_state_g);
+        check_search_progress(&mut app_state_g);
         app_state_g.notification.update_message();
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
         let app_state_clone = Arc::clone(&self.app_state);
         let mut app_state_g = app_state_clone.lock().unwrap();
-        match &mut app_state_g.mpv_child {
-            Some(mpv_child) => {
-                mpv_child.kill().unwrap();
-            }
-            None => {}
-        };
+        save_session(&app_state_g);
+        app_state_g.downloads.shutdown();
+        app_state_g.player.shutdown();
         exit(0);
     }
 }
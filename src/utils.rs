@@ -1,18 +1,25 @@
 use std::{
+    borrow::Cow,
     fs::{create_dir_all, File},
     io::Write,
-    path::PathBuf,
-    process::{exit, Command, Stdio},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     str::FromStr,
+    sync::OnceLock,
 };
 
-use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::mp4::{Mp4Codec, Mp4File};
+use lofty::picture::{Picture, PictureType};
 use lofty::probe::Probe;
-use lofty::tag::Accessor;
+use lofty::tag::{Accessor, ItemKey, Tag, TagExt};
 
-use dirs::home_dir;
+use dirs::{config_dir, home_dir};
+use log::warn;
 
-use crate::{state::filter_state::F1State, KEY_INPUT_POLL_TIMEOUT_MS, MPV_LUASCRIPT_FILENAME, MPV_STATUS_IPC_FILENAME, MUSIC_DIR};
+use crate::downloader::DownloaderKind;
+use crate::{config::Config, state::filter_state::F1State, KEY_INPUT_POLL_TIMEOUT_MS, MPV_IPC_SOCKET_FILENAME, MUSIC_DIR};
 
 pub fn duration_to_str(duration: u32) -> String {
     let min = duration / 60;
@@ -26,13 +33,21 @@ pub fn f1_state_enum_to_str(f1_state: &F1State) -> String {
         F1State::Playlists => "Playlists".to_string(),
         F1State::Artists => "Artists".to_string(),
         F1State::Albums => "Albums".to_string(),
+        F1State::Genres => "Genres".to_string(),
+        F1State::Years => "Years".to_string(),
     }
 }
 
+// read from the config once, a different music dir would need another db anyway
 pub fn get_music_dir() -> PathBuf {
-    let mut d = home_dir().unwrap();
-    d.push(MUSIC_DIR);
-    d
+    static MUSIC_DIR_PATH: OnceLock<PathBuf> = OnceLock::new();
+    MUSIC_DIR_PATH
+        .get_or_init(|| match Config::load().music_dir {
+            Some(d) if d.starts_with("~") => home_dir().unwrap().join(d.strip_prefix("~").unwrap()),
+            Some(d) => d,
+            None => home_dir().unwrap().join(MUSIC_DIR),
+        })
+        .clone()
 }
 
 pub fn get_newtracks_dir() -> PathBuf {
@@ -41,68 +56,71 @@ pub fn get_newtracks_dir() -> PathBuf {
     mdir
 }
 
+// the bincode track db used before the sqlite one, only read to import it
 pub fn get_cache_file_path() -> PathBuf {
     let mut mdir = get_music_dir();
     mdir.push(".trackdb");
     mdir
 }
 
-pub fn get_ipc_path() -> PathBuf {
-    let mut p = get_music_dir();
-    p.push(MPV_STATUS_IPC_FILENAME);
+pub fn get_config_file_path() -> PathBuf {
+    let mut p = config_dir().unwrap();
+    p.push(env!("CARGO_PKG_NAME"));
+    p.push("config.yaml");
     p
 }
 
-pub fn get_luascript_path() -> PathBuf {
+pub fn get_db_file_path() -> PathBuf {
+    let mut mdir = get_music_dir();
+    mdir.push(".library.db");
+    mdir
+}
+
+pub fn get_session_file_path() -> PathBuf {
+    let mut mdir = get_music_dir();
+    mdir.push(".session");
+    mdir
+}
+
+// mpv's json ipc server listens on a unix socket, or a named pipe on windows
+#[cfg(unix)]
+pub fn get_ipc_path() -> PathBuf {
     let mut p = get_music_dir();
-    p.push(MPV_LUASCRIPT_FILENAME);
+    p.push(MPV_IPC_SOCKET_FILENAME);
     p
 }
 
+#[cfg(windows)]
+pub fn get_ipc_path() -> PathBuf {
+    PathBuf::from(format!(r"\\.\pipe\{}", MPV_IPC_SOCKET_FILENAME))
+}
+
 pub fn init_functions() {
     init_files();
-    check_spotdl_installed();
     setup_logger().unwrap();
+    check_spotdl_installed(&Config::load());
 }
 
 pub fn init_files() {
     // creates directory for music
-    let mut music_dir_path = get_music_dir();
+    let music_dir_path = get_music_dir();
     create_dir_all(&music_dir_path).unwrap();
-
-    // creates ipc file for communication with mpv
-    let mut ipc_path = get_ipc_path();
-    File::create(&ipc_path).unwrap();
-
-    // creates lua script for mpv
-    let mut luascript_path = get_luascript_path();
-    init_lua_file(&luascript_path, ipc_path.to_str().unwrap());
-}
-
-fn init_lua_file(fp: &PathBuf, ipc_path: &str) {
-    let mut file = File::create(fp).unwrap();
-    let mut file_contents = include_str!("status_update.lua").to_string();
-    file_contents = file_contents.replace("<script_path>", ipc_path);
-    file.write_all(file_contents.as_bytes()).unwrap();
 }
 
-pub fn parse_bool(s: &str) -> bool {
-    if s == "true" {
-        true
-    } else {
-        false
+// only warns, the other downloaders can do without spotdl
+pub fn check_spotdl_installed(config: &Config) {
+    if !config.downloaders.contains(&DownloaderKind::Spotdl) {
+        return;
     }
-}
-
-pub fn check_spotdl_installed() {
-    let out = String::from_utf8(Command::new("pip").arg("list").output().unwrap().stdout).unwrap();
-    for l in out.lines() {
-        if l.contains("spotdl") {
-            return;
-        }
+    let installed = Command::new("pip")
+        .arg("list")
+        .output()
+        .ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .is_some_and(|out| out.lines().any(|l| l.contains("spotdl")));
+    if !installed {
+        warn!("spotdl installation not found! If you are using conda/venv, ensure that you are in the correct environment!");
     }
-    eprintln!("spotdl installation not found! If you are using conda/venv, ensure that you are in the correct environment!");
-    exit(1);
 }
 
 pub fn get_progress_display_str(secs_played: f64, total_secs: f64) -> String {
@@ -112,12 +130,53 @@ pub fn get_progress_display_str(secs_played: f64, total_secs: f64) -> String {
     format!("{}:{:0>2}/{}:{:0>2}", m1, ss1, m2, ss2)
 }
 
-pub fn get_metadata(p: &PathBuf) -> Option<(String, Option<Vec<String>>, Option<String>, u32)> {
-    let tagged_file = Probe::open(p).unwrap().read().unwrap();
+// the tags get_metadata reads from a file, along with its duration and codec
+pub struct TrackMetadata {
+    pub title: String,
+    pub artists: Option<Vec<String>>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub duration: u32,
+    pub codec: String,
+}
+
+// the codec of the audio in p, only mp4 files need to be looked into further
+fn get_codec_name(p: &Path, file_type: FileType) -> String {
+    let name = match file_type {
+        FileType::Mpeg => "MP3",
+        FileType::Flac => "FLAC",
+        FileType::Opus => "Opus",
+        FileType::Vorbis => "Vorbis",
+        FileType::Wav | FileType::Aiff => "PCM",
+        FileType::Aac => "AAC",
+        FileType::Mp4 => {
+            let mp4 = File::open(p)
+                .ok()
+                .and_then(|mut f| Mp4File::read_from(&mut f, ParseOptions::new()).ok());
+            match mp4.as_ref().map(|m| m.properties().codec()) {
+                Some(Mp4Codec::AAC) => "AAC",
+                Some(Mp4Codec::ALAC) => "ALAC",
+                Some(Mp4Codec::MP3) => "MP3",
+                Some(Mp4Codec::FLAC) => "FLAC",
+                _ => "MP4",
+            }
+        }
+        other => return format!("{:?}", other),
+    };
+    name.to_string()
+}
+
+// returns None if the file can't be read as audio or has no title tag
+pub fn get_metadata(p: &PathBuf) -> Option<TrackMetadata> {
+    let tagged_file = Probe::open(p).ok()?.read().ok()?;
     let duration = tagged_file.properties().duration().as_secs() as u32;
 
-    if let Some(tag) = tagged_file.primary_tag() {
-        let title = tag.title().unwrap().as_ref().to_string();
+    if let Some(tag) = tagged_file.primary_tag().or(tagged_file.first_tag()) {
+        let title = tag.title()?.as_ref().to_string();
         let album = match tag.album() {
             Some(x) => Some(x.as_ref().to_string()),
             None => None,
@@ -132,7 +191,18 @@ pub fn get_metadata(p: &PathBuf) -> Option<(String, Option<Vec<String>>, Option<
             ),
             None => None,
         };
-        Some((title, artist, album, duration))
+        Some(TrackMetadata {
+            title,
+            artists: artist,
+            album,
+            album_artist: tag.get_string(&ItemKey::AlbumArtist).map(|x| x.to_string()),
+            track_number: tag.track(),
+            disc_number: tag.disk(),
+            year: tag.year(),
+            genre: tag.genre().map(|x| x.to_string()),
+            duration,
+            codec: get_codec_name(p, tagged_file.file_type()),
+        })
     } else {
         None
     }
@@ -145,39 +215,112 @@ pub fn wrap_string(s: &mut String, n: u32) {
     }
 }
 
-// TODO: Fix this function
-pub fn set_metadata(
-    p: &PathBuf,
-    title: String,
-    artists: Vec<String>,
-    album: String,
-) -> Option<(String, Option<Vec<String>>, Option<String>, u32)> {
-    let tagged_file = Probe::open(p).unwrap().read().unwrap();
-    let duration = tagged_file.properties().duration().as_secs() as u32;
+// The tags the tag editor can change. Empty strings and Nones are removed from the file when
+// written, except for cover where None keeps the current one and an empty Vec removes it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagEdit {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: String,
+    pub cover: Option<Vec<u8>>,
+}
 
-    if let Some(tag) = tagged_file.primary_tag() {
-        let title = tag.title().unwrap().as_ref().to_string();
-        let album = match tag.album() {
-            Some(x) => Some(x.as_ref().to_string()),
-            None => None,
-        };
-        let artist = match tag.artist() {
-            Some(x) => Some(vec![x.as_ref().to_string()]),
-            None => None,
-        };
-        Some((title, artist, album, duration))
-    } else {
-        None
+pub fn read_tags(p: &Path) -> anyhow::Result<TagEdit> {
+    let tagged_file = Probe::open(p)?.read()?;
+    let tag = match tagged_file.primary_tag().or(tagged_file.first_tag()) {
+        Some(t) => t,
+        None => return Ok(TagEdit::default()),
+    };
+
+    let text = |s: Option<Cow<str>>| s.map(|x| x.to_string()).unwrap_or_default();
+    Ok(TagEdit {
+        title: text(tag.title()),
+        artists: tag
+            .artist()
+            .map(|a| a.split('/').map(|x| x.to_string()).collect())
+            .unwrap_or_default(),
+        album: text(tag.album()),
+        album_artist: tag.get_string(&ItemKey::AlbumArtist).unwrap_or_default().to_string(),
+        track_number: tag.track(),
+        year: tag.year(),
+        genre: text(tag.genre()),
+        cover: None,
+    })
+}
+
+// writes tags into the file's primary tag, which is created if the file has none yet
+pub fn write_tags(p: &Path, tags: &TagEdit) -> anyhow::Result<()> {
+    let mut tagged_file = Probe::open(p)?.read()?;
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
     }
+    let tag = tagged_file.primary_tag_mut().unwrap();
+
+    tag.set_title(tags.title.clone());
+    match tags.artists.is_empty() {
+        true => tag.remove_artist(),
+        false => tag.set_artist(tags.artists.join("/")),
+    }
+    match tags.album.is_empty() {
+        true => tag.remove_album(),
+        false => tag.set_album(tags.album.clone()),
+    }
+    match tags.album_artist.is_empty() {
+        true => tag.remove_key(&ItemKey::AlbumArtist),
+        false => {
+            tag.insert_text(ItemKey::AlbumArtist, tags.album_artist.clone());
+        }
+    }
+    match tags.track_number {
+        Some(n) => tag.set_track(n),
+        None => tag.remove_track(),
+    }
+    match tags.year {
+        Some(y) => tag.set_year(y),
+        None => tag.remove_year(),
+    }
+    match tags.genre.is_empty() {
+        true => tag.remove_genre(),
+        false => tag.set_genre(tags.genre.clone()),
+    }
+    if let Some(cover) = &tags.cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        if !cover.is_empty() {
+            let mut pic = Picture::from_reader(&mut cover.as_slice())?;
+            pic.set_pic_type(PictureType::CoverFront);
+            tag.push_picture(pic);
+        }
+    }
+
+    tag.save_to_path(p, WriteOptions::default())?;
+    Ok(())
 }
 
+// the front cover embedded in any of the file's tags, or else the first picture found
 pub fn get_album_cover(p: &PathBuf) -> Vec<u8> {
-    let tagged_file = Probe::open(p).unwrap().read().unwrap();
+    let tagged_file = match Probe::open(p).and_then(|f| f.read()) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
 
-    if let Some(tag) = tagged_file.primary_tag() {
-        tag.pictures()[0].clone().into_data()
-    } else {
-        Vec::new()
+    let pictures = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|t| t.pictures())
+        .collect::<Vec<&Picture>>();
+    let cover = pictures
+        .iter()
+        .find(|pic| pic.pic_type() == PictureType::CoverFront)
+        .or(pictures.first());
+
+    match cover {
+        Some(pic) => pic.data().to_vec(),
+        None => Vec::new(),
     }
 }
 